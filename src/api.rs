@@ -0,0 +1,389 @@
+// An HTTP/JSON API so editors, browser extensions and dashboards can drive the timer without
+// writing to the `in` file. The daemon serves it on 127.0.0.1 when `[api]` is enabled in the
+// config. Every request but the one for the OpenAPI description needs the token in
+// `~/.productivity-timer/api-token` as a bearer token. Commands are handed to the daemon's loop,
+// which answers with what came of them, and `/events` streams state changes as they happen. The
+// description is in `api/openapi.yaml`.
+use crate::credentials::api_token;
+use crate::daemon::{ask, Command, Request as DaemonRequest};
+use crate::events;
+use crate::report::{totals, By};
+use chrono::NaiveDate;
+use oauth2::url::form_urlencoded;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread::spawn;
+use std::time::Duration;
+
+const OPENAPI: &str = include_str!("api/openapi.yaml");
+
+pub const READ_TIMEOUT: Duration = Duration::from_secs(5);
+// Subscribers that can't keep up are dropped rather than queued for
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+const EVENT_STREAM: &str = "text/event-stream";
+const ENDPOINTS: &[&str] = &[
+    "/status",
+    "/events",
+    "/toggle",
+    "/switch",
+    "/add",
+    "/subtract",
+    "/complete",
+    "/report",
+];
+// Nothing the API takes comes close
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    query: Option<String>,
+    // Names are lowercased
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn query(&self, name: &str) -> Option<String> {
+        form_urlencoded::parse(self.query.as_deref().unwrap_or("").as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: &'static str, body: Value) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    pub fn error(status: &'static str, message: &str) -> Response {
+        Response::json(status, json!({ "error": message }))
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct TagBody {
+    tag: Option<String>,
+    // Where the duration's being started from, for the git context
+    directory: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MinutesBody {
+    minutes: u64,
+}
+
+#[derive(Deserialize)]
+struct CompleteBody {
+    session_tag: String,
+}
+
+// Binds before returning, so a port that's taken is an error here rather than on another thread
+pub fn serve(port: u16, requests: Sender<DaemonRequest>) -> Result<(), Error> {
+    let token = api_token()?;
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| match e.kind() {
+        ErrorKind::AddrInUse => Error::new(
+            ErrorKind::AddrInUse,
+            format!(
+                "port {} is taken; set a different `port` in the `[api]` table of the config",
+                port
+            ),
+        ),
+        _ => e,
+    })?;
+    println!("Serving the API on http://127.0.0.1:{}", port);
+
+    spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let token = token.clone();
+                    let requests = requests.clone();
+                    spawn(move || handle_connection(stream, &token, &requests));
+                }
+                Err(e) => eprintln!("Error accepting an API connection, {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+// One request per connection
+fn handle_connection(mut stream: TcpStream, token: &str, requests: &Sender<DaemonRequest>) {
+    if let Err(e) = stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+    {
+        eprintln!("Error setting up an API connection, {}", e);
+        return;
+    }
+    let response = match read_request(&stream) {
+        Ok(request) => handle(&request, token, requests),
+        Err(e) => Response::error("400 Bad Request", &e.to_string()),
+    };
+    if response.content_type == EVENT_STREAM {
+        return stream_events(stream, requests);
+    }
+
+    write_response(&mut stream, &response);
+}
+
+pub fn write_response(stream: &mut TcpStream, response: &Response) {
+    let head = format!(
+        "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    // The client's gone if this fails, and there's nobody left to tell
+    let _ = stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(response.body.as_bytes()));
+}
+
+// Server-sent events, until the client goes away. The status comes first, so clients don't have
+// to wait for the next tick to show something.
+fn stream_events(mut stream: TcpStream, requests: &Sender<DaemonRequest>) {
+    let events = events::subscribe();
+    let mut write = |text: String| stream.write_all(text.as_bytes());
+    let format = |name: &str, data: &Value| format!("event: {}\ndata: {}\n\n", name, data);
+
+    let mut result = write(format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncache-control: no-cache\r\nconnection: close\r\n\r\n",
+        EVENT_STREAM
+    ));
+    if let Some(status) = ask(requests, Command::Status) {
+        result = result.and_then(|_| write(format("tick", &status)));
+    }
+    if result.is_err() {
+        return;
+    }
+
+    for event in events {
+        if write(format(event.name, &event.data)).is_err() {
+            return;
+        }
+    }
+}
+
+pub fn read_request(stream: &TcpStream) -> Result<Request, Error> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts
+        .next()
+        .ok_or_else(|| invalid("No method"))?
+        .to_string();
+    let target = parts.next().ok_or_else(|| invalid("No path"))?;
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("The headers didn't end"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .map(|(_, value)| value.parse().map_err(|_| invalid("Bad content-length")))
+        .transpose()?
+        .unwrap_or(0);
+    if length > MAX_BODY_BYTES {
+        return Err(invalid("The body is too big"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    })
+}
+
+fn handle(request: &Request, token: &str, requests: &Sender<DaemonRequest>) -> Response {
+    // Only names for this machine, so a web page on a rebound domain can't reach the API
+    let host = request.header("host").unwrap_or("");
+    let hostname = host.rsplit_once(':').map_or(host, |(hostname, _)| hostname);
+    if !matches!(hostname, "127.0.0.1" | "localhost") {
+        return Response::error("403 Forbidden", "The Host has to be 127.0.0.1 or localhost");
+    }
+
+    if (request.method.as_str(), request.path.as_str()) == ("GET", "/openapi.yaml") {
+        return Response {
+            status: "200 OK",
+            content_type: "application/yaml",
+            body: OPENAPI.to_string(),
+        };
+    }
+
+    let given = request
+        .header("authorization")
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .map(|given| given.trim().to_string());
+    // Browsers' EventSource can't send headers, so the stream also takes it in the query
+    let given = match request.path.as_str() {
+        "/events" => given.or_else(|| request.query("token")),
+        _ => given,
+    };
+    let authorized = given.is_some_and(|given| tokens_match(&given, token));
+    if !authorized {
+        return Response::error(
+            "401 Unauthorized",
+            "Send the token in ~/.productivity-timer/api-token as `Authorization: Bearer <token>`",
+        );
+    }
+
+    route(request, requests).unwrap_or_else(|response| response)
+}
+
+fn route(request: &Request, requests: &Sender<DaemonRequest>) -> Result<Response, Response> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => run(requests, Command::Status),
+        // Streamed by handle_connection
+        ("GET", "/events") => Ok(Response {
+            status: "200 OK",
+            content_type: EVENT_STREAM,
+            body: String::new(),
+        }),
+        ("POST", "/toggle") => {
+            let body: TagBody = body(request)?;
+            run(
+                requests,
+                Command::Toggle {
+                    tag: non_empty(body.tag),
+                    directory: non_empty(body.directory),
+                },
+            )
+        }
+        ("POST", "/switch") => {
+            let body: TagBody = body(request)?;
+            run(
+                requests,
+                Command::Switch {
+                    tag: non_empty(body.tag),
+                    directory: non_empty(body.directory),
+                },
+            )
+        }
+        ("POST", "/add") => {
+            let body: MinutesBody = body(request)?;
+            run(requests, Command::Add(body.minutes))
+        }
+        ("POST", "/subtract") => {
+            let body: MinutesBody = body(request)?;
+            run(requests, Command::Subtract(body.minutes))
+        }
+        ("POST", "/complete") => {
+            let body: CompleteBody = body(request)?;
+            match non_empty(Some(body.session_tag)) {
+                Some(session_tag) => run(requests, Command::Complete(Some(session_tag))),
+                None => Err(Response::error(
+                    "400 Bad Request",
+                    "session_tag can't be empty",
+                )),
+            }
+        }
+        ("GET", "/report") => report(request),
+        (_, path) if ENDPOINTS.contains(&path) => {
+            Err(Response::error("405 Method Not Allowed", "Wrong method"))
+        }
+        _ => Err(Response::error("404 Not Found", "No such endpoint")),
+    }
+}
+
+// Compares every byte, so how long it takes doesn't give away how much of the token was right
+fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+// An empty body is the same as `{}`
+fn body<T: for<'de> Deserialize<'de>>(request: &Request) -> Result<T, Response> {
+    let body = if request.body.iter().all(u8::is_ascii_whitespace) {
+        b"{}".as_slice()
+    } else {
+        &request.body
+    };
+    serde_json::from_slice(body).map_err(|e| Response::error("400 Bad Request", &e.to_string()))
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn run(requests: &Sender<DaemonRequest>, command: Command) -> Result<Response, Response> {
+    ask(requests, command)
+        .map(|result| Response::json("200 OK", result))
+        .ok_or_else(|| Response::error("503 Service Unavailable", "The daemon didn't answer"))
+}
+
+fn report(request: &Request) -> Result<Response, Response> {
+    let bad_request = |message: String| Response::error("400 Bad Request", &message);
+    let date = |name: &str| {
+        request
+            .query(name)
+            .map(|date| {
+                NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map_err(|_| bad_request(format!("{} should be YYYY-MM-DD", name)))
+            })
+            .transpose()
+    };
+
+    let by = match request.query("by") {
+        Some(by) => By::parse(&by)
+            .ok_or_else(|| bad_request(format!("by should be one of {}", By::NAMES.join(", "))))?,
+        None => By::SessionTag,
+    };
+    let session_tag = request.query("session_tag");
+    let totals = totals(by, session_tag.as_deref(), date("since")?, date("until")?)
+        .map_err(|e| Response::error("500 Internal Server Error", &e.to_string()))?;
+
+    Ok(Response::json("200 OK", json!(totals)))
+}
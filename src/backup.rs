@@ -0,0 +1,296 @@
+// Where backups of the database go. Each backend stores whole files by name; which one is used,
+// and how it's set up, comes from the `[backup]` table in the config.
+mod directory;
+mod drive;
+mod schedule;
+mod webdav;
+
+pub use schedule::Scheduler;
+
+use crate::config::{get_config, Backend, BackupConfig, Schedule};
+use crate::credentials::backup_passphrase;
+use crate::crypto;
+use crate::database::{copy_database, database_filename};
+use chrono::{DateTime, Local, Utc};
+use directory::DirectoryBackend;
+use dirs::home_dir;
+use drive::DriveBackend;
+use serde::{Deserialize, Serialize};
+use std::fs::{read, read_to_string, remove_file, rename, write};
+use std::io::{Error, ErrorKind};
+use webdav::WebdavBackend;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupFile {
+    pub name: String,
+    pub size: Option<u64>,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+pub trait BackupBackend {
+    fn list(&self) -> Result<Vec<BackupFile>, Error>;
+    // Creates the file or replaces what's there
+    fn upload(&self, name: &str, contents: &[u8]) -> Result<(), Error>;
+    fn download(&self, name: &str) -> Result<Vec<u8>, Error>;
+    fn delete(&self, name: &str) -> Result<(), Error>;
+}
+
+// `interactive` is whether someone's there to log in, should the backend need it
+pub fn backend(config: &BackupConfig, interactive: bool) -> Result<Box<dyn BackupBackend>, Error> {
+    let missing = |table: &str| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "The {} backup backend needs a [backup.{}] table in the config",
+                table, table
+            ),
+        )
+    };
+
+    match config.backend {
+        Backend::Drive => Ok(Box::new(DriveBackend::new(&config.drive, interactive)?)),
+        Backend::Directory => match &config.directory {
+            Some(directory) => Ok(Box::new(DirectoryBackend::new(&directory.path))),
+            None => Err(missing("directory")),
+        },
+        Backend::Webdav => match &config.webdav {
+            Some(webdav) => Ok(Box::new(WebdavBackend::new(webdav)?)),
+            None => Err(missing("webdav")),
+        },
+    }
+}
+
+// How the last backups went, whether run by hand or by the daemon
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackupStatus {
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    // Failures since the last success
+    pub failures: u32,
+    // When the daemon will try again after a failure
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+fn status_filepath() -> String {
+    home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer/backup-status"
+}
+
+pub fn get_status() -> Result<BackupStatus, Error> {
+    match read_to_string(status_filepath()) {
+        Ok(status) => Ok(serde_json::from_str(&status)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(BackupStatus::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn set_status(status: &BackupStatus) -> Result<(), Error> {
+    let tmp_filepath = status_filepath() + ".tmp";
+    write(&tmp_filepath, serde_json::to_string_pretty(status)?)?;
+    rename(tmp_filepath, status_filepath())
+}
+
+pub fn backup() -> Result<(), Error> {
+    record_status(upload_database(true))
+}
+
+// Notes how a backup (or sync) went in the status file, passing its result along
+pub fn record_status(result: Result<(), Error>) -> Result<(), Error> {
+    let mut status = get_status()?;
+    match &result {
+        Ok(()) => {
+            status.last_success_at = Some(Utc::now());
+            status.failures = 0;
+            status.retry_at = None;
+        }
+        Err(e) => {
+            status.last_error_at = Some(Utc::now());
+            status.last_error = Some(e.to_string());
+            status.failures += 1;
+        }
+    }
+    set_status(&status)?;
+
+    result
+}
+
+pub fn upload_database(interactive: bool) -> Result<(), Error> {
+    let config = get_config()?.backup;
+
+    // Uploading a copy made with sqlite's backup API, rather than the file itself, means we never
+    // send a database that's halfway through a write
+    let copy_filepath = database_filename() + ".backup";
+    copy_database(&copy_filepath).map_err(Error::other)?;
+    let database = read(&copy_filepath);
+    remove_file(&copy_filepath)?;
+
+    let database = seal(database?, config.encrypt, || backup_passphrase(true))?;
+    backend(&config, interactive)?.upload(&config.filename, &database)?;
+    println!("Backed up to {}", config.filename);
+    Ok(())
+}
+
+fn seal<F>(database: Vec<u8>, encrypt: bool, passphrase: F) -> Result<Vec<u8>, Error>
+where
+    F: FnOnce() -> Result<String, Error>,
+{
+    match encrypt {
+        true => crypto::encrypt(&passphrase()?, &database),
+        false => Ok(database),
+    }
+}
+
+// Decrypts a downloaded backup if it was encrypted, whatever the config says now. A wrong
+// passphrase or a backup that's been tampered with are both errors.
+pub fn open<F>(backup: Vec<u8>, passphrase: F) -> Result<Vec<u8>, Error>
+where
+    F: FnOnce() -> Result<String, Error>,
+{
+    match crypto::is_encrypted(&backup) {
+        true => crypto::decrypt(&passphrase()?, &backup),
+        false => Ok(backup),
+    }
+}
+
+pub fn print_status() -> Result<(), Error> {
+    let config = get_config()?.backup;
+    let status = get_status()?;
+    let local = |timestamp: Option<DateTime<Utc>>| match timestamp {
+        Some(timestamp) => timestamp
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string(),
+        None => "never".to_string(),
+    };
+
+    match config.schedule {
+        Schedule::Off => println!("schedule: off; back up with `pt backup`"),
+        Schedule::Session => println!("schedule: after each completed session"),
+        Schedule::Nightly => println!("schedule: nightly at {}", config.nightly_at),
+    }
+    println!("last success: {}", local(status.last_success_at));
+    if let Some(error) = status.last_error {
+        println!("last error: {} ({})", local(status.last_error_at), error);
+    }
+    if status.failures > 0 {
+        println!("failures since the last success: {}", status.failures);
+    }
+    if status.retry_at.is_some() {
+        println!("next retry: {}", local(status.retry_at));
+    }
+    Ok(())
+}
+
+// For the scheduler, which needs to say when it'll try again
+fn set_retry_at(retry_at: Option<DateTime<Utc>>) -> Result<(), Error> {
+    let mut status = get_status()?;
+    status.retry_at = retry_at;
+    set_status(&status)
+}
+
+pub fn list() -> Result<(), Error> {
+    let config = get_config()?.backup;
+    let mut files = backend(&config, true)?.list()?;
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for file in files {
+        let modified_at = match file.modified_at {
+            Some(modified_at) => modified_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            None => "-".to_string(),
+        };
+        let size = match file.size {
+            Some(size) => size.to_string(),
+            None => "-".to_string(),
+        };
+        println!("{:<16} {:>10}  {}", modified_at, size, file.name);
+    }
+    Ok(())
+}
+
+pub fn delete(name: &str) -> Result<(), Error> {
+    let config = get_config()?.backup;
+    backend(&config, true)?.delete(name)?;
+    println!("Deleted {}", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("pt-encrypted-backup-{}", std::process::id()))
+    }
+
+    fn passphrase(passphrase: &str) -> impl FnOnce() -> Result<String, Error> + '_ {
+        move || Ok(passphrase.to_string())
+    }
+
+    #[test]
+    fn encrypted_backups_only_open_with_the_passphrase() {
+        let backend = DirectoryBackend::new(directory().to_str().unwrap());
+        let database = b"SQLite format 3\0 and some sessions".to_vec();
+
+        let sealed = seal(database.clone(), true, passphrase("correct horse")).unwrap();
+        assert!(!sealed
+            .windows(database.len())
+            .any(|window| window == database.as_slice()));
+        backend.upload("time_gained", &sealed).unwrap();
+
+        let downloaded = backend.download("time_gained").unwrap();
+        assert_eq!(
+            open(downloaded.clone(), passphrase("correct horse")).unwrap(),
+            database
+        );
+        assert_eq!(
+            open(downloaded, passphrase("battery staple"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+
+        backend.delete("time_gained").unwrap();
+        std::fs::remove_dir(directory()).unwrap();
+    }
+
+    #[test]
+    fn tampered_backups_are_rejected() {
+        let database = b"SQLite format 3\0 and some sessions".to_vec();
+        let sealed = seal(database, true, passphrase("correct horse")).unwrap();
+
+        // The salt, the nonce, and the ciphertext
+        for i in [10, 30, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert_eq!(
+                open(tampered, passphrase("correct horse"))
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::InvalidData
+            );
+        }
+
+        let truncated = sealed[..20].to_vec();
+        assert_eq!(
+            open(truncated, passphrase("correct horse"))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn plain_backups_pass_through_without_a_passphrase() {
+        let database = b"SQLite format 3\0".to_vec();
+        let sealed = seal(database.clone(), false, || panic!("no passphrase needed")).unwrap();
+
+        assert_eq!(sealed, database);
+        assert_eq!(
+            open(sealed, || panic!("no passphrase needed")).unwrap(),
+            database
+        );
+    }
+}
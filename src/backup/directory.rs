@@ -0,0 +1,104 @@
+// Backs up into a directory on this machine, e.g. a mounted network share or a folder that
+// another tool (Syncthing, Dropbox, rsync from cron) carries elsewhere
+use super::{BackupBackend, BackupFile};
+use crate::config::expand_home;
+use chrono::{DateTime, Utc};
+use std::fs::{create_dir_all, read, read_dir, remove_file, rename, File};
+use std::io::{Error, Write};
+use std::path::PathBuf;
+
+pub struct DirectoryBackend {
+    path: PathBuf,
+}
+
+impl DirectoryBackend {
+    pub fn new(path: &str) -> DirectoryBackend {
+        DirectoryBackend {
+            path: PathBuf::from(expand_home(path)),
+        }
+    }
+}
+
+impl BackupBackend for DirectoryBackend {
+    fn list(&self) -> Result<Vec<BackupFile>, Error> {
+        let mut files = Vec::new();
+
+        for entry in read_dir(&self.path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Skip anything half-uploaded
+            if !metadata.is_file() || name.ends_with(".tmp") {
+                continue;
+            }
+
+            files.push(BackupFile {
+                name,
+                size: Some(metadata.len()),
+                modified_at: metadata.modified().ok().map(DateTime::<Utc>::from),
+            });
+        }
+
+        Ok(files)
+    }
+
+    // Written to the side and renamed into place, so whatever's syncing the directory never sees
+    // half a database
+    fn upload(&self, name: &str, contents: &[u8]) -> Result<(), Error> {
+        create_dir_all(&self.path)?;
+
+        let tmp_filepath = self.path.join(name.to_string() + ".tmp");
+        let mut file = File::create(&tmp_filepath)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        rename(tmp_filepath, self.path.join(name))
+    }
+
+    fn download(&self, name: &str) -> Result<Vec<u8>, Error> {
+        read(self.path.join(name))
+    }
+
+    fn delete(&self, name: &str) -> Result<(), Error> {
+        remove_file(self.path.join(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn directory(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pt-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn round_trips_files() {
+        let path = directory("directory-backend");
+        let backend = DirectoryBackend::new(path.to_str().unwrap());
+
+        backend.upload("time_gained", b"first").unwrap();
+        backend.upload("time_gained", b"second").unwrap();
+        backend.upload("other", b"other").unwrap();
+
+        assert_eq!(backend.download("time_gained").unwrap(), b"second");
+
+        let mut names: Vec<String> = backend
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["other", "time_gained"]);
+
+        backend.delete("other").unwrap();
+        assert_eq!(
+            backend.download("other").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(backend.list().unwrap().len(), 1);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
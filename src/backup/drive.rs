@@ -0,0 +1,521 @@
+// Backs up into the Google Drive of whoever's logged in with `pt auth login`. Backups live in
+// their own folder and are found by name and mime type, so nothing else in the Drive is touched.
+use super::{BackupBackend, BackupFile};
+use crate::config::DriveConfig;
+use crate::credentials::get_client_credentials;
+use crate::oauth;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use std::io::{Error, ErrorKind};
+
+const DRIVE_URL: &str = "https://www.googleapis.com";
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+const BOUNDARY: &str = "productivity-timer-boundary";
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DriveFile {
+    id: String,
+    name: String,
+    // Drive sends 64-bit numbers as strings
+    size: Option<String>,
+    modified_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FilesResponse {
+    files: Vec<DriveFile>,
+}
+
+type GetToken = fn() -> Result<String, Error>;
+
+pub struct DriveBackend {
+    client: Client,
+    // Where the API lives; only ever changed for tests
+    url: String,
+    api_key: String,
+    folder: String,
+    mime_type: String,
+    keep_revisions: bool,
+    get_token: GetToken,
+    refresh_token: GetToken,
+}
+
+impl DriveBackend {
+    // Without `interactive`, as from the daemon, a missing or lapsed login is an error rather than a
+    // trip through the browser
+    pub fn new(config: &DriveConfig, interactive: bool) -> Result<DriveBackend, Error> {
+        let (get_token, refresh_token): (GetToken, GetToken) = match interactive {
+            true => (|| Ok(oauth::get_token()), || Ok(oauth::refresh_token())),
+            false => (oauth::get_token_unattended, oauth::refresh_token_unattended),
+        };
+
+        Ok(DriveBackend {
+            client: Client::new(),
+            url: DRIVE_URL.to_string(),
+            api_key: get_client_credentials()?.api_key,
+            folder: config.folder.to_string(),
+            mime_type: config.mime_type.to_string(),
+            keep_revisions: config.keep_revisions,
+            get_token,
+            refresh_token,
+        })
+    }
+
+    fn files_url(&self) -> String {
+        self.url.to_string() + "/drive/v3/files"
+    }
+
+    fn upload_url(&self) -> String {
+        self.url.to_string() + "/upload/drive/v3/files"
+    }
+
+    // Sends a request with our access token. If the API rejects it, the token is refreshed and the
+    // request is sent once more.
+    fn send_authorized<F>(&self, request: F) -> Result<Response, Error>
+    where
+        F: Fn(&str) -> RequestBuilder,
+    {
+        let send = |token: String| {
+            request(&token)
+                .query(&[("key", &self.api_key)])
+                .send()
+                .map_err(Error::other)
+        };
+
+        let response = send((self.get_token)()?)?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return check(send((self.refresh_token)()?)?);
+        }
+
+        check(response)
+    }
+
+    fn files(&self, query: &str) -> Result<Vec<DriveFile>, Error> {
+        Ok(self
+            .send_authorized(|token| {
+                self.client
+                    .get(self.files_url())
+                    .query(&[
+                        ("q", query),
+                        ("fields", "files(id,name,size,modifiedTime)"),
+                        ("orderBy", "modifiedTime desc"),
+                    ])
+                    .header(ACCEPT, "application/json")
+                    .bearer_auth(token)
+            })?
+            .json::<FilesResponse>()
+            .map_err(Error::other)?
+            .files)
+    }
+
+    fn find_folder(&self) -> Result<Option<DriveFile>, Error> {
+        let query = format!(
+            "name = '{}' and mimeType = '{}' and trashed = false",
+            escape(&self.folder),
+            FOLDER_MIME_TYPE
+        );
+        Ok(self.files(&query)?.into_iter().next())
+    }
+
+    fn find_or_create_folder(&self) -> Result<DriveFile, Error> {
+        if let Some(folder) = self.find_folder()? {
+            return Ok(folder);
+        }
+
+        let metadata = json!({ "name": self.folder, "mimeType": FOLDER_MIME_TYPE });
+        self.send_authorized(|token| {
+            self.client
+                .post(self.files_url())
+                .header(ACCEPT, "application/json")
+                .bearer_auth(token)
+                .json(&metadata)
+        })?
+        .json::<DriveFile>()
+        .map_err(Error::other)
+    }
+
+    fn find(&self, name: &str) -> Result<Option<DriveFile>, Error> {
+        let folder = match self.find_folder()? {
+            Some(folder) => folder,
+            None => return Ok(None),
+        };
+
+        let query = format!(
+            "name = '{}' and mimeType = '{}' and '{}' in parents and trashed = false",
+            escape(name),
+            escape(&self.mime_type),
+            escape(&folder.id)
+        );
+        Ok(self.files(&query)?.into_iter().next())
+    }
+
+    fn get(&self, name: &str) -> Result<DriveFile, Error> {
+        self.find(name)?.ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("There's no {} in {} in Google Drive", name, self.folder),
+            )
+        })
+    }
+
+    fn create(&self, name: &str, contents: &[u8]) -> Result<(), Error> {
+        let folder = self.find_or_create_folder()?;
+        let metadata = json!({ "name": name, "mimeType": self.mime_type, "parents": [folder.id] });
+
+        // Metadata and contents in one request, as multipart/related
+        let mut body = format!(
+            "--{}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{}\r\n--{}\r\nContent-Type: {}\r\n\r\n",
+            BOUNDARY, metadata, BOUNDARY, self.mime_type
+        )
+        .into_bytes();
+        body.extend_from_slice(contents);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+
+        self.send_authorized(|token| {
+            self.client
+                .post(self.upload_url())
+                .query(&[
+                    ("uploadType", "multipart"),
+                    ("keepRevisionForever", &self.keep_revisions.to_string()),
+                ])
+                .header(ACCEPT, "application/json")
+                .header(
+                    CONTENT_TYPE,
+                    format!("multipart/related; boundary={}", BOUNDARY),
+                )
+                .bearer_auth(token)
+                .body(body.clone())
+        })?;
+
+        Ok(())
+    }
+}
+
+impl BackupBackend for DriveBackend {
+    fn list(&self) -> Result<Vec<BackupFile>, Error> {
+        let folder = match self.find_folder()? {
+            Some(folder) => folder,
+            None => return Ok(Vec::new()),
+        };
+
+        let query = format!("'{}' in parents and trashed = false", escape(&folder.id));
+        Ok(self
+            .files(&query)?
+            .into_iter()
+            .map(|file| BackupFile {
+                name: file.name,
+                size: file.size.and_then(|size| size.parse().ok()),
+                modified_at: file.modified_time,
+            })
+            .collect())
+    }
+
+    // Uploading over the same file makes a new revision of it, which Drive keeps around
+    fn upload(&self, name: &str, contents: &[u8]) -> Result<(), Error> {
+        let file = match self.find(name)? {
+            Some(file) => file,
+            None => return self.create(name, contents),
+        };
+
+        self.send_authorized(|token| {
+            self.client
+                .patch(self.upload_url() + "/" + &file.id)
+                .query(&[
+                    ("uploadType", "media"),
+                    ("keepRevisionForever", &self.keep_revisions.to_string()),
+                ])
+                .header(ACCEPT, "application/json")
+                .header(CONTENT_TYPE, &self.mime_type)
+                .bearer_auth(token)
+                .body(contents.to_vec())
+        })?;
+
+        Ok(())
+    }
+
+    fn download(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let file = self.get(name)?;
+
+        let response = self.send_authorized(|token| {
+            self.client
+                .get(self.files_url() + "/" + &file.id)
+                .query(&[("alt", "media")])
+                .bearer_auth(token)
+        })?;
+
+        Ok(response.bytes().map_err(Error::other)?.to_vec())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), Error> {
+        let file = self.get(name)?;
+
+        self.send_authorized(|token| {
+            self.client
+                .delete(self.files_url() + "/" + &file.id)
+                .bearer_auth(token)
+        })?;
+
+        Ok(())
+    }
+}
+
+// For string literals in Drive's query language
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn check(response: Response) -> Result<Response, Error> {
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::NOT_FOUND => Err(Error::new(ErrorKind::NotFound, "Not found in Google Drive")),
+        status => Err(Error::other(format!(
+            "Google Drive responded with {}: {}",
+            status,
+            response.text().unwrap_or_default()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Request, Response};
+    use regex::Regex;
+    use reqwest::Url;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone)]
+    struct MockFile {
+        id: String,
+        name: String,
+        mime_type: String,
+        parents: Vec<String>,
+        contents: Vec<u8>,
+        revisions: Vec<bool>,
+    }
+
+    type Files = Arc<Mutex<Vec<MockFile>>>;
+
+    fn params(request: &Request) -> HashMap<String, String> {
+        let url = Url::parse(&format!(
+            "http://drive/?{}",
+            request.query.clone().unwrap_or_default()
+        ))
+        .unwrap();
+        url.query_pairs().into_owned().collect()
+    }
+
+    // Understands the handful of queries the backend makes
+    fn matches(file: &MockFile, query: &str) -> bool {
+        let capture = |pattern: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .captures(query)
+                .map(|captures| captures[1].to_string())
+        };
+
+        capture(r"name = '([^']*)'").is_none_or(|name| file.name == name)
+            && capture(r"mimeType = '([^']*)'").is_none_or(|mime| file.mime_type == mime)
+            && capture(r"'([^']*)' in parents").is_none_or(|parent| file.parents.contains(&parent))
+    }
+
+    fn json_file(file: &MockFile) -> serde_json::Value {
+        json!({
+            "id": file.id,
+            "name": file.name,
+            "size": file.contents.len().to_string(),
+            "modifiedTime": "2026-10-19T12:00:00Z",
+        })
+    }
+
+    // Just enough of Drive's v3 API to back up to. Anything but our test token is rejected.
+    fn serve(files: Files) -> String {
+        test_server::serve(move |request| {
+            let mut files = files.lock().unwrap();
+            if request.header("authorization") != Some("Bearer test-token") {
+                return Response::new(401);
+            }
+            let params = params(&request);
+            assert_eq!(params.get("key").map(String::as_str), Some("test-key"));
+            let next_id = format!("id-{}", files.len());
+
+            let path: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+            match (request.method.as_str(), path.as_slice()) {
+                ("GET", ["drive", "v3", "files"]) => {
+                    let listed: Vec<_> = files
+                        .iter()
+                        .filter(|file| matches(file, &params["q"]))
+                        .map(json_file)
+                        .collect();
+                    Response::new(200).body(json!({ "files": listed }).to_string())
+                }
+                ("POST", ["drive", "v3", "files"]) => {
+                    let metadata: serde_json::Value =
+                        serde_json::from_slice(&request.body).unwrap();
+                    let file = MockFile {
+                        id: next_id,
+                        name: metadata["name"].as_str().unwrap().to_string(),
+                        mime_type: metadata["mimeType"].as_str().unwrap().to_string(),
+                        parents: Vec::new(),
+                        contents: Vec::new(),
+                        revisions: Vec::new(),
+                    };
+                    let response = json_file(&file);
+                    files.push(file);
+                    Response::new(200).body(response.to_string())
+                }
+                ("POST", ["upload", "drive", "v3", "files"]) => {
+                    assert_eq!(params["uploadType"], "multipart");
+                    let boundary = request
+                        .header("content-type")
+                        .unwrap()
+                        .split("boundary=")
+                        .nth(1)
+                        .unwrap()
+                        .to_string();
+                    let body = String::from_utf8(request.body.clone()).unwrap();
+                    let parts: Vec<&str> = body.split(&format!("--{}", boundary)).collect();
+                    let part_body = |part: &str| {
+                        let (_, body) = part.split_once("\r\n\r\n").unwrap();
+                        body.strip_suffix("\r\n").unwrap().to_string()
+                    };
+
+                    let metadata: serde_json::Value =
+                        serde_json::from_str(&part_body(parts[1])).unwrap();
+                    let file = MockFile {
+                        id: next_id,
+                        name: metadata["name"].as_str().unwrap().to_string(),
+                        mime_type: metadata["mimeType"].as_str().unwrap().to_string(),
+                        parents: vec![metadata["parents"][0].as_str().unwrap().to_string()],
+                        contents: part_body(parts[2]).into_bytes(),
+                        revisions: vec![params["keepRevisionForever"] == "true"],
+                    };
+                    let response = json_file(&file);
+                    files.push(file);
+                    Response::new(200).body(response.to_string())
+                }
+                ("PATCH", ["upload", "drive", "v3", "files", id]) => {
+                    assert_eq!(params["uploadType"], "media");
+                    match files.iter_mut().find(|file| file.id == *id) {
+                        Some(file) => {
+                            file.contents = request.body.clone();
+                            file.revisions.push(params["keepRevisionForever"] == "true");
+                            Response::new(200).body(json_file(file).to_string())
+                        }
+                        None => Response::new(404),
+                    }
+                }
+                ("GET", ["drive", "v3", "files", id]) => {
+                    assert_eq!(params["alt"], "media");
+                    match files.iter().find(|file| file.id == *id) {
+                        Some(file) => Response::new(200).body(file.contents.clone()),
+                        None => Response::new(404),
+                    }
+                }
+                ("DELETE", ["drive", "v3", "files", id]) => {
+                    match files.iter().position(|file| file.id == *id) {
+                        Some(i) => {
+                            files.remove(i);
+                            Response::new(204)
+                        }
+                        None => Response::new(404),
+                    }
+                }
+                _ => Response::new(400),
+            }
+        })
+    }
+
+    fn backend(url: String, get_token: GetToken) -> DriveBackend {
+        DriveBackend {
+            client: Client::new(),
+            url,
+            api_key: "test-key".to_string(),
+            folder: "productivity-timer".to_string(),
+            mime_type: "application/x-sqlite3".to_string(),
+            keep_revisions: true,
+            get_token,
+            refresh_token: || Ok("test-token".to_string()),
+        }
+    }
+
+    // Something else the app can see, which backups must leave alone
+    fn unrelated_file() -> MockFile {
+        MockFile {
+            id: "unrelated".to_string(),
+            name: "notes.txt".to_string(),
+            mime_type: "text/plain".to_string(),
+            parents: vec!["root".to_string()],
+            contents: b"notes".to_vec(),
+            revisions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn creates_the_folder_and_file_then_updates_it() {
+        let files: Files = Arc::new(Mutex::new(vec![unrelated_file()]));
+        let backend = backend(serve(Arc::clone(&files)), || Ok("test-token".to_string()));
+
+        backend.upload("time_gained", b"first").unwrap();
+        backend.upload("time_gained", b"second").unwrap();
+
+        {
+            let files = files.lock().unwrap();
+            assert_eq!(files.len(), 3);
+            assert_eq!(files[0].contents, b"notes");
+
+            let folder = &files[1];
+            assert_eq!(folder.name, "productivity-timer");
+            assert_eq!(folder.mime_type, FOLDER_MIME_TYPE);
+
+            let backup = &files[2];
+            assert_eq!(backup.name, "time_gained");
+            assert_eq!(backup.mime_type, "application/x-sqlite3");
+            assert_eq!(backup.parents, vec![folder.id.clone()]);
+            assert_eq!(backup.revisions, vec![true, true]);
+        }
+
+        assert_eq!(backend.download("time_gained").unwrap(), b"second");
+        let listed: Vec<String> = backend
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        assert_eq!(listed, vec!["time_gained"]);
+
+        backend.delete("time_gained").unwrap();
+        assert!(backend.list().unwrap().is_empty());
+        assert_eq!(files.lock().unwrap()[0].name, "notes.txt");
+    }
+
+    #[test]
+    fn reports_a_missing_backup_instead_of_panicking() {
+        let files: Files = Arc::new(Mutex::new(vec![unrelated_file()]));
+        let backend = backend(serve(files), || Ok("test-token".to_string()));
+
+        assert!(backend.list().unwrap().is_empty());
+        assert_eq!(
+            backend.download("time_gained").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn refreshes_a_rejected_token() {
+        let files: Files = Arc::new(Mutex::new(Vec::new()));
+        let backend = backend(
+            serve(Arc::clone(&files)),
+            || Ok("expired-token".to_string()),
+        );
+
+        backend.upload("time_gained", b"first").unwrap();
+        assert_eq!(backend.download("time_gained").unwrap(), b"first");
+    }
+}
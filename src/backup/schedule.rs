@@ -0,0 +1,285 @@
+// Backups the daemon runs on its own, per `schedule` in the `[backup]` table of the config. They
+// run on a thread so a slow upload doesn't hold up the timer, and failures are retried with
+// exponential backoff until one succeeds. They're syncs, so another machine's backups are merged in
+// rather than overwritten.
+use super::{get_status, set_retry_at};
+use crate::config::{get_config, Schedule};
+use crate::hooks;
+use crate::hooks::Event;
+use crate::sync::sync;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, Utc};
+use serde_json::json;
+use std::io::Error;
+use std::thread::{spawn, JoinHandle};
+
+const FIRST_RETRY_SECONDS: i64 = 60;
+const MAX_RETRY_SECONDS: i64 = 60 * 60;
+
+pub struct Scheduler {
+    schedule: Schedule,
+    nightly_at: NaiveTime,
+    // A backup is owed, whether or not it's been tried yet
+    pending: bool,
+    failures: u32,
+    retry_at: Option<DateTime<Utc>>,
+    running: Option<JoinHandle<Result<(), String>>>,
+    // The last day a nightly backup was owed for
+    nightly_on: Option<NaiveDate>,
+    // What a backup is; only ever changed for tests
+    run: fn() -> Result<(), Error>,
+}
+
+impl Scheduler {
+    // The config is only read here, so the daemon has to be restarted to pick up changes
+    pub fn new() -> Scheduler {
+        let (schedule, nightly_at) = match get_config() {
+            Ok(config) => match NaiveTime::parse_from_str(&config.backup.nightly_at, "%H:%M") {
+                Ok(nightly_at) => (config.backup.schedule, nightly_at),
+                Err(e) => {
+                    eprintln!("Error, nightly_at should be HH:MM: {}", e);
+                    (Schedule::Off, NaiveTime::MIN)
+                }
+            },
+            Err(e) => {
+                eprintln!("Error, {}", e);
+                (Schedule::Off, NaiveTime::MIN)
+            }
+        };
+
+        // Don't back up again tonight if that's already happened, e.g. before a restart
+        let nightly_on = get_status()
+            .ok()
+            .and_then(|status| status.last_success_at)
+            .map(|last_success_at| backup_day(last_success_at.with_timezone(&Local), nightly_at));
+
+        Scheduler {
+            schedule,
+            nightly_at,
+            pending: false,
+            failures: 0,
+            retry_at: None,
+            running: None,
+            nightly_on,
+            // Nobody's around to log in when the daemon backs up
+            run: || sync(false),
+        }
+    }
+
+    pub fn session_completed(&mut self) {
+        if self.schedule == Schedule::Session {
+            self.pending = true;
+        }
+    }
+
+    // Called every time around the daemon's loop
+    pub fn tick(&mut self) {
+        if let Some(e) = self.advance(Local::now()) {
+            self.report_failure(e);
+        }
+    }
+
+    // Collects a finished backup and starts one if it's owed and due. Returns why the backup
+    // failed, if one just did.
+    fn advance(&mut self, now: DateTime<Local>) -> Option<String> {
+        if self.schedule == Schedule::Off {
+            return None;
+        }
+
+        let mut failure = None;
+        if self
+            .running
+            .as_ref()
+            .is_some_and(|running| running.is_finished())
+        {
+            let result = self
+                .running
+                .take()
+                .unwrap()
+                .join()
+                .unwrap_or_else(|_| Err("The backup panicked".to_string()));
+            failure = self.finished(result, now.with_timezone(&Utc));
+        }
+
+        if self.schedule == Schedule::Nightly {
+            let day = backup_day(now, self.nightly_at);
+            if self.nightly_on.is_none_or(|nightly_on| nightly_on < day) {
+                self.pending = true;
+            }
+            self.nightly_on = Some(day);
+        }
+
+        let due = self
+            .retry_at
+            .is_none_or(|retry_at| retry_at <= now.with_timezone(&Utc));
+        if self.pending && due && self.running.is_none() {
+            let run = self.run;
+            self.running = Some(spawn(move || run().map_err(|e| e.to_string())));
+        }
+
+        failure
+    }
+
+    fn finished(&mut self, result: Result<(), String>, now: DateTime<Utc>) -> Option<String> {
+        match result {
+            Ok(()) => {
+                self.pending = false;
+                self.failures = 0;
+                self.retry_at = None;
+                None
+            }
+            Err(e) => {
+                self.failures += 1;
+                self.retry_at = Some(now + retry_after(self.failures));
+                Some(e)
+            }
+        }
+    }
+
+    fn report_failure(&self, e: String) {
+        let retry_at = self.retry_at.unwrap();
+        eprintln!("Error backing up, retrying at {}: {}", retry_at, e);
+        hooks::run(
+            Event::BackupFailed,
+            json!({
+                "error": e,
+                "failures": self.failures,
+                "retry_at": retry_at.with_timezone(&Local).to_rfc3339(),
+            }),
+        );
+
+        if let Err(e) = set_retry_at(self.retry_at) {
+            eprintln!("Error writing the backup status, {}", e);
+        }
+    }
+}
+
+// A minute, then doubling up to an hour
+fn retry_after(failures: u32) -> Duration {
+    let seconds = FIRST_RETRY_SECONDS.saturating_mul(1 << failures.saturating_sub(1).min(30));
+    Duration::seconds(seconds.min(MAX_RETRY_SECONDS))
+}
+
+// Nightly backups belong to the day they're run after `nightly_at` on, so one made at 01:00 with
+// `nightly_at` at 03:00 counts for the day before
+fn backup_day(at: DateTime<Local>, nightly_at: NaiveTime) -> NaiveDate {
+    let day = at.date_naive();
+    if at.time() >= nightly_at {
+        day
+    } else {
+        day.pred_opt().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::thread::sleep;
+
+    fn scheduler(schedule: Schedule, run: fn() -> Result<(), Error>) -> Scheduler {
+        Scheduler {
+            schedule,
+            nightly_at: NaiveTime::from_hms_opt(3, 0, 0).unwrap(),
+            pending: false,
+            failures: 0,
+            retry_at: None,
+            running: None,
+            nightly_on: None,
+            run,
+        }
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 1, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn wait(scheduler: &Scheduler) {
+        while !scheduler.running.as_ref().unwrap().is_finished() {
+            sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn retries_back_off_to_an_hour() {
+        let seconds: Vec<i64> = [1, 2, 3, 6, 7, 40]
+            .iter()
+            .map(|failures| retry_after(*failures).num_seconds())
+            .collect();
+
+        assert_eq!(seconds, vec![60, 120, 240, 1920, 3600, 3600]);
+    }
+
+    #[test]
+    fn nightly_backups_before_nightly_at_count_for_the_day_before() {
+        let nightly_at = NaiveTime::from_hms_opt(3, 0, 0).unwrap();
+        let day = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+
+        assert_eq!(backup_day(at(2, 2, 59), nightly_at), day(1));
+        assert_eq!(backup_day(at(2, 3, 0), nightly_at), day(2));
+        assert_eq!(backup_day(at(2, 23, 59), nightly_at), day(2));
+        assert_eq!(
+            backup_day(at(1, 0, 30), nightly_at),
+            NaiveDate::from_ymd_opt(2023, 12, 31).unwrap()
+        );
+    }
+
+    #[test]
+    fn session_backups_run_once_owed_and_retry_when_due() {
+        let mut scheduler = scheduler(Schedule::Session, || Err(Error::other("offline")));
+
+        assert_eq!(scheduler.advance(at(2, 12, 0)), None);
+        assert!(scheduler.running.is_none());
+
+        scheduler.session_completed();
+        scheduler.advance(at(2, 12, 0));
+        wait(&scheduler);
+        assert_eq!(scheduler.advance(at(2, 12, 0)), Some("offline".to_string()));
+        assert_eq!(scheduler.failures, 1);
+        assert_eq!(scheduler.retry_at, Some(at(2, 12, 1).with_timezone(&Utc)));
+        assert!(scheduler.running.is_none());
+
+        // Still owed, but not tried again until the retry's due
+        scheduler.advance(at(2, 12, 0));
+        assert!(scheduler.running.is_none());
+
+        scheduler.run = || Ok(());
+        scheduler.advance(at(2, 12, 1));
+        wait(&scheduler);
+        assert_eq!(scheduler.advance(at(2, 12, 1)), None);
+        assert!(!scheduler.pending);
+        assert_eq!(scheduler.failures, 0);
+        assert_eq!(scheduler.retry_at, None);
+
+        scheduler.advance(at(2, 12, 2));
+        assert!(scheduler.running.is_none());
+    }
+
+    #[test]
+    fn nightly_backups_run_once_past_nightly_at() {
+        let mut scheduler = scheduler(Schedule::Nightly, || Ok(()));
+        scheduler.nightly_on = Some(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+
+        scheduler.advance(at(2, 2, 59));
+        assert!(scheduler.running.is_none());
+
+        scheduler.advance(at(2, 3, 0));
+        wait(&scheduler);
+        scheduler.advance(at(2, 3, 1));
+        assert!(!scheduler.pending);
+        assert!(scheduler.running.is_none());
+
+        scheduler.advance(at(2, 23, 0));
+        assert!(scheduler.running.is_none());
+    }
+
+    #[test]
+    fn nothing_runs_when_the_schedule_is_off() {
+        let mut scheduler = scheduler(Schedule::Off, || panic!("backups are off"));
+        scheduler.pending = true;
+
+        assert_eq!(scheduler.advance(at(2, 12, 0)), None);
+        assert!(scheduler.running.is_none());
+    }
+}
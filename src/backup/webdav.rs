@@ -0,0 +1,317 @@
+// Backs up into a WebDAV collection: Nextcloud, ownCloud, a NAS, `rclone serve webdav`, and so on
+use super::{BackupBackend, BackupFile};
+use crate::config::WebdavConfig;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::{Method, StatusCode, Url};
+use std::io::{Error, ErrorKind};
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop>
+    <d:resourcetype/>
+    <d:getcontentlength/>
+    <d:getlastmodified/>
+  </d:prop>
+</d:propfind>"#;
+
+pub struct WebdavBackend {
+    client: Client,
+    url: Url,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl WebdavBackend {
+    pub fn new(config: &WebdavConfig) -> Result<WebdavBackend, Error> {
+        // Without the trailing "/", joining a name would replace the collection's last segment
+        let url = if config.url.ends_with('/') {
+            config.url.to_string()
+        } else {
+            config.url.to_string() + "/"
+        };
+
+        Ok(WebdavBackend {
+            client: Client::new(),
+            url: Url::parse(&url).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?,
+            username: config.username.clone(),
+            password: config.password.clone(),
+        })
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    fn file_url(&self, name: &str) -> Result<Url, Error> {
+        self.url
+            .join(name)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+    }
+}
+
+impl BackupBackend for WebdavBackend {
+    fn list(&self) -> Result<Vec<BackupFile>, Error> {
+        let response = check(
+            self.request(Method::from_bytes(b"PROPFIND").unwrap(), self.url.clone())
+                .header("Depth", "1")
+                .header("Content-Type", "application/xml")
+                .body(PROPFIND_BODY)
+                .send()
+                .map_err(Error::other)?,
+        )?;
+
+        parse_multistatus(&self.url, &response.text().map_err(Error::other)?)
+    }
+
+    fn upload(&self, name: &str, contents: &[u8]) -> Result<(), Error> {
+        check(
+            self.request(Method::PUT, self.file_url(name)?)
+                .header("Content-Type", "application/octet-stream")
+                .body(contents.to_vec())
+                .send()
+                .map_err(Error::other)?,
+        )?;
+        Ok(())
+    }
+
+    fn download(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let response = check(
+            self.request(Method::GET, self.file_url(name)?)
+                .send()
+                .map_err(Error::other)?,
+        )?;
+        Ok(response.bytes().map_err(Error::other)?.to_vec())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), Error> {
+        check(
+            self.request(Method::DELETE, self.file_url(name)?)
+                .send()
+                .map_err(Error::other)?,
+        )?;
+        Ok(())
+    }
+}
+
+fn check(response: Response) -> Result<Response, Error> {
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::NOT_FOUND => Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} isn't on the WebDAV server", response.url()),
+        )),
+        status => Err(Error::other(format!(
+            "The WebDAV server responded with {}",
+            status
+        ))),
+    }
+}
+
+// Turns a PROPFIND response into the files directly inside the collection, skipping the
+// collection itself and any subcollections
+fn parse_multistatus(collection: &Url, xml: &str) -> Result<Vec<BackupFile>, Error> {
+    let document =
+        roxmltree::Document::parse(xml).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let mut files = Vec::new();
+    for response in document
+        .descendants()
+        .filter(|node| is_dav(node, "response"))
+    {
+        if find(response, "collection").is_some() {
+            continue;
+        }
+
+        let href = match find(response, "href").and_then(|href| href.text()) {
+            Some(href) => href.trim(),
+            None => continue,
+        };
+        // Servers send hrefs as paths or as whole URLs
+        let url = collection
+            .join(href)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let name = match url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+        {
+            Some(name) if !name.is_empty() => percent_decode(name),
+            _ => continue,
+        };
+
+        files.push(BackupFile {
+            name,
+            size: find(response, "getcontentlength")
+                .and_then(|length| length.text())
+                .and_then(|length| length.trim().parse().ok()),
+            modified_at: find(response, "getlastmodified")
+                .and_then(|modified| modified.text())
+                .and_then(|modified| DateTime::parse_from_rfc2822(modified.trim()).ok())
+                .map(|modified| modified.with_timezone(&Utc)),
+        });
+    }
+
+    Ok(files)
+}
+
+fn is_dav(node: &roxmltree::Node, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().namespace() == Some("DAV:")
+        && node.tag_name().name() == name
+}
+
+fn find<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.descendants().find(|node| is_dav(node, name))
+}
+
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = if bytes[i] == b'%' {
+            segment
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    // Just enough of a WebDAV server to back up to: one flat collection at /dav/
+    fn serve() -> String {
+        let files: Arc<Mutex<BTreeMap<String, Vec<u8>>>> = Arc::new(Mutex::new(BTreeMap::new()));
+
+        let base_url = test_server::serve(move |request| {
+            let mut files = files.lock().unwrap();
+            if request.header("authorization") != Some("Basic bWU6c2VjcmV0") {
+                return Response::new(401);
+            }
+
+            let name = request.path.trim_start_matches("/dav/").to_string();
+            match request.method.as_str() {
+                "PROPFIND" if name.is_empty() && request.header("depth") == Some("1") => {
+                    let mut xml = r#"<?xml version="1.0"?><D:multistatus xmlns:D="DAV:">
+                        <D:response><D:href>/dav/</D:href><D:propstat><D:prop>
+                        <D:resourcetype><D:collection/></D:resourcetype>
+                        </D:prop></D:propstat></D:response>"#
+                        .to_string();
+                    for (name, contents) in files.iter() {
+                        xml += &format!(
+                            "<D:response><D:href>/dav/{}</D:href><D:propstat><D:prop>\
+                             <D:resourcetype/>\
+                             <D:getcontentlength>{}</D:getcontentlength>\
+                             <D:getlastmodified>Mon, 19 Oct 2026 12:00:00 GMT</D:getlastmodified>\
+                             </D:prop></D:propstat></D:response>",
+                            name,
+                            contents.len()
+                        );
+                    }
+                    Response::new(207).body(xml + "</D:multistatus>")
+                }
+                "PUT" => {
+                    let status = if files.contains_key(&name) { 204 } else { 201 };
+                    files.insert(name, request.body);
+                    Response::new(status)
+                }
+                "GET" => match files.get(&name) {
+                    Some(contents) => Response::new(200).body(contents.clone()),
+                    None => Response::new(404),
+                },
+                "DELETE" => match files.remove(&name) {
+                    Some(_) => Response::new(204),
+                    None => Response::new(404),
+                },
+                _ => Response::new(405),
+            }
+        });
+
+        base_url + "/dav"
+    }
+
+    fn backend(url: String, password: &str) -> WebdavBackend {
+        WebdavBackend::new(&WebdavConfig {
+            url,
+            username: Some("me".to_string()),
+            password: Some(password.to_string()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trips_files() {
+        let backend = backend(serve(), "secret");
+
+        backend.upload("time_gained", b"first").unwrap();
+        backend.upload("time_gained", b"second").unwrap();
+        backend.upload("time gained old", b"old").unwrap();
+
+        assert_eq!(backend.download("time_gained").unwrap(), b"second");
+        assert_eq!(
+            backend.list().unwrap(),
+            vec![
+                BackupFile {
+                    name: "time gained old".to_string(),
+                    size: Some(3),
+                    modified_at: Some(
+                        DateTime::parse_from_rfc3339("2026-10-19T12:00:00Z")
+                            .unwrap()
+                            .with_timezone(&Utc)
+                    ),
+                },
+                BackupFile {
+                    name: "time_gained".to_string(),
+                    size: Some(6),
+                    modified_at: Some(
+                        DateTime::parse_from_rfc3339("2026-10-19T12:00:00Z")
+                            .unwrap()
+                            .with_timezone(&Utc)
+                    ),
+                },
+            ]
+        );
+
+        backend.delete("time gained old").unwrap();
+        assert_eq!(
+            backend.download("time gained old").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(backend.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reports_rejected_credentials() {
+        let backend = backend(serve(), "wrong");
+
+        assert!(backend.upload("time_gained", b"first").is_err());
+        assert!(backend.list().is_err());
+    }
+}
@@ -0,0 +1,395 @@
+// Meetings don't count, so the daemon reads the local .ics files in the `[calendar]` table of the
+// config (kept up to date by whatever syncs your calendars) to know when you're in one. A busy
+// event either pauses the running duration until it's over, or is flagged, letting the duration
+// run and subtracting the overlap when the session's completed. The first rule whose calendar and
+// keyword match an event says which, and `action` covers events no rule matches.
+mod ics;
+
+use crate::config::{expand_home, get_config, CalendarAction, CalendarConfig, CalendarRule};
+use chrono::{DateTime, Duration as ChronoDuration, Local, TimeZone, Utc};
+use std::fs::{metadata, read_to_string};
+use std::io::Error;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+// Files are only looked at (and re-read if they've changed) this often
+const CHECK_EVERY: Duration = Duration::from_secs(30);
+
+// An occurrence of a busy event, and what to do about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Busy {
+    pub calendar: String,
+    pub summary: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub action: CalendarAction,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    // A duration's running into an event it should be paused for
+    Pause(Busy),
+    // The event it was paused for is over
+    Resume(Busy),
+    // A duration's running into an event whose time will be subtracted
+    Flag(Busy),
+}
+
+struct File {
+    filepath: String,
+    modified: Option<SystemTime>,
+    calendar: ics::Calendar,
+}
+
+pub struct CalendarWatcher {
+    files: Vec<File>,
+    action: CalendarAction,
+    rules: Vec<CalendarRule>,
+    checked_at: Option<Instant>,
+    // Occurrences already paused for or flagged, so each is only acted on once
+    handled: Vec<Busy>,
+    paused_for: Option<Busy>,
+}
+
+impl CalendarWatcher {
+    pub fn new(config: CalendarConfig) -> CalendarWatcher {
+        CalendarWatcher {
+            files: config
+                .files
+                .iter()
+                .map(|filepath| File {
+                    filepath: expand_home(filepath),
+                    modified: None,
+                    calendar: ics::Calendar::default(),
+                })
+                .collect(),
+            action: config.action,
+            rules: config.rules,
+            checked_at: None,
+            handled: Vec::new(),
+            paused_for: None,
+        }
+    }
+
+    // Called every time around the daemon's loop
+    pub fn tick(&mut self, active: bool) -> Vec<Change> {
+        if self.files.is_empty()
+            || self
+                .checked_at
+                .is_some_and(|checked_at| checked_at.elapsed() < CHECK_EVERY)
+        {
+            return Vec::new();
+        }
+        self.checked_at = Some(Instant::now());
+
+        self.reload();
+        let now = Utc::now();
+        let ongoing = self.busy_between(now, now + ChronoDuration::seconds(1));
+        self.observe(active, now, ongoing)
+    }
+
+    // `ongoing` is what's on at `now`
+    fn observe(&mut self, active: bool, now: DateTime<Utc>, mut ongoing: Vec<Busy>) -> Vec<Change> {
+        let mut changes = Vec::new();
+        self.handled.retain(|busy| busy.end > now);
+        ongoing
+            .retain(|busy| busy.action != CalendarAction::Ignore && !self.handled.contains(busy));
+        // Pausing first, since there's nothing to flag once the duration's paused
+        ongoing.sort_by_key(|busy| busy.action != CalendarAction::Pause);
+
+        if let Some(paused_for) = self.paused_for.take() {
+            let next_is_a_pause = ongoing
+                .first()
+                .is_some_and(|next| next.action == CalendarAction::Pause);
+            if paused_for.end > now {
+                self.paused_for = Some(paused_for);
+            } else if !active && next_is_a_pause {
+                // Back to back meetings keep it paused
+                let next = ongoing.remove(0);
+                self.handled.push(next.clone());
+                self.paused_for = Some(next);
+            } else if !active {
+                changes.push(Change::Resume(paused_for));
+            }
+            // Otherwise it was started again during the event, so there's nothing to resume
+        }
+        if !active {
+            return changes;
+        }
+
+        for busy in ongoing {
+            self.handled.push(busy.clone());
+            match busy.action {
+                CalendarAction::Pause => {
+                    self.paused_for = Some(busy.clone());
+                    changes.push(Change::Pause(busy));
+                    break;
+                }
+                CalendarAction::Flag => changes.push(Change::Flag(busy)),
+                CalendarAction::Ignore => (),
+            }
+        }
+        changes
+    }
+
+    // Re-reads files that have changed. One that can't be read keeps what was last read from it.
+    fn reload(&mut self) {
+        for file in &mut self.files {
+            let modified = metadata(&file.filepath).and_then(|metadata| metadata.modified());
+            match modified {
+                Ok(modified) if file.modified == Some(modified) => (),
+                Ok(modified) => match read_to_string(&file.filepath) {
+                    Ok(text) => {
+                        file.calendar = ics::parse(&text);
+                        file.modified = Some(modified);
+                    }
+                    Err(e) => eprintln!("Error reading {}, {}", file.filepath, e),
+                },
+                Err(e) => eprintln!("Error reading {}, {}", file.filepath, e),
+            }
+        }
+    }
+
+    // Every busy occurrence that overlaps `from` to `to`, ignored or not, in order
+    pub fn busy_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Busy> {
+        let mut busy = Vec::new();
+        for file in &self.files {
+            let calendar = file.calendar.name.clone().unwrap_or_else(|| {
+                Path::new(&file.filepath)
+                    .file_stem()
+                    .map_or(file.filepath.clone(), |stem| {
+                        stem.to_string_lossy().to_string()
+                    })
+            });
+            for occurrence in file.calendar.occurrences(from, to) {
+                busy.push(Busy {
+                    action: self.action_for(&calendar, &occurrence.summary),
+                    calendar: calendar.clone(),
+                    summary: occurrence.summary,
+                    start: occurrence.start,
+                    end: occurrence.end,
+                });
+            }
+        }
+        busy.sort_by_key(|busy| busy.start);
+        busy
+    }
+
+    fn action_for(&self, calendar: &str, summary: &str) -> CalendarAction {
+        let summary = summary.to_lowercase();
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.calendar
+                    .as_ref()
+                    .is_none_or(|name| name.eq_ignore_ascii_case(calendar))
+                    && rule
+                        .keyword
+                        .as_ref()
+                        .is_none_or(|keyword| summary.contains(&keyword.to_lowercase()))
+            })
+            .map_or(self.action, |rule| rule.action)
+    }
+
+    // How much of each of the spans (a session's durations) flagged events overlap, and which events
+    pub fn flagged_overlap(
+        &mut self,
+        spans: &[(DateTime<Utc>, DateTime<Utc>)],
+    ) -> (Vec<i64>, Vec<Busy>) {
+        let from = spans.iter().map(|(begin_at, _)| *begin_at).min();
+        let to = spans.iter().map(|(_, end_at)| *end_at).max();
+        let (from, to) = match (from, to) {
+            (Some(from), Some(to)) if !self.files.is_empty() => (from, to),
+            _ => return (vec![0; spans.len()], Vec::new()),
+        };
+
+        self.reload();
+        let flagged: Vec<Busy> = self
+            .busy_between(from, to)
+            .into_iter()
+            .filter(|busy| busy.action == CalendarAction::Flag)
+            .filter(|busy| {
+                overlaps(spans, &[(busy.start, busy.end)])
+                    .iter()
+                    .any(|seconds| *seconds > 0)
+            })
+            .collect();
+        let events: Vec<(DateTime<Utc>, DateTime<Utc>)> =
+            flagged.iter().map(|busy| (busy.start, busy.end)).collect();
+        (overlaps(spans, &events), flagged)
+    }
+}
+
+// Seconds of each of the spans, which don't overlap each other, that events overlap. Events that
+// overlap each other only count once.
+fn overlaps(
+    spans: &[(DateTime<Utc>, DateTime<Utc>)],
+    events: &[(DateTime<Utc>, DateTime<Utc>)],
+) -> Vec<i64> {
+    let mut events = events.to_vec();
+    events.sort();
+    let mut merged: Vec<(DateTime<Utc>, DateTime<Utc>)> = Vec::new();
+    for (start, end) in events {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    spans
+        .iter()
+        .map(|(begin_at, end_at)| {
+            merged
+                .iter()
+                .map(|(start, end)| ((*end_at).min(*end) - (*begin_at).max(*start)).num_seconds())
+                .map(|seconds| seconds.max(0))
+                .sum()
+        })
+        .collect()
+}
+
+// Lists today's busy events and what's done about each, to check the rules by
+pub fn print_today() -> Result<(), Error> {
+    let mut watcher = CalendarWatcher::new(get_config()?.calendar);
+    if watcher.files.is_empty() {
+        println!("There are no calendar files in the config");
+        return Ok(());
+    }
+    watcher.reload();
+
+    let today = Local::now().date_naive();
+    let midnight = |date: chrono::NaiveDate| {
+        Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+    let busy = watcher.busy_between(midnight(today), midnight(today.succ_opt().unwrap()));
+    if busy.is_empty() {
+        println!("Nothing's on today");
+    }
+    for busy in busy {
+        let time = |at: DateTime<Utc>| at.with_timezone(&Local).format("%H:%M");
+        println!(
+            "{}-{}  {} ({}): {:?}",
+            time(busy.start),
+            time(busy.end),
+            busy.summary,
+            busy.calendar,
+            busy.action
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 6, 1, hour, minute, 0).unwrap()
+    }
+
+    fn busy(
+        summary: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        action: CalendarAction,
+    ) -> Busy {
+        Busy {
+            calendar: "work".to_string(),
+            summary: summary.to_string(),
+            start,
+            end,
+            action,
+        }
+    }
+
+    #[test]
+    fn subtracts_overlapping_events_once() {
+        let spans = [(at(9, 0), at(10, 0)), (at(10, 30), at(12, 0))];
+        let events = [
+            // 15 minutes of the first span
+            (at(9, 45), at(10, 15)),
+            // Half an hour of the second, overlapping each other
+            (at(11, 0), at(11, 20)),
+            (at(11, 10), at(11, 30)),
+            // Between spans
+            (at(10, 0), at(10, 30)),
+        ];
+
+        assert_eq!(overlaps(&spans, &events), [15 * 60, 30 * 60]);
+        assert_eq!(overlaps(&spans, &[]), [0, 0]);
+    }
+
+    #[test]
+    fn matches_rules_by_calendar_and_keyword() {
+        let rule = |calendar: Option<&str>, keyword: Option<&str>, action| CalendarRule {
+            calendar: calendar.map(str::to_string),
+            keyword: keyword.map(str::to_string),
+            action,
+        };
+        let watcher = CalendarWatcher::new(CalendarConfig {
+            files: Vec::new(),
+            action: CalendarAction::Flag,
+            rules: vec![
+                rule(None, Some("focus"), CalendarAction::Ignore),
+                rule(Some("Work"), Some("standup"), CalendarAction::Pause),
+                rule(Some("family"), None, CalendarAction::Ignore),
+            ],
+        });
+
+        assert_eq!(
+            watcher.action_for("work", "Daily Standup"),
+            CalendarAction::Pause
+        );
+        assert_eq!(
+            watcher.action_for("personal", "Daily Standup"),
+            CalendarAction::Flag
+        );
+        assert_eq!(
+            watcher.action_for("work", "Focus time"),
+            CalendarAction::Ignore
+        );
+        assert_eq!(
+            watcher.action_for("family", "Dentist"),
+            CalendarAction::Ignore
+        );
+    }
+
+    #[test]
+    fn pauses_once_per_event_and_resumes_after() {
+        let mut watcher = CalendarWatcher::new(CalendarConfig::default());
+        let pause = |summary, start, end| busy(summary, start, end, CalendarAction::Pause);
+        let standup = pause("Standup", at(9, 0), at(9, 15));
+        let review = pause("Review", at(9, 15), at(10, 0));
+        let one_on_one = pause("1:1", at(11, 0), at(11, 30));
+        let lunch = busy("Lunch", at(12, 0), at(13, 0), CalendarAction::Flag);
+
+        assert_eq!(
+            watcher.observe(true, at(9, 1), vec![standup.clone()]),
+            vec![Change::Pause(standup)]
+        );
+        // Straight into another meeting, so it stays paused
+        assert_eq!(watcher.observe(false, at(9, 15), vec![review.clone()]), []);
+        // Started again during the review, on purpose, so it's left going
+        assert_eq!(watcher.observe(true, at(9, 20), vec![review.clone()]), []);
+        assert_eq!(watcher.observe(true, at(10, 0), vec![]), []);
+
+        assert_eq!(
+            watcher.observe(true, at(11, 0), vec![one_on_one.clone()]),
+            vec![Change::Pause(one_on_one.clone())]
+        );
+        assert_eq!(
+            watcher.observe(false, at(11, 30), vec![]),
+            vec![Change::Resume(one_on_one)]
+        );
+
+        assert_eq!(
+            watcher.observe(true, at(12, 1), vec![lunch.clone()]),
+            vec![Change::Flag(lunch.clone())]
+        );
+        assert_eq!(watcher.observe(true, at(12, 2), vec![lunch]), []);
+    }
+}
@@ -0,0 +1,559 @@
+// Just enough of iCalendar (RFC 5545) to tell when someone's busy: a file's events, and when simple
+// recurrences (daily, weekly on some days, monthly on a date, yearly) happen again. There's no time
+// zone database to look TZIDs up in, so times with one are taken as local time. All-day events,
+// free ones (TRANSP:TRANSPARENT) and cancelled ones aren't busy. Recurrences with rules beyond
+// those (e.g. the second Tuesday of each month) only count their first occurrence.
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use std::collections::HashSet;
+
+// One time an event happens
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    pub summary: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct Calendar {
+    // X-WR-CALNAME, which most calendar apps write
+    pub name: Option<String>,
+    events: Vec<Event>,
+}
+
+#[derive(Debug)]
+struct Event {
+    uid: Option<String>,
+    summary: String,
+    // Local wall-clock times, so recurrences stay at the same time of day across DST changes
+    start: NaiveDateTime,
+    length: Duration,
+    busy: bool,
+    rule: Option<Rule>,
+    exceptions: Vec<NaiveDateTime>,
+    // Set on an occurrence of a recurring event that's been moved or changed
+    recurrence_id: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug)]
+struct Rule {
+    frequency: Frequency,
+    interval: i64,
+    count: Option<usize>,
+    until: Option<NaiveDateTime>,
+    days: Vec<Weekday>,
+}
+
+// Properties of an event as they're read
+#[derive(Debug, Default)]
+struct Draft {
+    uid: Option<String>,
+    summary: Option<String>,
+    start: Option<Time>,
+    end: Option<Time>,
+    duration: Option<Duration>,
+    free: bool,
+    cancelled: bool,
+    rule: Option<Rule>,
+    exceptions: Vec<NaiveDateTime>,
+    recurrence_id: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Time {
+    Date(NaiveDate),
+    Local(NaiveDateTime),
+}
+
+impl Time {
+    fn local(self) -> NaiveDateTime {
+        match self {
+            Time::Date(date) => date.and_time(NaiveTime::MIN),
+            Time::Local(local) => local,
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Calendar {
+    let mut calendar = Calendar::default();
+    let mut draft: Option<Draft> = None;
+    // How deep inside an event's own components (e.g. its alarms) we are
+    let mut depth = 0;
+
+    for line in unfold(text) {
+        let Property {
+            name,
+            params,
+            value,
+        } = match split_property(&line) {
+            Some(property) => property,
+            None => continue,
+        };
+
+        match (name.as_str(), value, &mut draft) {
+            ("BEGIN", "VEVENT", None) => draft = Some(Draft::default()),
+            ("BEGIN", _, Some(_)) => depth += 1,
+            ("END", "VEVENT", Some(_)) if depth == 0 => {
+                if let Some(event) = draft.take().and_then(Draft::finish) {
+                    calendar.events.push(event);
+                }
+            }
+            ("END", _, Some(_)) => depth -= 1,
+            ("X-WR-CALNAME", _, None) => calendar.name = Some(unescape(value)),
+            (_, _, Some(draft)) if depth == 0 => draft.set(&name, &params, value),
+            _ => (),
+        }
+    }
+
+    calendar
+}
+
+impl Draft {
+    fn set(&mut self, name: &str, params: &[(String, String)], value: &str) {
+        match name {
+            "UID" => self.uid = Some(value.to_string()),
+            "SUMMARY" => self.summary = Some(unescape(value)),
+            "DTSTART" => self.start = parse_time(params, value),
+            "DTEND" => self.end = parse_time(params, value),
+            "DURATION" => self.duration = parse_duration(value),
+            "TRANSP" => self.free = value == "TRANSPARENT",
+            "STATUS" => self.cancelled = value == "CANCELLED",
+            "RRULE" => self.rule = parse_rule(value),
+            "EXDATE" => self.exceptions.extend(
+                value
+                    .split(',')
+                    .filter_map(|value| parse_time(params, value))
+                    .map(Time::local),
+            ),
+            "RECURRENCE-ID" => self.recurrence_id = parse_time(params, value).map(Time::local),
+            _ => (),
+        }
+    }
+
+    fn finish(self) -> Option<Event> {
+        let start = self.start?;
+        let length = match (self.end, self.duration) {
+            (Some(end), _) => end.local() - start.local(),
+            (None, Some(duration)) => duration,
+            (None, None) => Duration::zero(),
+        };
+
+        Some(Event {
+            uid: self.uid,
+            summary: self.summary.unwrap_or_default(),
+            start: start.local(),
+            length,
+            busy: matches!(start, Time::Local(_))
+                && length > Duration::zero()
+                && !self.free
+                && !self.cancelled,
+            rule: self.rule,
+            exceptions: self.exceptions,
+            recurrence_id: self.recurrence_id,
+        })
+    }
+}
+
+impl Calendar {
+    // Busy occurrences that overlap `from` to `to`, in no particular order
+    pub fn occurrences(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Occurrence> {
+        // Occurrences that were moved or changed are replaced by their own events
+        let moved: HashSet<(Option<&str>, NaiveDateTime)> = self
+            .events
+            .iter()
+            .filter_map(|event| Some((event.uid.as_deref(), event.recurrence_id?)))
+            .collect();
+
+        let mut occurrences = Vec::new();
+        for event in self.events.iter().filter(|event| event.busy) {
+            let starts = match event.recurrence_id {
+                Some(_) => vec![event.start],
+                None => event.starts(to.with_timezone(&Local).naive_local()),
+            };
+            for start in starts {
+                if event.recurrence_id.is_none()
+                    && (event.exceptions.contains(&start)
+                        || moved.contains(&(event.uid.as_deref(), start)))
+                {
+                    continue;
+                }
+                // Times skipped by a DST change never happen
+                let start = match Local.from_local_datetime(&start).earliest() {
+                    Some(start) => start.with_timezone(&Utc),
+                    None => continue,
+                };
+                let end = start + event.length;
+                if start < to && end > from {
+                    occurrences.push(Occurrence {
+                        summary: event.summary.clone(),
+                        start,
+                        end,
+                    });
+                }
+            }
+        }
+        occurrences
+    }
+}
+
+impl Event {
+    // When it starts, up to `to`, going day by day from the first occurrence
+    fn starts(&self, to: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let rule = match &self.rule {
+            Some(rule) => rule,
+            None => return vec![self.start],
+        };
+        let first = self.start.date();
+        let mut starts = Vec::new();
+
+        let mut date = first;
+        while date.and_time(self.start.time()) <= to {
+            let start = date.and_time(self.start.time());
+            if rule.until.is_some_and(|until| start > until) {
+                break;
+            }
+            if rule.matches(first, date) {
+                starts.push(start);
+                if rule.count.is_some_and(|count| starts.len() >= count) {
+                    break;
+                }
+            }
+            date = match date.succ_opt() {
+                Some(date) => date,
+                None => break,
+            };
+        }
+        starts
+    }
+}
+
+impl Rule {
+    fn matches(&self, first: NaiveDate, date: NaiveDate) -> bool {
+        let on_day = |default: bool| match self.days.is_empty() {
+            true => default,
+            false => self.days.contains(&date.weekday()),
+        };
+        match self.frequency {
+            Frequency::Daily => (date - first).num_days() % self.interval == 0 && on_day(true),
+            Frequency::Weekly => {
+                // Weeks start on Monday
+                let monday = first - Duration::days(first.weekday().num_days_from_monday() as i64);
+                ((date - monday).num_days() / 7) % self.interval == 0
+                    && on_day(date.weekday() == first.weekday())
+            }
+            Frequency::Monthly => {
+                let months = (date.year() - first.year()) as i64 * 12 + date.month() as i64
+                    - first.month() as i64;
+                date.day() == first.day() && months % self.interval == 0
+            }
+            Frequency::Yearly => {
+                (date.month(), date.day()) == (first.month(), first.day())
+                    && (date.year() - first.year()) as i64 % self.interval == 0
+            }
+        }
+    }
+}
+
+// None for rules this doesn't understand
+fn parse_rule(value: &str) -> Option<Rule> {
+    let mut rule = Rule {
+        frequency: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        days: Vec::new(),
+    };
+    let mut frequency = None;
+
+    for part in value.split(';') {
+        let (key, value) = part.split_once('=')?;
+        match key {
+            "FREQ" => {
+                frequency = Some(match value {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return None,
+                })
+            }
+            "INTERVAL" => rule.interval = value.parse().ok().filter(|interval| *interval > 0)?,
+            "COUNT" => rule.count = Some(value.parse().ok()?),
+            // A date means the whole of that day
+            "UNTIL" => {
+                rule.until = Some(match parse_time(&[], value)? {
+                    Time::Date(date) => date.and_hms_opt(23, 59, 59)?,
+                    Time::Local(local) => local,
+                })
+            }
+            // Only plain days, not e.g. 2TU for the second Tuesday
+            "BYDAY" => {
+                rule.days = value
+                    .split(',')
+                    .map(parse_weekday)
+                    .collect::<Option<Vec<_>>>()?
+            }
+            "WKST" => (),
+            _ => return None,
+        }
+    }
+
+    rule.frequency = frequency?;
+    if !rule.days.is_empty() && matches!(rule.frequency, Frequency::Monthly | Frequency::Yearly) {
+        return None;
+    }
+    Some(rule)
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+// A date, or a date-time in UTC (ending in Z), floating or with a TZID, which are both local
+fn parse_time(params: &[(String, String)], value: &str) -> Option<Time> {
+    let is_date = params
+        .iter()
+        .any(|(name, value)| name == "VALUE" && value == "DATE");
+    if is_date || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(Time::Date);
+    }
+
+    match value.strip_suffix('Z') {
+        Some(utc) => {
+            let utc = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+            Some(Time::Local(
+                Utc.from_utc_datetime(&utc)
+                    .with_timezone(&Local)
+                    .naive_local(),
+            ))
+        }
+        None => NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(Time::Local),
+    }
+}
+
+// e.g. PT1H30M or P1D
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let value = value.strip_prefix('P')?;
+
+    let mut seconds = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => in_time = true,
+            _ => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                seconds += n * match (c, in_time) {
+                    ('W', false) => 7 * 24 * 60 * 60,
+                    ('D', false) => 24 * 60 * 60,
+                    ('H', true) => 60 * 60,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(Duration::seconds(sign * seconds))
+}
+
+// Long lines are split, with each continuation starting with a space or tab
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+// A content line, NAME;PARAM=VALUE;...:VALUE
+struct Property<'a> {
+    name: String,
+    params: Vec<(String, String)>,
+    value: &'a str,
+}
+
+// Quoted parameter values can have colons in them
+fn split_property(line: &str) -> Option<Property<'_>> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+
+    let mut parts = line[..colon].split(';');
+    let name = parts.next()?.to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| {
+            (
+                name.to_ascii_uppercase(),
+                value.trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: &line[colon + 1..],
+    })
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => (),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+X-WR-CALNAME:Work\r
+BEGIN:VEVENT\r
+UID:standup\r
+SUMMARY:Standup\\, daily\r
+DTSTART:20260601T090000Z\r
+DURATION:PT15M\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,WE,FR;COUNT=5\r
+EXDATE:20260603T090000Z\r
+BEGIN:VALARM\r
+DURATION:PT1H\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+RECURRENCE-ID:20260605T090000Z\r
+SUMMARY:Standup (moved)\r
+DTSTART:20260605T100000Z\r
+DTEND:20260605T101500Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:offsite\r
+SUMMARY:Offsite\r
+DTSTART;VALUE=DATE:20260602\r
+DTEND;VALUE=DATE:20260603\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:focus\r
+SUMMARY:Focus time\r
+DTSTART:20260602T130000Z\r
+DTEND:20260602T150000Z\r
+TRANSP:TRANSPARENT\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:review\r
+SUMMARY:Design review with a very long title that goes on and on past seventy\r
+  five bytes\r
+DTSTART:20260602T140000Z\r
+DTEND:20260602T150000Z\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    // Recurrences keep to the local time of day, so the times are in June, away from DST changes
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 6, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn finds_busy_occurrences_of_recurring_and_single_events() {
+        let calendar = parse(CALENDAR);
+        assert_eq!(calendar.name.as_deref(), Some("Work"));
+
+        let mut occurrences = calendar.occurrences(at(1, 0, 0), at(30, 0, 0));
+        occurrences.sort_by_key(|occurrence| occurrence.start);
+        let found: Vec<(&str, DateTime<Utc>, DateTime<Utc>)> = occurrences
+            .iter()
+            .map(|occurrence| {
+                (
+                    occurrence.summary.as_str(),
+                    occurrence.start,
+                    occurrence.end,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                ("Standup, daily", at(1, 9, 0), at(1, 9, 15)),
+                (
+                    "Design review with a very long title that goes on and on past seventy five bytes",
+                    at(2, 14, 0),
+                    at(2, 15, 0)
+                ),
+                // The 3rd was skipped and the 5th moved, and the count includes both
+                ("Standup (moved)", at(5, 10, 0), at(5, 10, 15)),
+                ("Standup, daily", at(8, 9, 0), at(8, 9, 15)),
+                ("Standup, daily", at(10, 9, 0), at(10, 9, 15)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_durations_and_rules() {
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(
+            parse_duration("P1DT15M"),
+            Some(Duration::minutes(24 * 60 + 15))
+        );
+        assert_eq!(parse_duration("-PT15M"), Some(Duration::minutes(-15)));
+        assert_eq!(parse_duration("1H"), None);
+
+        assert!(parse_rule("FREQ=DAILY;INTERVAL=2;UNTIL=20261231").is_some());
+        // The second Tuesday of each month isn't something this can work out
+        assert!(parse_rule("FREQ=MONTHLY;BYDAY=2TU").is_none());
+        assert!(parse_rule("FREQ=MONTHLY;BYSETPOS=-1;BYDAY=FR").is_none());
+        assert!(parse_rule("FREQ=HOURLY").is_none());
+    }
+}
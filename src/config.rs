@@ -0,0 +1,388 @@
+// Settings from `~/.productivity-timer/config.toml`. Everything has a default, so the file (and
+// any table in it) is optional.
+use dirs::home_dir;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub api: ApiConfig,
+    pub auth: AuthConfig,
+    pub backup: BackupConfig,
+    pub calendar: CalendarConfig,
+    pub git: GitConfig,
+    pub goals: GoalsConfig,
+    pub hooks: HooksConfig,
+    pub idle: IdleConfig,
+    pub import: ImportConfig,
+    pub issues: IssuesConfig,
+    pub metrics: MetricsConfig,
+    pub snapshots: SnapshotsConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    // The daemon only serves the API when this is on, and only on 127.0.0.1
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for ApiConfig {
+    fn default() -> ApiConfig {
+        ApiConfig {
+            enabled: false,
+            port: 7272,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    // How to get a token when there isn't one that works
+    pub flow: Flow,
+    // Where Google sends the browser back to; 0 picks any free port, which only clients of the
+    // Desktop app type allow
+    pub redirect_port: u16,
+}
+
+impl Default for AuthConfig {
+    fn default() -> AuthConfig {
+        AuthConfig {
+            flow: Flow::Browser,
+            redirect_port: 8080,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flow {
+    // Consent in a browser on this machine, which sends it back to a local listener
+    Browser,
+    // A code to enter on any other device, for when there's no browser here, e.g. over SSH
+    Device,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub backend: Backend,
+    // What the database is called wherever it's backed up to
+    pub filename: String,
+    // When the daemon backs up on its own
+    pub schedule: Schedule,
+    // Local time for nightly backups, as HH:MM
+    pub nightly_at: String,
+    // Encrypts backups with a passphrase before they leave this machine
+    pub encrypt: bool,
+    pub drive: DriveConfig,
+    pub directory: Option<DirectoryConfig>,
+    pub webdav: Option<WebdavConfig>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> BackupConfig {
+        BackupConfig {
+            backend: Backend::Drive,
+            filename: "time_gained".to_string(),
+            schedule: Schedule::Off,
+            nightly_at: "03:00".to_string(),
+            encrypt: false,
+            drive: DriveConfig::default(),
+            directory: None,
+            webdav: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Drive,
+    Directory,
+    Webdav,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Schedule {
+    Off,
+    // After each completed session
+    Session,
+    Nightly,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DriveConfig {
+    // The folder backups go in, made at the top of your Drive if it isn't there
+    pub folder: String,
+    pub mime_type: String,
+    // Asks Drive to keep every uploaded version instead of pruning them after 30 days
+    pub keep_revisions: bool,
+}
+
+impl Default for DriveConfig {
+    fn default() -> DriveConfig {
+        DriveConfig {
+            folder: "productivity-timer".to_string(),
+            mime_type: "application/x-sqlite3".to_string(),
+            keep_revisions: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DirectoryConfig {
+    // Any directory, e.g. a mounted network share or a folder another tool syncs
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebdavConfig {
+    // The collection backups go in, e.g. https://cloud.example.com/remote.php/dav/files/me/pt/
+    pub url: String,
+    pub username: Option<String>,
+    // Keep the config file private (chmod 600) if you put this in it
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CalendarConfig {
+    // Local .ics files, kept up to date by whatever syncs your calendars
+    pub files: Vec<String>,
+    // What to do about busy events no rule matches
+    pub action: CalendarAction,
+    // Tried in order; the first that matches an event says what to do about it
+    pub rules: Vec<CalendarRule>,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> CalendarConfig {
+        CalendarConfig {
+            files: Vec::new(),
+            action: CalendarAction::Flag,
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CalendarAction {
+    // Pauses the running duration until the event's over
+    Pause,
+    // Lets the duration run, and subtracts the overlap when the session's completed
+    Flag,
+    Ignore,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CalendarRule {
+    // The calendar's name (its X-WR-CALNAME, or else the file's name without .ics); any if unset
+    pub calendar: Option<String>,
+    // Found in the event's summary, ignoring case; any if unset
+    pub keyword: Option<String>,
+    pub action: CalendarAction,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GitConfig {
+    // Saves each duration with the repo and branch it was spent in
+    pub enabled: bool,
+    // The working copy to look at, e.g. "~/code/pt"; without one, it's the directory pt was run
+    // from when the duration started
+    pub directory: Option<String>,
+    // What the commit hook calls the time spent since the last commit
+    pub trailer: String,
+}
+
+impl Default for GitConfig {
+    fn default() -> GitConfig {
+        GitConfig {
+            enabled: false,
+            directory: None,
+            trailer: "Time-Spent".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GoalsConfig {
+    // Time to gain a day, across every session tag
+    pub daily_minutes: i64,
+}
+
+impl Default for GoalsConfig {
+    fn default() -> GoalsConfig {
+        // The readme's 4 hours of reading, writing or thinking a day
+        GoalsConfig { daily_minutes: 240 }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    // Hooks still running after this long are killed
+    pub timeout_seconds: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> HooksConfig {
+        HooksConfig {
+            timeout_seconds: 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    // Prints how long there's been no input, in milliseconds, e.g. xprintidle. Nothing's detected
+    // without one.
+    pub command: Option<String>,
+    pub threshold_minutes: u64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> IdleConfig {
+        IdleConfig {
+            command: None,
+            threshold_minutes: 10,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ImportConfig {
+    // Projects (or Timewarrior tags) from other trackers, and the session tags they become
+    pub session_tags: BTreeMap<String, String>,
+    pub csv: CsvImportConfig,
+}
+
+// Which columns of a CSV hold what. Start, and either end or duration, are required.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CsvImportConfig {
+    pub start: String,
+    pub end: Option<String>,
+    // As HH:MM:SS or seconds
+    pub duration: Option<String>,
+    pub session_tag: Option<String>,
+    pub tag: Option<String>,
+    // A chrono format for local times, e.g. "%d/%m/%Y %H:%M"; RFC 3339 if unset
+    pub time_format: Option<String>,
+    pub delimiter: char,
+}
+
+impl Default for CsvImportConfig {
+    fn default() -> CsvImportConfig {
+        CsvImportConfig {
+            start: "start".to_string(),
+            end: Some("end".to_string()),
+            duration: None,
+            session_tag: Some("session_tag".to_string()),
+            tag: Some("tag".to_string()),
+            time_format: None,
+            delimiter: ',',
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct IssuesConfig {
+    // Regexes for issue-tracker keys in tags and session notes, tried in order. A pattern's first
+    // group is the key if it has one, or else the whole match.
+    pub patterns: Vec<String>,
+}
+
+impl Default for IssuesConfig {
+    fn default() -> IssuesConfig {
+        // Jira's and Linear's, e.g. ABC-123
+        IssuesConfig {
+            patterns: vec![r"\b[A-Z][A-Z0-9]+-\d+\b".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    // Anything can scrape it, so think twice before listening beyond 127.0.0.1
+    pub listen: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> MetricsConfig {
+        MetricsConfig {
+            enabled: false,
+            listen: "127.0.0.1:9272".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SnapshotsConfig {
+    // Days, counting back from today, to keep the daemon's daily snapshot for
+    pub daily: usize,
+    // Weeks to keep the last daily snapshot of, once it's too old to be kept as a daily one
+    pub weekly: usize,
+    // Snapshots taken before restores, migrations and imports
+    pub others: usize,
+}
+
+impl Default for SnapshotsConfig {
+    fn default() -> SnapshotsConfig {
+        SnapshotsConfig {
+            daily: 7,
+            weekly: 4,
+            others: 10,
+        }
+    }
+}
+
+pub fn config_filepath() -> String {
+    home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer/config.toml"
+}
+
+pub fn get_config() -> Result<Config, Error> {
+    match read_to_string(config_filepath()) {
+        Ok(config) => toml::from_str(&config).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Problem reading {}: {}", config_filepath(), e),
+            )
+        }),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e),
+    }
+}
+
+// In seconds, falling back to the default when the config can't be read
+pub fn daily_goal() -> i64 {
+    get_config()
+        .map(|config| config.goals)
+        .unwrap_or_default()
+        .daily_minutes
+        * 60
+}
+
+// So paths in the config can start with `~/`
+pub fn expand_home(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => home_dir().unwrap().as_path().display().to_string() + "/" + rest,
+        None => path.to_string(),
+    }
+}
@@ -0,0 +1,261 @@
+// Where OAuth tokens and Google client credentials live, and where passphrases come from.
+// Everything is kept in the data dir, readable only by the user (0600), and nothing in here should
+// ever be printed.
+use crate::crypto;
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+use std::fs::{
+    create_dir_all, read, read_to_string, remove_file, rename, set_permissions, OpenOptions,
+};
+use std::io::{Error, ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Mutex;
+
+// Set these to use an encrypted token file or encrypted backups without being prompted, e.g. from
+// the daemon
+const TOKEN_PASSPHRASE_VAR: &str = "PT_TOKEN_PASSPHRASE";
+const BACKUP_PASSPHRASE_VAR: &str = "PT_BACKUP_PASSPHRASE";
+
+// So we only prompt once per run, even if the token is read and then refreshed
+static TOKEN_PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+static BACKUP_PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+    pub api_key: String,
+}
+
+// Where the client credentials came from, for `pt auth status`
+#[derive(Debug)]
+pub enum ClientCredentialsSource {
+    DataDir,
+    Env,
+    Missing,
+}
+
+fn filepath(filename: &str) -> String {
+    home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer/" + filename
+}
+
+// Writes to the side and renames into place, so a file is never briefly readable by others or
+// left half-written
+pub fn write_private(filepath: &str, contents: &[u8]) -> Result<(), Error> {
+    // Logging in can come before the daemon has ever made the data dir
+    if let Some(directory) = Path::new(filepath).parent() {
+        create_dir_all(directory)?;
+    }
+
+    let tmp_filepath = filepath.to_string() + ".tmp";
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp_filepath)?;
+    // mode() only applies when the file is created
+    set_permissions(&tmp_filepath, PermissionsExt::from_mode(0o600))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    rename(tmp_filepath, filepath)
+}
+
+pub fn get_client_credentials() -> Result<ClientCredentials, Error> {
+    match read_to_string(filepath("credentials")) {
+        Ok(credentials) => Ok(serde_json::from_str(&credentials)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => get_client_credentials_from_env(),
+        Err(e) => Err(e),
+    }
+}
+
+// Older setups kept these in a `.env` in whatever directory pt was run from
+fn get_client_credentials_from_env() -> Result<ClientCredentials, Error> {
+    dotenv::dotenv().ok();
+    let var = |name: &str| {
+        dotenv::var(name).map_err(|_| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("{} isn't set; run `pt auth login` to set it up", name),
+            )
+        })
+    };
+
+    Ok(ClientCredentials {
+        client_id: var("GOOGLE_CLIENT_ID")?,
+        client_secret: var("GOOGLE_CLIENT_SECRET")?,
+        api_key: var("API_KEY")?,
+    })
+}
+
+pub fn client_credentials_source() -> ClientCredentialsSource {
+    if read(filepath("credentials")).is_ok() {
+        ClientCredentialsSource::DataDir
+    } else if get_client_credentials_from_env().is_ok() {
+        ClientCredentialsSource::Env
+    } else {
+        ClientCredentialsSource::Missing
+    }
+}
+
+pub fn save_client_credentials(credentials: &ClientCredentials) -> Result<(), Error> {
+    write_private(
+        &filepath("credentials"),
+        serde_json::to_string_pretty(credentials)?.as_bytes(),
+    )
+}
+
+pub fn token_is_encrypted() -> Result<bool, Error> {
+    Ok(crypto::is_encrypted(&read(filepath("token"))?))
+}
+
+pub fn get_token() -> Result<String, Error> {
+    let token = read(filepath("token"))?;
+
+    let token = if crypto::is_encrypted(&token) {
+        crypto::decrypt(&token_passphrase()?, &token)?
+    } else {
+        token
+    };
+
+    String::from_utf8(token).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+pub fn save_token(token: &str, encrypt: bool) -> Result<(), Error> {
+    let token = if encrypt {
+        crypto::encrypt(&token_passphrase()?, token.as_bytes())?
+    } else {
+        token.as_bytes().to_vec()
+    };
+
+    write_private(&filepath("token"), &token)
+}
+
+pub fn delete_token() -> Result<(), Error> {
+    match remove_file(filepath("token")) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// What clients of the daemon's API send as a bearer token. Made the first time the API's served;
+// delete the file and restart the daemon to get a new one.
+pub fn api_token() -> Result<String, Error> {
+    match read_to_string(filepath("api-token")) {
+        Ok(token) => Ok(token.trim().to_string()),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let token = crypto::random_id();
+            write_private(&filepath("api-token"), token.as_bytes())?;
+            Ok(token)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn token_passphrase() -> Result<String, Error> {
+    passphrase(
+        &TOKEN_PASSPHRASE,
+        TOKEN_PASSPHRASE_VAR,
+        "Token passphrase: ",
+        false,
+    )
+}
+
+// Asks twice when `confirm` is set, since a mistyped passphrase on the way up would make the
+// backup impossible to restore
+pub fn backup_passphrase(confirm: bool) -> Result<String, Error> {
+    passphrase(
+        &BACKUP_PASSPHRASE,
+        BACKUP_PASSPHRASE_VAR,
+        "Backup passphrase: ",
+        confirm,
+    )
+}
+
+fn passphrase(
+    cache: &Mutex<Option<String>>,
+    var: &str,
+    prompt: &str,
+    confirm: bool,
+) -> Result<String, Error> {
+    let mut passphrase = cache.lock().unwrap();
+
+    if passphrase.is_none() {
+        *passphrase = match std::env::var(var) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                let value = rpassword::prompt_password(prompt)?;
+                if confirm && rpassword::prompt_password("Again: ")? != value {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "The passphrases don't match",
+                    ));
+                }
+                Some(value)
+            }
+        };
+    }
+
+    Ok(passphrase.clone().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{metadata, write};
+
+    fn mode(filepath: &str) -> u32 {
+        metadata(filepath).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn write_private_is_only_readable_by_the_user() {
+        let directory = std::env::temp_dir().join(format!("pt-private-{}", std::process::id()));
+        let filepath = directory.join("token").display().to_string();
+
+        write_private(&filepath, b"first").unwrap();
+        assert_eq!(mode(&filepath), 0o600);
+        assert!(!Path::new(&(filepath.clone() + ".tmp")).exists());
+
+        // An existing file, and a leftover from a write that died part way, are both replaced
+        set_permissions(&filepath, PermissionsExt::from_mode(0o644)).unwrap();
+        write(filepath.clone() + ".tmp", b"stale").unwrap();
+        set_permissions(filepath.clone() + ".tmp", PermissionsExt::from_mode(0o644)).unwrap();
+        write_private(&filepath, b"second").unwrap();
+        assert_eq!(mode(&filepath), 0o600);
+        assert_eq!(read(&filepath).unwrap(), b"second");
+        assert!(!Path::new(&(filepath + ".tmp")).exists());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    // HOME and the passphrase are process-wide, so the whole round trip is one test
+    #[test]
+    fn encrypted_token_round_trips_and_rejects_the_wrong_passphrase() {
+        let home = std::env::temp_dir().join(format!("pt-credentials-{}", std::process::id()));
+        let original_home = std::env::var_os("HOME");
+        std::env::set_var("HOME", &home);
+        std::env::set_var(TOKEN_PASSPHRASE_VAR, "correct horse");
+        *TOKEN_PASSPHRASE.lock().unwrap() = None;
+
+        save_token("{\"access_token\":\"secret\"}", true).unwrap();
+        assert!(token_is_encrypted().unwrap());
+        assert!(!String::from_utf8_lossy(&read(filepath("token")).unwrap()).contains("secret"));
+        assert_eq!(mode(&filepath("token")), 0o600);
+        assert_eq!(get_token().unwrap(), "{\"access_token\":\"secret\"}");
+
+        std::env::set_var(TOKEN_PASSPHRASE_VAR, "battery staple");
+        *TOKEN_PASSPHRASE.lock().unwrap() = None;
+        let error = get_token().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        *TOKEN_PASSPHRASE.lock().unwrap() = None;
+        std::env::remove_var(TOKEN_PASSPHRASE_VAR);
+        match original_home {
+            Some(original_home) => std::env::set_var("HOME", original_home),
+            None => std::env::remove_var("HOME"),
+        }
+        std::fs::remove_dir_all(home).unwrap();
+    }
+}
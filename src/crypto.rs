@@ -0,0 +1,85 @@
+// Passphrase-based authenticated encryption for anything we'd rather not leave lying around in the
+// clear. Encrypted data is laid out as:
+//
+//   magic (6 bytes) | version (1 byte) | salt (16 bytes) | nonce (24 bytes) | ciphertext + tag
+//
+// The key is derived from the passphrase and salt with Argon2id, and the ciphertext is
+// XChaCha20-Poly1305, so a wrong passphrase and a tampered file both fail decryption.
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::io::{Error, ErrorKind};
+
+const MAGIC: &[u8; 6] = b"PTENC\0";
+const VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + SALT_LENGTH + NONCE_LENGTH;
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+
+    let cipher = cipher(passphrase, &salt)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| Error::other("Problem encrypting"))?;
+
+    let mut data = Vec::with_capacity(HEADER_LENGTH + ciphertext.len());
+    data.extend_from_slice(MAGIC);
+    data.push(VERSION);
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+pub fn decrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+    if !is_encrypted(data) || data.len() < HEADER_LENGTH {
+        return Err(Error::new(ErrorKind::InvalidData, "Not encrypted by pt"));
+    }
+
+    let version = data[MAGIC.len()];
+    if version != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported encryption version {}", version),
+        ));
+    }
+
+    let salt_start = MAGIC.len() + 1;
+    let nonce_start = salt_start + SALT_LENGTH;
+    let salt = &data[salt_start..nonce_start];
+    let nonce = XNonce::from_slice(&data[nonce_start..HEADER_LENGTH]);
+
+    cipher(passphrase, salt)?
+        .decrypt(nonce, &data[HEADER_LENGTH..])
+        .map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "Couldn't decrypt; the passphrase is wrong or the data has been tampered with",
+            )
+        })
+}
+
+// 128 random bits as hex, for ids that have to be unique across machines
+pub fn random_id() -> String {
+    let mut id = [0u8; 16];
+    OsRng.fill_bytes(&mut id);
+    id.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, Error> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::other(format!("Problem deriving key: {}", e)))?;
+
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
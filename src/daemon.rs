@@ -1,13 +1,40 @@
-use crate::session::Session;
+use crate::api;
+use crate::backup::Scheduler;
+use crate::calendar::{CalendarWatcher, Change};
+use crate::config::{daily_goal, get_config, GitConfig};
+use crate::database;
+use crate::events;
+use crate::git;
+use crate::hooks;
+use crate::hooks::Event;
+use crate::idle::IdleWatcher;
+use crate::issues;
+use crate::metrics;
+use crate::session::{Session, Status};
+use crate::snapshots;
+use chrono::{Local, Utc};
 use daemonize::Daemonize;
 use dirs::home_dir;
 use regex::Regex;
-use std::fs::{create_dir, read_to_string, write, File, OpenOptions};
+use serde_json::{json, Value};
+use std::env::{current_dir, current_exe};
+use std::fs::{create_dir, read_to_string, rename, write, File, OpenOptions};
 use std::io::{Error, ErrorKind};
+use std::mem::replace;
 use std::path::Path;
-use std::process::exit;
+use std::process::{exit, Command as Process, Stdio};
+use std::sync::mpsc::{channel, Sender};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// How often subscribers get the status
+const TICK_EVERY: Duration = Duration::from_secs(1);
+
+// Long enough for the daemon to get around its loop, and to save a session
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long to wait for the daemon to pick up "e" and exit
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub fn init() {
     let working_directory =
@@ -28,85 +55,361 @@ pub fn init() {
     }
 }
 
+// What the daemon can be asked to do, through the in file or the API
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // Starts a duration with the tag, or pauses the running one. The directory is where it was
+    // started from, for the git context.
+    Toggle {
+        tag: Option<String>,
+        directory: Option<String>,
+    },
+    // Starts a duration with the tag, pausing the running one first
+    Switch {
+        tag: Option<String>,
+        directory: Option<String>,
+    },
+    Add(u64),
+    Subtract(u64),
+    // Completes the session under the session tag
+    Complete(Option<String>),
+    // Changes nothing; answered with the status like the rest
+    Status,
+}
+
+// A command from another thread, and where to send what came of it
+pub type Request = (Command, Sender<Value>);
+
+// Hands a command to the daemon's loop from another of its threads, and waits for what came of it.
+// None if the loop doesn't answer in time.
+pub fn ask(requests: &Sender<Request>, command: Command) -> Option<Value> {
+    let (reply, replied) = channel();
+    requests.send((command, reply)).ok()?;
+    replied.recv_timeout(REPLY_TIMEOUT).ok()
+}
+
 fn listen_for_durations() {
     let mut session = Session::new();
     let half_second = Duration::from_millis(500);
+    let mut snapshot_checked_on = None;
+    let mut backups = Scheduler::new();
+    // The API's reports count on durations having keys from the current patterns
+    if let Err(e) = issues::backfill() {
+        eprintln!("Error taking issue keys, {}", e);
+    }
+    let config = get_config().unwrap_or_else(|e| {
+        eprintln!("Error, {}", e);
+        Default::default()
+    });
+    let mut idle = IdleWatcher::new(
+        config.idle,
+        Duration::from_secs(config.hooks.timeout_seconds),
+    );
+    let git = config.git;
+    let mut calendar = CalendarWatcher::new(config.calendar);
+    // The tag and git context of a duration paused for an event, to start again once it's over
+    let mut resume_after_event = None;
+    let daily_goal = daily_goal();
+    // Time gained in sessions completed today, so the goal can be checked without the database
+    let mut completed_today = 0;
+    let mut goal_reached_on = None;
+    let mut ticked_at = Instant::now();
+    // The status is only written out when it's changed; set until a write works
+    let mut status_changed = true;
+
+    // `requests` is held onto so `received` never disconnects, even with the API off
+    let (requests, received) = channel::<Request>();
+    if config.api.enabled {
+        if let Err(e) = api::serve(config.api.port, requests.clone()) {
+            eprintln!("Error starting the API, {}", e);
+        }
+    }
+    if config.metrics.enabled {
+        if let Err(e) = metrics::serve(&config.metrics.listen, requests.clone()) {
+            eprintln!("Error serving metrics, {}", e);
+        }
+    }
 
     loop {
-        sleep(half_second);
+        // API requests are handled as they come in, rather than on the next tick
+        let request = received.recv_timeout(half_second).ok();
+
+        // Once a day, and when the daemon starts
+        let today = Local::now().date_naive();
+        if snapshot_checked_on != Some(today) {
+            if let Err(e) = snapshots::take_daily_if_due() {
+                eprintln!("Error taking the daily snapshot, {}", e);
+            }
+            completed_today = database::get_todays_time_gained().unwrap_or_else(|e| {
+                eprintln!("Error reading today's time gained, {}", e);
+                0
+            });
+            // Reached before the daemon started, e.g. before a restart or a restore, so the hook's
+            // already run
+            if daily_goal > 0 && completed_today >= daily_goal {
+                goal_reached_on = Some(today);
+            }
+            snapshot_checked_on = Some(today);
+        }
+        backups.tick();
 
         let input = read_from_in_file().unwrap();
-        match input.trim() {
+        let command = match input.trim() {
             "e" => exit(0),
             "c" => {
                 let tag = get_tag().unwrap();
-                session.tag = tag;
-                session.save_session();
-                session = Session::new();
                 reset_tag().unwrap();
+                Some(Command::Complete(tag))
             }
             "t" => {
-                match session.active {
-                    // TODO: figure out best way to take in flags for stuff like tags
-                    true => session.pause(),
-                    false => {
-                        // TODO: DRY/refactor this logic (in -a, too)
-                        let tag = get_tag().unwrap();
-                        match tag {
-                            Some(tag) => {
-                                session.record_time(Some(tag));
-                            }
-                            None => session.record_time(None),
-                        }
-                    }
-                }
+                // TODO: figure out best way to take in flags for stuff like tags
+                let tag = get_tag().unwrap();
                 reset_tag().unwrap();
+                let directory = get_directory().unwrap();
+                reset_directory().unwrap();
+                Some(Command::Toggle { tag, directory })
+            }
+            // Switches to a new tag, starting a duration for it whether or not one was running
+            "w" => {
+                let tag = get_tag().unwrap();
+                reset_tag().unwrap();
+                let directory = get_directory().unwrap();
+                reset_directory().unwrap();
+                Some(Command::Switch { tag, directory })
             }
             // TODO: deprecate, doublecheck unused
             "p" => {
                 let time_gained = session.analytics.get_time_gained_formatted();
                 println!("gained time: {:?}", time_gained);
+                None
             }
             "g" => {
                 let tag = get_tag().unwrap().unwrap();
                 let time_gained = session.get_tag_time_gained(tag);
                 println!("{:?}", time_gained);
                 reset_tag().unwrap();
+                None
             }
             "a" => {
                 let minutes_to_add: u64 = get_misc().unwrap().parse().unwrap();
-
-                // TODO: support tags
-                session.record_addition(minutes_to_add);
-
                 reset_misc().unwrap();
+                Some(Command::Add(minutes_to_add))
             }
             "s" => {
                 let minutes_to_subtract: u64 = get_misc().unwrap().parse().unwrap();
+                reset_misc().unwrap();
+                Some(Command::Subtract(minutes_to_subtract))
+            }
+            _ => None,
+        };
 
-                // TODO: support tags
-                session.record_subtraction(minutes_to_subtract);
+        let mut commands = Vec::new();
+        if let Some(command) = command {
+            commands.push((command, None));
+        }
+        if let Some((command, reply)) = request {
+            commands.push((command, Some(reply)));
+        }
+        for (command, reply) in commands {
+            if command != Command::Status {
+                // Whatever's asked for takes over from resuming after an event
+                resume_after_event = None;
+                status_changed = true;
+            }
+            let result = run(command, &mut session, &git, &mut calendar);
+            if let Some(completed) = &result {
+                completed_today += completed["time_gained"].as_i64().unwrap_or(0);
+                backups.session_completed();
+            }
+            if let Some(reply) = reply {
+                session.update_time_gained();
+                let response = result.unwrap_or_else(|| json!(session.status()));
+                // Whoever asked may have given up waiting
+                let _ = reply.send(response);
+            }
+        }
 
-                reset_misc().unwrap();
+        for change in calendar.tick(session.active) {
+            if !matches!(change, Change::Flag(_)) {
+                status_changed = true;
+            }
+            match change {
+                Change::Pause(busy) if session.active => {
+                    let seconds = pause_duration(&mut session);
+                    resume_after_event = session
+                        .last_duration()
+                        .map(|(tag, _)| (tag, session.last_context()));
+                    publish_status(
+                        "paused",
+                        &mut session,
+                        json!({ "duration_seconds": seconds, "calendar_event": busy.summary }),
+                    );
+                }
+                Change::Resume(busy) => {
+                    if let Some((tag, context)) = resume_after_event.take() {
+                        start_duration(&mut session, tag, context);
+                        publish_status(
+                            "started",
+                            &mut session,
+                            json!({ "calendar_event": busy.summary }),
+                        );
+                    }
+                }
+                Change::Flag(busy) => events::publish(
+                    "flagged",
+                    json!({
+                        "calendar": busy.calendar,
+                        "summary": busy.summary,
+                        "start": busy.start,
+                        "end": busy.end,
+                    }),
+                ),
+                _ => (),
             }
-            _ => (),
         }
 
         session.update_time_gained();
-        // TODO: figure out best strategy for updating time gained: file? -p running every few
-        // seconds? Cf i3bar/zsh and see what feels best
-        let time_gained = session.analytics.get_time_gained_formatted();
-        set_time_gained(time_gained).expect("Error writing to time-gained file");
+        let status = session.status();
+        if ticked_at.elapsed() >= TICK_EVERY {
+            events::publish("tick", json!(status));
+            ticked_at = Instant::now();
+        }
+
+        // Once a day, counting the running session
+        if daily_goal > 0
+            && goal_reached_on != Some(today)
+            && completed_today + status.time_gained >= daily_goal
+        {
+            hooks::run(
+                Event::GoalReached,
+                json!({
+                    "daily_goal": daily_goal,
+                    "time_gained_today": completed_today + status.time_gained,
+                }),
+            );
+            goal_reached_on = Some(today);
+        }
+        if let Some(idle_for) = idle.tick(status.active) {
+            hooks::run(
+                Event::IdleDetected,
+                json!({
+                    "idle_seconds": idle_for.as_secs(),
+                    "tag": status.tag,
+                    "current_duration": status.current_duration,
+                }),
+            );
+        }
+
+        if status_changed {
+            match set_status(&status) {
+                Ok(()) => status_changed = false,
+                Err(e) => eprintln!("Error writing the status, {}", e),
+            }
+        }
         reset_in_file().unwrap();
     }
 }
 
+// Returns the completed session's details when the command completes one
+fn run(
+    command: Command,
+    session: &mut Session,
+    git: &GitConfig,
+    calendar: &mut CalendarWatcher,
+) -> Option<Value> {
+    match command {
+        Command::Complete(tag) => {
+            // A running duration has no time gained until it's paused
+            if session.active {
+                pause_duration(session);
+            }
+            // Time in flagged events doesn't count
+            let (busy_by_duration, busy) = calendar.flagged_overlap(&session.spans());
+            session.deduct(&busy_by_duration);
+            let busy_seconds: i64 = busy_by_duration.iter().sum();
+            session.tag = tag;
+            session.update_time_gained();
+            let completed = json!({
+                "session_id": session.id,
+                "session_tag": session.tag,
+                "time_gained": session.status().time_gained,
+                "durations_count": session.durations_count(),
+                "busy_seconds": busy_seconds,
+                "busy_events": busy.iter().map(|busy| &busy.summary).collect::<Vec<_>>(),
+            });
+            replace(session, Session::new()).save_session();
+            hooks::run(Event::SessionCompleted, completed.clone());
+            events::publish("completed", completed.clone());
+            return Some(completed);
+        }
+        Command::Toggle { tag, directory } => match session.active {
+            true => {
+                let seconds = pause_duration(session);
+                publish_status("paused", session, json!({ "duration_seconds": seconds }));
+            }
+            false => {
+                start_duration(session, tag, git::resolve(git, directory.as_deref()));
+                publish_status("started", session, json!({}));
+            }
+        },
+        Command::Switch { tag, directory } => {
+            let previous_tag = match session.active {
+                true => {
+                    pause_duration(session);
+                    session.last_duration().and_then(|(tag, _)| tag)
+                }
+                false => None,
+            };
+            start_duration(session, tag, git::resolve(git, directory.as_deref()));
+            publish_status("switched", session, json!({ "previous_tag": previous_tag }));
+        }
+        // TODO: support tags
+        Command::Add(minutes) => session.record_addition(minutes),
+        Command::Subtract(minutes) => session.record_subtraction(minutes),
+        Command::Status => (),
+    }
+    None
+}
+
+// The session's status, with `details` (a JSON object) added to it
+fn publish_status(name: &'static str, session: &mut Session, details: Value) {
+    session.update_time_gained();
+    let mut data = json!(session.status());
+    if let (Some(data), Value::Object(details)) = (data.as_object_mut(), details) {
+        data.extend(details);
+    }
+    events::publish(name, data);
+}
+
+fn start_duration(session: &mut Session, tag: Option<String>, context: Option<git::Context>) {
+    session.record_time(tag.clone(), context.clone());
+    hooks::run(
+        Event::DurationStarted,
+        json!({
+            "session_id": session.id,
+            "tag": tag,
+            "repo": context.as_ref().map(|context| &context.repo),
+            "branch": context.as_ref().map(|context| &context.branch),
+        }),
+    );
+}
+
+// Returns how long the duration was, in seconds
+fn pause_duration(session: &mut Session) -> i64 {
+    session.pause();
+    let (tag, seconds) = session.last_duration().unwrap();
+    hooks::run(
+        Event::DurationPaused,
+        json!({ "session_id": session.id, "tag": tag, "duration_seconds": seconds }),
+    );
+    seconds
+}
+
 // TODO: convert to struct with a constuctor? Something like Files::new() and maybe Files::clean()
 fn create_files() -> Result<(File, File), Error> {
     let in_filepath = get_filepath("in")?;
     let out_filepath = get_filepath("out")?;
     let err_filepath = get_filepath("err")?;
-    let time_gained_filepath = get_filepath("time-gained")?;
     let durations_count_filepath = get_filepath("durations-count")?;
     let durations_avg_filepath = get_filepath("durations-average")?;
 
@@ -117,7 +420,6 @@ fn create_files() -> Result<(File, File), Error> {
     // We only need this created, not passed back. We won't use File for
     // the in-file below, but rather the &str constant in_file
     create_file(&in_filepath, false /*append*/);
-    create_file(&time_gained_filepath, false /*append*/);
     create_file(&durations_count_filepath, false /*append*/);
     create_file(&durations_avg_filepath, false /*append*/);
     // TODO: decide if I should clean outfile
@@ -152,6 +454,7 @@ fn create_file(file_name: &str, append: bool) -> File {
         .read(true)
         .write(true)
         .create(true)
+        .truncate(false)
         .open(file_name)
         .unwrap()
 }
@@ -163,34 +466,20 @@ fn read_from_in_file() -> Result<String, Error> {
 }
 
 // TODO: move to a utils mod
-pub fn format_instant_to_hhmmss(time_gained: Duration) -> String {
-    let seconds_raw = time_gained.as_secs() % 60;
-    let minutes_raw = (time_gained.as_secs() / 60) % 60;
-    let hours_raw = (time_gained.as_secs() / 60) / 60;
-
-    let seconds: String;
-    let minutes: String;
-    let hours: String;
-
-    if seconds_raw < 10 {
-        seconds = "0".to_owned() + &seconds_raw.to_string();
-    } else {
-        seconds = seconds_raw.to_string();
-    }
-
-    if minutes_raw < 10 {
-        minutes = "0".to_owned() + &minutes_raw.to_string();
+// Negative time (more subtracted than gained) is prefixed with a `-`, e.g. -00:05:00
+pub fn format_instant_to_hhmmss(time_gained: chrono::Duration) -> String {
+    let sign = if time_gained < chrono::Duration::zero() {
+        "-"
     } else {
-        minutes = minutes_raw.to_string();
-    }
+        ""
+    };
+    let total_seconds = time_gained.num_seconds().abs();
 
-    if hours_raw < 10 {
-        hours = "0".to_owned() + &hours_raw.to_string();
-    } else {
-        hours = hours_raw.to_string();
-    }
+    let seconds = total_seconds % 60;
+    let minutes = (total_seconds / 60) % 60;
+    let hours = (total_seconds / 60) / 60;
 
-    format!("{}:{}:{}", hours, minutes, seconds)
+    format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds)
 }
 
 // TODO: make into struct with methods for getting/resetting?
@@ -207,21 +496,44 @@ fn reset_tag() -> Result<(), Error> {
     Ok(())
 }
 
+fn reset_directory() -> Result<(), Error> {
+    let directory_filepath = get_filepath("directory")?;
+    write(directory_filepath, "").expect("Problem writing to directory file");
+    Ok(())
+}
+
 fn get_misc() -> Result<String, Error> {
     let misc_filepath = get_filepath("misc")?;
-    Ok(read_to_string(&misc_filepath)?)
+    read_to_string(&misc_filepath)
 }
 
 fn get_tag() -> Result<Option<String>, Error> {
     let tag_filepath = get_filepath("tag")?;
     let tag = read_to_string(&tag_filepath).unwrap();
-    if tag != "" {
+    if !tag.is_empty() {
         Ok(Some(tag))
     } else {
         Ok(None)
     }
 }
 
+// Missing until a duration's been started from the CLI or the interface
+fn get_directory() -> Result<Option<String>, Error> {
+    let directory_filepath = get_filepath("directory")?;
+    match read_to_string(&directory_filepath) {
+        Ok(directory) if !directory.is_empty() => Ok(Some(directory)),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Where pt's being run from, for the daemon to find the git context in
+fn set_directory() -> Result<(), Error> {
+    let directory_filepath = get_filepath("directory")?;
+    write(directory_filepath, current_dir()?.display().to_string())
+}
+
 pub fn add_minutes(minutes_to_add: String) -> Result<(), Error> {
     let in_filepath = get_filepath("in")?;
     let misc_filepath = get_filepath("misc")?;
@@ -286,9 +598,10 @@ fn get_filepath(filename: &str) -> Result<String, Error> {
         "misc" => Ok(working_directory + filename),
         "tag" => Ok(working_directory + filename),
         "timer.pid" => Ok(working_directory + filename),
-        "time-gained" => Ok(working_directory + filename),
         "durations-count" => Ok(working_directory + filename),
         "durations-average" => Ok(working_directory + filename),
+        "status" => Ok(working_directory + filename),
+        "directory" => Ok(working_directory + filename),
         _ => Err(Error::new(
             ErrorKind::InvalidInput,
             filename.to_string() + "is not a valid file name",
@@ -296,9 +609,20 @@ fn get_filepath(filename: &str) -> Result<String, Error> {
     }
 }
 
+pub fn switch_tag(tag: String) -> Result<(), Error> {
+    let in_filepath = get_filepath("in")?;
+    let tag_filepath = get_filepath("tag")?;
+
+    set_directory()?;
+    write(in_filepath, "w").expect("Error writing to in file");
+    write(tag_filepath, tag).expect("Error writing to tag file");
+    Ok(())
+}
+
 pub fn trigger_time(tag: Option<String>) -> Result<(), Error> {
     let filepath = get_filepath("in")?;
     let tag_filepath = get_filepath("tag")?;
+    set_directory()?;
     write(filepath, "t")?;
 
     match tag {
@@ -310,13 +634,76 @@ pub fn trigger_time(tag: Option<String>) -> Result<(), Error> {
     }
 }
 
+// The running session's, from the status the daemon wrote
 pub fn get_time_gained() -> Result<String, Error> {
-    let filepath = get_filepath("time-gained")?;
-    Ok(read_to_string(filepath)?)
+    let time_gained = get_status()?.map_or(0, |status| status.time_gained);
+    Ok(format_instant_to_hhmmss(chrono::Duration::seconds(
+        time_gained,
+    )))
+}
+
+pub fn is_running() -> bool {
+    let pid = match get_filepath("timer.pid").and_then(read_to_string) {
+        Ok(pid) => pid.trim().to_string(),
+        Err(_) => return false,
+    };
+
+    // Signal 0 only checks that the process exists
+    Process::new("kill")
+        .args(["-0", &pid])
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
 }
 
-fn set_time_gained(time_gained: String) -> Result<(), Error> {
-    let filepath = get_filepath("time-gained")?;
-    write(filepath, time_gained)?;
+// Asks the daemon to exit and waits for it to. Whatever session it had in memory is lost.
+pub fn stop() -> Result<(), Error> {
+    write(get_filepath("in")?, "e")?;
+
+    let started = Instant::now();
+    while is_running() {
+        if started.elapsed() > STOP_TIMEOUT {
+            return Err(Error::new(
+                ErrorKind::TimedOut,
+                "The daemon didn't stop; kill it with `kill $(cat ~/.productivity-timer/timer.pid)`",
+            ));
+        }
+        sleep(Duration::from_millis(100));
+    }
+
+    Ok(())
+}
+
+// Starts the daemon the same way `pt -d` does
+pub fn start() -> Result<(), Error> {
+    let status = Process::new(current_exe()?)
+        .arg("-d")
+        .stdout(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(Error::other("Problem starting the daemon"));
+    }
     Ok(())
 }
+
+// For other processes; the daemon's threads ask it instead. None when the daemon hasn't written a
+// status yet. It's written when something changes, so a running duration is counted up to now.
+pub fn get_status() -> Result<Option<Status>, Error> {
+    let filepath = get_filepath("status")?;
+    match read_to_string(filepath) {
+        Ok(status) => Ok(serde_json::from_str::<Status>(&status)
+            .ok()
+            .map(|status| status.caught_up_to(Utc::now().timestamp()))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn set_status(status: &Status) -> Result<(), Error> {
+    let filepath = get_filepath("status")?;
+    // Written to the side and renamed into place so readers never see half a status
+    let tmp_filepath = filepath.clone() + ".tmp";
+    write(&tmp_filepath, serde_json::to_string(status)?)?;
+    rename(tmp_filepath, filepath)
+}
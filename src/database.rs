@@ -1,52 +1,634 @@
-use crate::oauth::get_token;
+use crate::crypto;
+use crate::daemon::format_instant_to_hhmmss;
+use crate::git;
+use crate::snapshots;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use dirs::home_dir;
-use dotenv;
-use reqwest;
-use reqwest::header::{ACCEPT, CONTENT_TYPE};
-use rusqlite::{params, Connection, Result};
-use serde::Deserialize;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value;
+use rusqlite::{
+    named_params, params, Connection, DatabaseName, OpenFlags, OptionalExtension, Result,
+};
+use std::collections::HashSet;
 use std::fmt;
-use std::fs::{File, OpenOptions};
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::Path;
 
 // TODO better db name
 const DATABASE_NAME: &str = "time_gained";
-const DRIVE_FILE_URL: &str = "https://www.googleapis.com/drive/v3/files";
-// NB the trailing "/"
-const DRIVE_FILE_UPLOAD_URL: &str = "https://www.googleapis.com/upload/drive/v3/files/";
 
-fn database_filename() -> String {
+pub fn database_filename() -> String {
     let working_directory =
         home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer";
-    let filename = working_directory + "/" + &DATABASE_NAME.to_string();
-    filename
+    working_directory + "/" + DATABASE_NAME
 }
 
 fn connect_to_database() -> Result<Connection, rusqlite::Error> {
-    let database = database_filename();
-    Connection::open(database)
+    open_database(&database_filename(), true)
 }
 
-pub fn new_session() -> Result<u64> {
-    let conn = connect_to_database()?;
+// Any of our databases, brought up to the current schema. Only the local one is worth a snapshot
+// before it's migrated; the others are copies.
+fn open_database(filepath: &str, snapshot: bool) -> Result<Connection, rusqlite::Error> {
+    let conn = Connection::open(filepath)?;
+    register_time_functions(&conn)?;
+    register_sync_functions(&conn)?;
+    migrate(&conn, snapshot)?;
+    Ok(conn)
+}
+
+// Identifies this machine in the rows it writes, so rows from different machines can be told
+// apart when they're merged. Made the first time it's needed.
+pub fn device_id() -> String {
+    let filepath =
+        home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer/device-id";
+    match read_to_string(&filepath) {
+        Ok(device_id) => device_id.trim().to_string(),
+        Err(_) => {
+            let device_id = crypto::random_id();
+            if let Some(directory) = Path::new(&filepath).parent() {
+                create_dir_all(directory).expect("Problem making the data dir");
+            }
+            write(&filepath, &device_id).expect("Problem saving the device id");
+            device_id
+        }
+    }
+}
 
-    // TODO: move thesxe table creation queries to connect_to_database
+// Each entry moves the schema up one version; the current version lives in sqlite's
+// `user_version` pragma, so only the migrations a database hasn't seen yet are run
+const MIGRATIONS: &[&str] = &[
     // SQLite doesnt have a storage class set aside for storing dates and/or times. We can
     // use TEXT and the time fns will work with it (supposedly)
-    match conn.execute(
-        "CREATE TABLE IF NOT EXISTS sessions (
-            id                          INTEGER PRIMARY KEY,
-            total_time                  TEXT,
-            durations_count             INTEGER,
-            durations_avg               TEXT,
-            tag                         TEXT
-        )",
+    "CREATE TABLE IF NOT EXISTS sessions (
+        id                          INTEGER PRIMARY KEY,
+        total_time                  TEXT,
+        durations_count             INTEGER,
+        durations_avg               TEXT,
+        tag                         TEXT
+    );
+    CREATE TABLE IF NOT EXISTS tags (
+        id                          INTEGER PRIMARY KEY,
+        session_id                  INTEGER,
+        value                       TEXT,
+        time                        TEXT
+    );",
+    // Sessions from before this migration have no dates, so they only show up when viewing all
+    // time in the interface
+    "ALTER TABLE sessions ADD COLUMN created_at TEXT;
+    ALTER TABLE sessions ADD COLUMN completed_at TEXT;",
+    // Each row in tags is one duration; older ones have no timestamps and are left out of the
+    // calendar
+    "ALTER TABLE tags ADD COLUMN begin_at TEXT;
+    ALTER TABLE tags ADD COLUMN end_at TEXT;",
+    // Rows get ids that are the same on every machine, so copies of the database can be merged.
+    // Older rows' ids are made from their contents, so machines that share a history (e.g. one was
+    // restored from the other's backup) give them the same ones. Legacy rows have no device.
+    "ALTER TABLE sessions ADD COLUMN uuid TEXT;
+    ALTER TABLE sessions ADD COLUMN device_id TEXT;
+    ALTER TABLE sessions ADD COLUMN updated_at TEXT;
+    UPDATE sessions SET
+        uuid = legacy_uuid(id, created_at, completed_at, total_time, durations_count, tag),
+        updated_at = coalesce(completed_at, created_at);
+    CREATE UNIQUE INDEX sessions_uuid ON sessions (uuid);
+    ALTER TABLE tags ADD COLUMN uuid TEXT;
+    ALTER TABLE tags ADD COLUMN session_uuid TEXT;
+    ALTER TABLE tags ADD COLUMN device_id TEXT;
+    ALTER TABLE tags ADD COLUMN updated_at TEXT;
+    UPDATE tags SET
+        uuid = legacy_uuid(id, session_id, value, time, begin_at, end_at),
+        session_uuid = (SELECT uuid FROM sessions WHERE sessions.id = tags.session_id),
+        updated_at = coalesce(end_at, begin_at);
+    CREATE UNIQUE INDEX tags_uuid ON tags (uuid);
+    CREATE TABLE tombstones (
+        uuid                        TEXT PRIMARY KEY,
+        deleted_at                  TEXT NOT NULL,
+        device_id                   TEXT
+    );",
+    // Whatever you want to remember about a session
+    "ALTER TABLE sessions ADD COLUMN note TEXT;",
+    // The git repo and branch a duration was spent in, when the git context is on
+    "ALTER TABLE tags ADD COLUMN repo TEXT;
+    ALTER TABLE tags ADD COLUMN branch TEXT;",
+    // The issue-tracker key from a duration's tag or its session's note, e.g. ABC-123
+    "ALTER TABLE tags ADD COLUMN issue TEXT;",
+];
+
+// Timestamps are stored in UTC in the same format as sqlite's datetime('now'), so sqlite's date
+// fns (e.g. date(begin_at, 'localtime')) work on them
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format(TIMESTAMP_FORMAT).to_string()
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    let timestamp = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+    Some(Utc.from_utc_datetime(&timestamp))
+}
+
+fn migrate(conn: &Connection, snapshot: bool) -> Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    if snapshot && version < MIGRATIONS.len() && has_sessions(conn)? {
+        let filepath = snapshots::new_filepath("migration").map_err(snapshot_error)?;
+        conn.backup(DatabaseName::Main, filepath, None)?;
+        snapshots::prune().map_err(snapshot_error)?;
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            i + 1
+        ))?;
+    }
+
+    Ok(())
+}
+
+// Whether there's anything worth a snapshot. Databases from before the schema version was kept are
+// at version 0 like brand new ones, so it's their sessions that tell them apart.
+fn has_sessions(conn: &Connection) -> Result<bool> {
+    let has_table: bool = conn.query_row(
+        "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'sessions'",
         [],
-    ) {
-        Ok(..) => (),
-        Err(e) => panic!("error creating sessions table: {}", e),
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(false);
+    }
+    conn.query_row("SELECT EXISTS (SELECT 1 FROM sessions)", [], |row| {
+        row.get(0)
+    })
+}
+
+fn snapshot_error(e: std::io::Error) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_IOERR),
+        Some(format!("Problem taking a snapshot before migrating: {}", e)),
+    )
+}
+
+// Copies the database with sqlite's online backup API, which is safe even while the daemon is
+// writing to it
+pub fn copy_database(to: &str) -> Result<()> {
+    let conn = connect_to_database()?;
+    conn.backup(DatabaseName::Main, to, None)
+}
+
+#[derive(Debug)]
+pub struct DatabaseSummary {
+    // "ok", or whatever problems sqlite found
+    pub integrity: String,
+    pub schema_version: usize,
+    // Whether it has a sessions table at all, i.e. whether it's one of ours
+    pub has_sessions: bool,
+    pub session_tags: Vec<SessionTagSummary>,
+}
+
+impl DatabaseSummary {
+    pub fn is_newer_than_us(&self) -> bool {
+        self.schema_version > MIGRATIONS.len()
+    }
+}
+
+#[derive(Debug)]
+pub struct SessionTagSummary {
+    pub tag: Option<String>,
+    pub sessions: i64,
+    pub total_seconds: i64,
+}
+
+// Checks over any database file without changing it, e.g. a backup before it's restored
+pub fn summarize_database(filepath: &str) -> Result<DatabaseSummary> {
+    let conn = Connection::open_with_flags(filepath, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    register_time_functions(&conn)?;
+
+    let integrity: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    let schema_version = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let has_sessions = conn.query_row(
+        "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'sessions'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    let session_tags = if has_sessions {
+        conn.prepare(
+            "SELECT tag, count(*), coalesce(sum(seconds(total_time)), 0) FROM sessions
+            WHERE total_time IS NOT NULL GROUP BY tag ORDER BY tag",
+        )?
+        .query_map([], |row| {
+            Ok(SessionTagSummary {
+                tag: row.get(0)?,
+                sessions: row.get(1)?,
+                total_seconds: row.get(2)?,
+            })
+        })?
+        .collect::<Result<_>>()?
+    } else {
+        Vec::new()
+    };
+
+    Ok(DatabaseSummary {
+        integrity: integrity.join("; "),
+        schema_version,
+        has_sessions,
+        session_tags,
+    })
+}
+
+// Brings a copy of a database, e.g. one that's been downloaded, up to the current schema so it can
+// be merged
+pub fn migrate_database(filepath: &str) -> Result<()> {
+    open_database(filepath, false).map(|_| ())
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MergeSummary {
+    pub sessions_added: usize,
+    pub sessions_updated: usize,
+    pub durations_added: usize,
+    pub durations_updated: usize,
+    pub deleted: usize,
+}
+
+pub fn merge_into_local(filepath: &str) -> Result<MergeSummary> {
+    let conn = connect_to_database()?;
+    merge(&conn, filepath)
+}
+
+// Merges the sessions and durations of the database at `filepath`, which has to be at the current
+// schema, into this one by uuid. Rows only one side has are added. When both have a row, the one
+// with the later `updated_at` wins, and the one with the greater device id if those are the same,
+// so it doesn't matter which side merges into which. Deletions win over edits: a row either side
+// has a tombstone for is gone from both. Sessions still going on the other side are left until
+// they're completed.
+fn merge(conn: &Connection, filepath: &str) -> Result<MergeSummary> {
+    conn.execute("ATTACH DATABASE ?1 AS remote", [filepath])?;
+    let result = merge_attached(conn);
+    conn.execute("DETACH DATABASE remote", [])?;
+    result
+}
+
+fn merge_attached(conn: &Connection) -> Result<MergeSummary> {
+    let tx = conn.unchecked_transaction()?;
+    let mut summary = MergeSummary::default();
+
+    tx.execute(
+        "INSERT OR IGNORE INTO main.tombstones (uuid, deleted_at, device_id)
+        SELECT uuid, deleted_at, device_id FROM remote.tombstones",
+        [],
+    )?;
+    summary.deleted += tx.execute(
+        "DELETE FROM main.tags WHERE uuid IN (SELECT uuid FROM main.tombstones)",
+        [],
+    )?;
+    summary.deleted += tx.execute(
+        "DELETE FROM main.sessions WHERE uuid IN (SELECT uuid FROM main.tombstones)",
+        [],
+    )?;
+
+    const REMOTE_WINS: &str = "(coalesce(r.updated_at, ''), coalesce(r.device_id, ''))
+        > (coalesce(l.updated_at, ''), coalesce(l.device_id, ''))";
+
+    summary.sessions_updated = tx.execute(
+        &format!(
+            "UPDATE main.sessions AS l SET
+            (total_time, durations_count, durations_avg, tag, created_at, completed_at, note, device_id, updated_at) =
+            (SELECT r.total_time, r.durations_count, r.durations_avg, r.tag, r.created_at, r.completed_at, r.note, r.device_id, r.updated_at
+            FROM remote.sessions r WHERE r.uuid = l.uuid)
+            WHERE EXISTS (SELECT 1 FROM remote.sessions r WHERE r.uuid = l.uuid AND {})",
+            REMOTE_WINS
+        ),
+        [],
+    )?;
+    summary.sessions_added = tx.execute(
+        "INSERT INTO main.sessions
+        (total_time, durations_count, durations_avg, tag, created_at, completed_at, note, uuid, device_id, updated_at)
+        SELECT r.total_time, r.durations_count, r.durations_avg, r.tag, r.created_at, r.completed_at, r.note, r.uuid, r.device_id, r.updated_at
+        FROM remote.sessions r
+        WHERE r.total_time IS NOT NULL
+        AND NOT EXISTS (SELECT 1 FROM main.sessions l WHERE l.uuid = r.uuid)
+        AND NOT EXISTS (SELECT 1 FROM main.tombstones d WHERE d.uuid = r.uuid)
+        ORDER BY r.id",
+        [],
+    )?;
+
+    summary.durations_updated = tx.execute(
+        &format!(
+            "UPDATE main.tags AS l SET
+            (value, time, begin_at, end_at, repo, branch, issue, session_uuid, device_id, updated_at) =
+            (SELECT r.value, r.time, r.begin_at, r.end_at, r.repo, r.branch, r.issue, r.session_uuid, r.device_id, r.updated_at
+            FROM remote.tags r WHERE r.uuid = l.uuid)
+            WHERE EXISTS (SELECT 1 FROM remote.tags r WHERE r.uuid = l.uuid AND {})",
+            REMOTE_WINS
+        ),
+        [],
+    )?;
+    summary.durations_added = tx.execute(
+        "INSERT INTO main.tags
+        (session_id, value, time, begin_at, end_at, repo, branch, issue, uuid, session_uuid, device_id, updated_at)
+        SELECT NULL, r.value, r.time, r.begin_at, r.end_at, r.repo, r.branch, r.issue, r.uuid, r.session_uuid, r.device_id, r.updated_at
+        FROM remote.tags r
+        WHERE NOT EXISTS (SELECT 1 FROM main.tags l WHERE l.uuid = r.uuid)
+        AND NOT EXISTS (SELECT 1 FROM main.tombstones d WHERE d.uuid = r.uuid)
+        ORDER BY r.id",
+        [],
+    )?;
+
+    // Session ids are local to each database, so durations are tied to their sessions by uuid
+    tx.execute(
+        "UPDATE main.tags SET session_id = (SELECT id FROM main.sessions s WHERE s.uuid = tags.session_uuid)
+        WHERE session_uuid IS NOT NULL",
+        [],
+    )?;
+
+    tx.commit()?;
+    Ok(summary)
+}
+
+// A duration from somewhere other than the daemon, e.g. another time tracker
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewDuration {
+    pub tag: Option<String>,
+    pub issue: Option<String>,
+    pub begin_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+}
+
+// When every duration with timestamps began and ended, for telling whether one's already here
+pub fn get_duration_spans() -> Result<HashSet<(DateTime<Utc>, DateTime<Utc>)>> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT begin_at, end_at FROM tags WHERE begin_at IS NOT NULL AND end_at IS NOT NULL",
+    )?;
+    let spans = stmt
+        .query_map([], |row| {
+            let begin_at: String = row.get(0)?;
+            let end_at: String = row.get(1)?;
+            Ok((parse_timestamp(&begin_at), parse_timestamp(&end_at)))
+        })?
+        .map(Result::unwrap)
+        .filter_map(|(begin_at, end_at)| Some((begin_at?, end_at?)))
+        .collect();
+
+    Ok(spans)
+}
+
+// Saves each session tag and its durations as one completed session, all or nothing
+pub fn insert_sessions(sessions: &[(String, Vec<NewDuration>)]) -> Result<()> {
+    let mut conn = connect_to_database()?;
+    let tx = conn.transaction()?;
+    let device_id = device_id();
+
+    for (session_tag, durations) in sessions {
+        let seconds: Vec<i64> = durations
+            .iter()
+            .map(|duration| (duration.end_at - duration.begin_at).num_seconds())
+            .collect();
+        let total: i64 = seconds.iter().sum();
+        let count = durations.len() as i64;
+        let hhmmss = |seconds| format_instant_to_hhmmss(chrono::Duration::seconds(seconds));
+
+        let session_id: i64 = tx.query_row(
+            "INSERT INTO sessions (total_time, durations_count, durations_avg, tag, created_at, completed_at, uuid, device_id, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, lower(hex(randomblob(16))), ?7, datetime('now')) returning id",
+            params![
+                hhmmss(total),
+                count,
+                hhmmss(if count > 0 { total / count } else { 0 }),
+                session_tag,
+                durations.iter().map(|duration| duration.begin_at).min().map(format_timestamp),
+                durations.iter().map(|duration| duration.end_at).max().map(format_timestamp),
+                device_id
+            ],
+            |row| row.get(0),
+        )?;
+
+        for (duration, seconds) in durations.iter().zip(seconds) {
+            tx.execute(
+                "INSERT INTO tags (session_id, value, time, begin_at, end_at, issue, uuid, session_uuid, device_id, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, lower(hex(randomblob(16))), (SELECT uuid FROM sessions WHERE id = ?1), ?7, datetime('now'))",
+                params![
+                    session_id,
+                    duration.tag,
+                    hhmmss(seconds),
+                    format_timestamp(duration.begin_at),
+                    format_timestamp(duration.end_at),
+                    duration.issue,
+                    device_id
+                ],
+            )?;
+        }
+    }
+
+    tx.commit()
+}
+
+// Notes the most recently completed session if `session_id` is None. Returns the session noted, if
+// there is one.
+pub fn set_session_note(session_id: Option<u64>, note: &str) -> Result<Option<u64>> {
+    let conn = connect_to_database()?;
+
+    let session_id: Option<u64> = match session_id {
+        Some(session_id) => Some(session_id),
+        None => conn
+            .query_row(
+                "SELECT id FROM sessions WHERE total_time IS NOT NULL ORDER BY completed_at DESC, id DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?,
+    };
+    let session_id = match session_id {
+        Some(session_id) => session_id,
+        None => return Ok(None),
+    };
+
+    let updated = conn.execute(
+        "UPDATE sessions SET (note, device_id, updated_at) = (?1, ?2, datetime('now')) WHERE id = ?3",
+        params![note, device_id(), session_id],
+    )?;
+    Ok(Some(session_id).filter(|_| updated > 0))
+}
+
+// What a duration's issue key is taken from
+#[derive(Debug)]
+pub struct IssueSource {
+    pub id: i64,
+    pub tag: Option<String>,
+    pub note: Option<String>,
+}
+
+// For a completed session's durations
+pub fn get_issue_sources(session_id: u64) -> Result<Vec<IssueSource>> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.value, s.note FROM tags t JOIN sessions s ON s.id = t.session_id
+        WHERE s.total_time IS NOT NULL AND s.id = ?1",
+    )?;
+    let sources = stmt
+        .query_map([session_id], |row| {
+            Ok(IssueSource {
+                id: row.get(0)?,
+                tag: row.get(1)?,
+                note: row.get(2)?,
+            })
+        })?
+        .map(Result::unwrap)
+        .collect();
+
+    Ok(sources)
+}
+
+// Sets durations' issue keys by id, only touching the ones that change so syncing doesn't pick up
+// edits that weren't. Returns how many changed.
+pub fn set_issues(issues: &[(i64, Option<String>)]) -> Result<usize> {
+    let mut conn = connect_to_database()?;
+    let tx = conn.transaction()?;
+    let device_id = device_id();
+
+    let mut changed = 0;
+    for (id, issue) in issues {
+        changed += tx.execute(
+            "UPDATE tags SET (issue, device_id, updated_at) = (?1, ?2, datetime('now'))
+            WHERE id = ?3 AND issue IS NOT ?1",
+            params![issue, device_id, id],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(changed)
+}
+
+// Takes every completed duration's issue key again with `extract`, given its tag and its session's
+// note, e.g. once the patterns have changed. Returns how many changed.
+pub fn backfill_issues<F>(extract: F) -> Result<usize>
+where
+    F: Fn(Option<&str>, Option<&str>) -> Option<String>,
+{
+    let mut conn = connect_to_database()?;
+    fill_issues(&mut conn, &device_id(), extract)
+}
+
+fn fill_issues<F>(conn: &mut Connection, device_id: &str, extract: F) -> Result<usize>
+where
+    F: Fn(Option<&str>, Option<&str>) -> Option<String>,
+{
+    let tx = conn.transaction()?;
+
+    let issues: Vec<(i64, Option<String>)> = tx
+        .prepare(
+            "SELECT t.id, t.value, s.note FROM tags t JOIN sessions s ON s.id = t.session_id
+            WHERE s.total_time IS NOT NULL",
+        )?
+        .query_map([], |row| {
+            let tag: Option<String> = row.get(1)?;
+            let note: Option<String> = row.get(2)?;
+            Ok((row.get(0)?, extract(tag.as_deref(), note.as_deref())))
+        })?
+        .collect::<Result<_>>()?;
+
+    let mut changed = 0;
+    for (id, issue) in issues {
+        changed += tx.execute(
+            "UPDATE tags SET (issue, device_id, updated_at) = (?1, ?2, datetime('now'))
+            WHERE id = ?3 AND issue IS NOT ?1",
+            params![issue, device_id, id],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(changed)
+}
+
+// Removes a session and its durations, leaving tombstones so merging with another copy of the
+// database doesn't bring them back
+pub fn delete_session(session_id: u64) -> Result<usize> {
+    let mut conn = connect_to_database()?;
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "INSERT OR REPLACE INTO tombstones (uuid, deleted_at, device_id)
+        SELECT uuid, datetime('now'), ?2 FROM sessions WHERE id = ?1
+        UNION ALL SELECT uuid, datetime('now'), ?2 FROM tags WHERE session_id = ?1",
+        params![session_id, device_id()],
+    )?;
+    tx.execute("DELETE FROM tags WHERE session_id = ?1", [session_id])?;
+    let deleted = tx.execute("DELETE FROM sessions WHERE id = ?1", [session_id])?;
+
+    tx.commit()?;
+    Ok(deleted)
+}
+
+// Times are stored as (possibly negative) HH:MM:SS text, which sqlite's own time fns can't make
+// sense of once a session goes below zero or past 24 hours. These stand in for
+// `strftime('%s', time) - strftime('%s', '00:00:00')` and `time(seconds, 'unixepoch')`.
+fn register_time_functions(conn: &Connection) -> Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+
+    conn.create_scalar_function("seconds", 1, flags, |ctx| {
+        let time: Option<String> = ctx.get(0)?;
+        Ok(time.and_then(|time| parse_hhmmss(&time)))
+    })?;
+
+    conn.create_scalar_function("hhmmss", 1, flags, |ctx| {
+        let seconds: Option<i64> = ctx.get(0)?;
+        Ok(seconds.map(|seconds| format_instant_to_hhmmss(chrono::Duration::seconds(seconds))))
+    })?;
+
+    Ok(())
+}
+
+fn register_sync_functions(conn: &Connection) -> Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+
+    // FNV-1a over the arguments; only meant to tell rows apart, not to be hard to forge
+    conn.create_scalar_function("legacy_uuid", -1, flags, |ctx| {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for i in 0..ctx.len() {
+            let value: Value = ctx.get(i)?;
+            for byte in format!("{:?}\x1f", value).bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        Ok(format!("legacy-{:016x}", hash))
+    })?;
+
+    Ok(())
+}
+
+fn parse_hhmmss(time: &str) -> Option<i64> {
+    let (sign, time) = match time.strip_prefix('-') {
+        Some(time) => (-1, time),
+        None => (1, time),
     };
 
-    let mut stmt = conn.prepare("INSERT INTO sessions DEFAULT VALUES returning id")?;
+    let mut parts = time.split(':').map(|part| part.parse::<i64>().ok());
+    let hours = parts.next()??;
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(sign * (hours * 60 * 60 + minutes * 60 + seconds))
+}
+
+pub fn new_session() -> Result<u64> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO sessions (created_at, uuid, device_id, updated_at)
+        VALUES (datetime('now'), lower(hex(randomblob(16))), ?1, datetime('now')) returning id",
+    )?;
 
     struct Id {
         id: u64,
@@ -55,7 +637,7 @@ pub fn new_session() -> Result<u64> {
     // TODO: fix this query to not be a map--just a simple execute, but rough with rusqlite so
     // hacked into place
     let ids: Vec<Id> = stmt
-        .query_map([], |row| Ok(Id { id: row.get(0)? }))?
+        .query_map([device_id()], |row| Ok(Id { id: row.get(0)? }))?
         .map(Result::unwrap)
         .collect();
 
@@ -64,24 +646,32 @@ pub fn new_session() -> Result<u64> {
     Ok(id)
 }
 
-pub fn save_tag(session_id: u64, tag_value: String, time: String) -> Result<()> {
+// Durations started through the API without a tag are saved with a NULL one
+pub fn save_tag(
+    session_id: u64,
+    tag_value: Option<String>,
+    time: String,
+    begin_at: DateTime<Utc>,
+    end_at: Option<DateTime<Utc>>,
+    context: Option<&git::Context>,
+    issue: Option<String>,
+) -> Result<()> {
     let conn = connect_to_database()?;
-    match conn.execute(
-        "CREATE TABLE IF NOT EXISTS tags (
-            id                          INTEGER PRIMARY KEY,
-            session_id                  INTEGER,
-            value                       TEXT,
-            time                        TEXT
-        )",
-        [],
-    ) {
-        Ok(..) => (),
-        Err(e) => panic!("error creating sessions table: {}", e),
-    };
 
     match conn.execute(
-        "INSERT INTO tags (session_id, value, time) VALUES (?1, ?2, ?3)",
-        params![session_id, tag_value, time],
+        "INSERT INTO tags (session_id, value, time, begin_at, end_at, repo, branch, issue, uuid, session_uuid, device_id, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, lower(hex(randomblob(16))), (SELECT uuid FROM sessions WHERE id = ?1), ?9, datetime('now'))",
+        params![
+            session_id,
+            tag_value,
+            time,
+            format_timestamp(begin_at),
+            end_at.map(format_timestamp),
+            context.map(|context| &context.repo),
+            context.map(|context| &context.branch),
+            issue,
+            device_id()
+        ],
     ) {
         Ok(..) => (),
         Err(e) => panic!("error inserting into db: {:?}", e),
@@ -90,20 +680,6 @@ pub fn save_tag(session_id: u64, tag_value: String, time: String) -> Result<()>
     Ok(())
 }
 
-pub fn get_tag_time(tag_value: &String) -> Result<u32> {
-    let conn = connect_to_database()?;
-
-    let mut stmt = conn.prepare("SELECT sum(strftime('%s', time) - strftime('%s', '00:00:00')) FROM tags WHERE value = :tag_value")?;
-    let mut rows = stmt.query(&[(":tag_value", tag_value)])?;
-    let mut time: Option<u32> = None;
-
-    while let Some(row) = rows.next()? {
-        time = row.get(0)?;
-    }
-
-    Ok(time.unwrap())
-}
-
 pub fn save_session(
     time_gained: String,
     durations_count: u32,
@@ -113,8 +689,8 @@ pub fn save_session(
 ) -> Result<()> {
     let conn = connect_to_database()?;
     match conn.execute(
-        "UPDATE sessions SET (total_time, durations_count, durations_avg, tag) = (?1, ?2, ?3, ?4) WHERE id = ?5",
-        params![time_gained, durations_count, durations_avg, tag, session_id],
+        "UPDATE sessions SET (total_time, durations_count, durations_avg, tag, completed_at, device_id, updated_at) = (?1, ?2, ?3, ?4, datetime('now'), ?6, datetime('now')) WHERE id = ?5",
+        params![time_gained, durations_count, durations_avg, tag, session_id, device_id()],
     ) {
         Ok(..) => (),
         Err(e) =>panic!("error inserting into db: {:?}", e)
@@ -124,24 +700,22 @@ pub fn save_session(
 }
 
 // TODO: reconsider typing
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct TimeGained {
     id: i32,
-    total_time: i32,
+    total_time: i64,
     durations_count: i32,
     durations_avg: String,
 }
 
 pub fn get_times() -> Result<Vec<TimeGained>> {
-    let working_directory =
-        home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer";
-    let database = working_directory + "/" + &DATABASE_NAME.to_string();
-    let conn = Connection::open(database)?;
+    let conn = connect_to_database()?;
 
     // TODO: interpolate the database name to make it dynamic
-    // Use sqlite3's datetime fns to get total seconds
+    // seconds() is registered in connect_to_database; it handles negative totals
     let mut stmt =
-        conn.prepare("SELECT id, strftime('%s', total_time) - strftime('%s', '00:00:00'), durations_count, durations_avg FROM sessions WHERE total_time IS NOT NULL")?;
+        conn.prepare("SELECT id, seconds(total_time), durations_count, durations_avg FROM sessions WHERE total_time IS NOT NULL")?;
 
     let times: Vec<TimeGained> = stmt
         .query_map([], |row| {
@@ -176,15 +750,12 @@ impl fmt::Display for LifetimeOverview {
 }
 
 pub fn get_lifetime_overview(session_tag: &String) -> Result<Vec<LifetimeOverview>> {
-    let working_directory =
-        home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer";
-    let database = working_directory + "/" + &DATABASE_NAME.to_string();
-    let conn = Connection::open(database)?;
+    let conn = connect_to_database()?;
 
     // TODO: interpolate the database name to make it dynamic
-    // Use sqlite3's datetime fns to get total seconds
+    // seconds() is registered in connect_to_database; it handles negative totals
     let mut stmt =
-        conn.prepare("SELECT time(sum(strftime('%s', total_time) - strftime('%s', '00:00:00')) / count(total_time), 'unixepoch'), time(sum(strftime('%s', durations_avg) - strftime('%s', '00:00:00')) / count(durations_avg), 'unixepoch') FROM sessions WHERE tag = :tag")?;
+        conn.prepare("SELECT coalesce(hhmmss(sum(seconds(total_time)) / count(total_time)), '00:00:00'), coalesce(hhmmss(sum(seconds(durations_avg)) / count(durations_avg)), '00:00:00') FROM sessions WHERE tag = :tag")?;
 
     let times: Vec<LifetimeOverview> = stmt
         .query_map(&[(":tag", &session_tag)], |row| {
@@ -210,7 +781,7 @@ pub fn get_tags_pane(session_tag: &String) -> Result<String> {
     let conn = connect_to_database()?;
 
     let mut stmt =
-        conn.prepare("SELECT value, time(sum(strftime('%s', time) - strftime('%s', '00:00:00')), 'unixepoch') AS time FROM tags t JOIN sessions s ON s.id = t.session_id WHERE s.tag = :tag AND t.time IS NOT NULL AND t.value IS NOT NULL GROUP BY VALUE ORDER BY t.time DESC")?;
+        conn.prepare("SELECT value, hhmmss(sum(seconds(time))) AS time FROM tags t JOIN sessions s ON s.id = t.session_id WHERE s.tag = :tag AND t.time IS NOT NULL AND t.value IS NOT NULL GROUP BY VALUE ORDER BY t.time DESC")?;
 
     let tags: String = stmt
         .query_map(&[(":tag", &session_tag)], |row| {
@@ -225,149 +796,646 @@ pub fn get_tags_pane(session_tag: &String) -> Result<String> {
     Ok(tags)
 }
 
+pub fn get_session_tags_pane(session_id: u64) -> Result<String> {
+    let conn = connect_to_database()?;
+
+    let mut stmt =
+        conn.prepare("SELECT value, hhmmss(sum(seconds(time))) AS time FROM tags WHERE session_id = :session_id AND time IS NOT NULL AND value IS NOT NULL GROUP BY value ORDER BY sum(seconds(time)) DESC")?;
+
+    let tags: String = stmt
+        .query_map(&[(":session_id", &session_id)], |row| {
+            Ok(Tag {
+                value: row.get(0)?,
+                duration: row.get(1)?,
+            })
+        })?
+        .map(Result::unwrap)
+        .map(|t| format!("{} :: {}\n", t.value, t.duration))
+        .collect::<String>();
+    Ok(tags)
+}
+
+#[derive(Debug)]
+pub struct TagTime {
+    pub value: String,
+    pub seconds: i64,
+}
+
+// Time per tag across every session completed today, whatever its session tag
+pub fn get_todays_tags() -> Result<Vec<TagTime>> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT t.value, sum(seconds(t.time)) FROM tags t JOIN sessions s ON s.id = t.session_id
+        WHERE date(s.completed_at, 'localtime') = date('now', 'localtime')
+        AND t.time IS NOT NULL AND t.value IS NOT NULL
+        GROUP BY t.value",
+    )?;
+
+    let tags: Vec<TagTime> = stmt
+        .query_map([], |row| {
+            Ok(TagTime {
+                value: row.get(0)?,
+                seconds: row.get(1)?,
+            })
+        })?
+        .map(Result::unwrap)
+        .collect();
+
+    Ok(tags)
+}
+
+// Completed sessions and their time per session tag, for those completed on or after `since` (a
+// local date), or all of them
+pub fn get_completed_sessions(since: Option<NaiveDate>) -> Result<Vec<SessionTagSummary>> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT tag, count(*), coalesce(sum(seconds(total_time)), 0) FROM sessions
+        WHERE total_time IS NOT NULL
+        AND (?1 IS NULL OR date(completed_at, 'localtime') >= ?1)
+        GROUP BY tag ORDER BY tag",
+    )?;
+    let session_tags = stmt
+        .query_map(params![since.map(|since| since.to_string())], |row| {
+            Ok(SessionTagSummary {
+                tag: row.get(0)?,
+                sessions: row.get(1)?,
+                total_seconds: row.get(2)?,
+            })
+        })?
+        .collect::<Result<_>>()?;
+
+    Ok(session_tags)
+}
+
+// Seconds gained in sessions completed today (local time), across every session tag
+pub fn get_todays_time_gained() -> Result<i64> {
+    let conn = connect_to_database()?;
+
+    conn.query_row(
+        "SELECT coalesce(sum(seconds(total_time)), 0) FROM sessions
+        WHERE date(completed_at, 'localtime') = date('now', 'localtime')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+pub fn get_session_tags() -> Result<Vec<String>> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT tag FROM sessions WHERE tag IS NOT NULL AND total_time IS NOT NULL ORDER BY tag",
+    )?;
+
+    let session_tags: Vec<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .map(Result::unwrap)
+        .collect();
+
+    Ok(session_tags)
+}
+
 #[derive(Debug)]
 pub struct TotalTimeAsSeconds {
-    pub total_time: i32,
+    pub id: u64,
+    // Signed, since a session can lose more time than it gained
+    pub total_time: i64,
+    // UTC, as written by sqlite's datetime('now'); None for sessions saved before dates were
+    // recorded
+    pub completed_at: Option<String>,
 }
 
-pub fn get_total_time_as_seconds(session_tag: &String) -> Result<Vec<TotalTimeAsSeconds>> {
-    let working_directory =
-        home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer";
-    let database = working_directory + "/" + &DATABASE_NAME.to_string();
-    let conn = Connection::open(database)?;
+impl TotalTimeAsSeconds {
+    // The local date the session was completed on
+    pub fn completed_on(&self) -> Option<NaiveDate> {
+        let completed_at = parse_timestamp(self.completed_at.as_ref()?)?;
+        Some(completed_at.with_timezone(&Local).date_naive())
+    }
+}
+
+// `range` is a half-open [since, until) span of local dates; None means all sessions, including
+// those without a completion date
+pub fn get_total_time_as_seconds(
+    session_tag: &str,
+    range: Option<(NaiveDate, NaiveDate)>,
+) -> Result<Vec<TotalTimeAsSeconds>> {
+    let conn = connect_to_database()?;
 
     // TODO: interpolate the database name to make it dynamic
-    // Use sqlite3's datetime fns to get total seconds
-    let mut stmt = conn
-        .prepare("SELECT strftime('%s', total_time) - strftime('%s', '00:00:00') FROM sessions WHERE total_time IS NOT NULL AND tag = :tag")?;
+    // seconds() is registered in connect_to_database; it handles negative totals
+    let mut stmt = conn.prepare(
+        "SELECT id, seconds(total_time), completed_at FROM sessions
+        WHERE total_time IS NOT NULL AND tag = :tag
+        AND (:since IS NULL OR date(completed_at, 'localtime') >= :since)
+        AND (:until IS NULL OR date(completed_at, 'localtime') < :until)
+        ORDER BY id",
+    )?;
+
+    let (since, until) = range_params(range);
 
-    //let mut rows = stmt.query(&[(":tag_value", tag_value)])?;
     let total_times: Vec<TotalTimeAsSeconds> = stmt
-        .query_map(&[(":tag", &session_tag)], |row| {
-            Ok(TotalTimeAsSeconds {
-                total_time: row.get(0)?,
-            })
-        })?
+        .query_map(
+            named_params! { ":tag": session_tag, ":since": since, ":until": until },
+            |row| {
+                Ok(TotalTimeAsSeconds {
+                    id: row.get(0)?,
+                    total_time: row.get(1)?,
+                    completed_at: row.get(2)?,
+                })
+            },
+        )?
         .map(Result::unwrap)
         .collect();
 
     Ok(total_times)
 }
 
-#[derive(Deserialize, Debug)]
-struct DriveFile {
-    kind: String,
-    id: String,
-    name: String,
-    // TODO: not picking up?
-    #[allow(non_camel_case_types)]
-    mimeType: String,
-}
-
-#[derive(Deserialize, Debug)]
-struct FilesResponse {
-    kind: String,
-    // TODO: not picking up?
-    #[allow(non_camel_case_types)]
-    incompleteSearch: bool,
-    files: Vec<DriveFile>,
-}
-
-pub fn backup() -> Result<(), reqwest::Error> {
-    dotenv::dotenv().ok();
-    let api_key = dotenv::var("API_KEY").unwrap();
-    let token = get_token();
-
-    let client = reqwest::blocking::Client::new();
-
-    // TODO better error handling--need to figure out uniform error handling across app
-    let drive_database_file_id = &client
-        .get(DRIVE_FILE_URL.to_string() + "?key=" + &api_key)
-        // TODO figure out if I actually need this content-type
-        .header(ACCEPT, "application/json")
-        .bearer_auth(&token)
-        .send()?
-        .json::<FilesResponse>()
-        .unwrap()
-        .files[0]
-        // NB also has mimetype
-        .id;
-
-    let database_filename = database_filename();
-    let local_database_file = File::open(database_filename).unwrap();
-
-    let result = &client
-        // NB uploadType=media is good up to 5mb, which is ~416x the size of my current sqlite db;
-        // we'll worry about multipart uploads whenever we actually have to worry about them, but
-        // reqwest has an api for it
-        .patch(
-            DRIVE_FILE_UPLOAD_URL.to_string()
-                + drive_database_file_id
-                + "?key="
-                + &api_key
-                + "&uploadType=media",
-        )
-        .header(ACCEPT, "application/json")
-        .header(CONTENT_TYPE, "application/x-sqlite3")
-        .bearer_auth(token)
-        .body(local_database_file)
-        .send();
+#[derive(Debug)]
+pub struct SessionTagTime {
+    pub session_id: u64,
+    pub value: String,
+    pub seconds: i64,
+}
 
-    println!("result: {:?}", result);
+// Time per tag within each session, for the same sessions as get_total_time_as_seconds
+pub fn get_session_tag_times(
+    session_tag: &str,
+    range: Option<(NaiveDate, NaiveDate)>,
+) -> Result<Vec<SessionTagTime>> {
+    let conn = connect_to_database()?;
 
-    Ok(())
+    let mut stmt = conn.prepare(
+        "SELECT t.session_id, t.value, sum(seconds(t.time)) FROM tags t JOIN sessions s ON s.id = t.session_id
+        WHERE s.total_time IS NOT NULL AND s.tag = :tag
+        AND t.time IS NOT NULL AND t.value IS NOT NULL
+        AND (:since IS NULL OR date(s.completed_at, 'localtime') >= :since)
+        AND (:until IS NULL OR date(s.completed_at, 'localtime') < :until)
+        GROUP BY t.session_id, t.value
+        ORDER BY t.session_id, t.value",
+    )?;
+
+    let (since, until) = range_params(range);
+
+    let tag_times: Vec<SessionTagTime> = stmt
+        .query_map(
+            named_params! { ":tag": session_tag, ":since": since, ":until": until },
+            |row| {
+                Ok(SessionTagTime {
+                    session_id: row.get(0)?,
+                    value: row.get(1)?,
+                    seconds: row.get(2)?,
+                })
+            },
+        )?
+        .map(Result::unwrap)
+        .collect();
+
+    Ok(tag_times)
+}
+
+#[derive(Debug)]
+pub struct DailyTime {
+    pub date: NaiveDate,
+    pub seconds: i64,
+}
+
+// Time gained per local day since the given date, going by when each duration began
+pub fn get_daily_time(session_tag: &str, since: NaiveDate) -> Result<Vec<DailyTime>> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT date(t.begin_at, 'localtime') AS day, sum(seconds(t.time)) FROM tags t JOIN sessions s ON s.id = t.session_id
+        WHERE s.tag = :tag AND t.begin_at IS NOT NULL AND t.time IS NOT NULL
+        AND date(t.begin_at, 'localtime') >= :since
+        GROUP BY day",
+    )?;
+
+    let daily_times: Vec<DailyTime> = stmt
+        .query_map(
+            named_params! { ":tag": session_tag, ":since": since.to_string() },
+            |row| {
+                let date: String = row.get(0)?;
+                Ok(DailyTime {
+                    date: NaiveDate::parse_from_str(&date, "%Y-%m-%d").unwrap(),
+                    seconds: row.get(1)?,
+                })
+            },
+        )?
+        .map(Result::unwrap)
+        .collect();
+
+    Ok(daily_times)
+}
+
+// A duration as saved in the tags table, with when it began and ended
+#[derive(Debug)]
+pub struct TimestampedDuration {
+    pub tag: Option<String>,
+    pub seconds: i64,
+    pub begin_at: DateTime<Utc>,
+    pub end_at: Option<DateTime<Utc>>,
+}
+
+// Durations that began within the half-open [since, until) span of local dates, oldest first
+pub fn get_durations(
+    session_tag: &str,
+    range: Option<(NaiveDate, NaiveDate)>,
+) -> Result<Vec<TimestampedDuration>> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT t.value, seconds(t.time), t.begin_at, t.end_at FROM tags t JOIN sessions s ON s.id = t.session_id
+        WHERE s.tag = :tag AND t.begin_at IS NOT NULL AND t.time IS NOT NULL
+        AND (:since IS NULL OR date(t.begin_at, 'localtime') >= :since)
+        AND (:until IS NULL OR date(t.begin_at, 'localtime') < :until)
+        ORDER BY t.begin_at",
+    )?;
+
+    let (since, until) = range_params(range);
+
+    let durations: Vec<TimestampedDuration> = stmt
+        .query_map(
+            named_params! { ":tag": session_tag, ":since": since, ":until": until },
+            |row| {
+                let begin_at: String = row.get(2)?;
+                let end_at: Option<String> = row.get(3)?;
+                Ok(TimestampedDuration {
+                    tag: row.get(0)?,
+                    seconds: row.get(1)?,
+                    begin_at: parse_timestamp(&begin_at).unwrap(),
+                    end_at: end_at.as_deref().and_then(parse_timestamp),
+                })
+            },
+        )?
+        .map(Result::unwrap)
+        .collect();
+
+    Ok(durations)
+}
+
+// A duration along with the session it belongs to
+#[derive(Debug)]
+pub struct SessionDuration {
+    pub uuid: String,
+    pub session_id: u64,
+    pub session_tag: Option<String>,
+    pub session_note: Option<String>,
+    pub tag: Option<String>,
+    pub seconds: i64,
+    // Durations from before these were recorded have neither
+    pub begin_at: Option<DateTime<Utc>>,
+    pub end_at: Option<DateTime<Utc>>,
+    // Only recorded with the git context on
+    pub repo: Option<String>,
+    pub branch: Option<String>,
+    pub issue: Option<String>,
+}
+
+// Every duration of every completed session, oldest first, optionally only those with the given
+// session tag or that began on or after `since` and before `until` (local dates). Durations with no
+// timestamps are only included when there are no dates to go by.
+pub fn get_session_durations(
+    session_tag: Option<&str>,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<Vec<SessionDuration>> {
+    let conn = connect_to_database()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT t.uuid, s.id, s.tag, t.value, seconds(t.time), t.begin_at, t.end_at, s.note, t.repo, t.branch, t.issue FROM tags t JOIN sessions s ON s.id = t.session_id
+        WHERE s.total_time IS NOT NULL AND t.time IS NOT NULL
+        AND (:tag IS NULL OR s.tag = :tag)
+        AND (:since IS NULL OR date(t.begin_at, 'localtime') >= :since)
+        AND (:until IS NULL OR date(t.begin_at, 'localtime') < :until)
+        ORDER BY t.begin_at, t.id",
+    )?;
+
+    let durations: Vec<SessionDuration> = stmt
+        .query_map(
+            named_params! {
+                ":tag": session_tag,
+                ":since": since.map(|since| since.to_string()),
+                ":until": until.map(|until| until.to_string()),
+            },
+            |row| {
+                let begin_at: Option<String> = row.get(5)?;
+                let end_at: Option<String> = row.get(6)?;
+                Ok(SessionDuration {
+                    uuid: row.get(0)?,
+                    session_id: row.get(1)?,
+                    session_tag: row.get(2)?,
+                    session_note: row.get(7)?,
+                    tag: row.get(3)?,
+                    seconds: row.get(4)?,
+                    begin_at: begin_at.as_deref().and_then(parse_timestamp),
+                    end_at: end_at.as_deref().and_then(parse_timestamp),
+                    repo: row.get(8)?,
+                    branch: row.get(9)?,
+                    issue: row.get(10)?,
+                })
+            },
+        )?
+        .map(Result::unwrap)
+        .collect();
+
+    Ok(durations)
+}
+
+fn range_params(range: Option<(NaiveDate, NaiveDate)>) -> (Option<String>, Option<String>) {
+    match range {
+        Some((since, until)) => (Some(since.to_string()), Some(until.to_string())),
+        None => (None, None),
+    }
 }
 
-pub fn sync_local_to_remote() -> Result<(), reqwest::Error> {
-    // TODO figure out how to move this to its own fn or the top of the file, not per fn
-    dotenv::dotenv().ok();
-    let api_key = dotenv::var("API_KEY").unwrap();
-    let token = get_token();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issues;
+    use std::fs::remove_file;
 
-    let client = reqwest::blocking::Client::new();
+    fn database(name: &str) -> (Connection, String) {
+        let filepath = std::env::temp_dir()
+            .join(format!("pt-merge-{}-{}", name, std::process::id()))
+            .display()
+            .to_string();
+        let _ = remove_file(&filepath);
+        (open_database(&filepath, false).unwrap(), filepath)
+    }
+
+    fn add_session(conn: &Connection, uuid: &str, device_id: &str, tag: &str, updated_at: &str) {
+        conn.execute(
+            "INSERT INTO sessions (total_time, durations_count, tag, uuid, device_id, updated_at)
+            VALUES ('00:10:00', 1, ?1, ?2, ?3, ?4)",
+            params![tag, uuid, device_id, updated_at],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO tags (session_id, value, time, uuid, session_uuid, device_id, updated_at)
+            VALUES ((SELECT id FROM sessions WHERE uuid = ?1), 'writing', '00:10:00', ?1 || '-tag', ?1, ?2, ?3)",
+            params![uuid, device_id, updated_at],
+        )
+        .unwrap();
+    }
 
-    // TODO better error handling--need to figure out uniform error handling across app
-    let drive_database_file_id = &client
-        .get(DRIVE_FILE_URL.to_string() + "?key=" + &api_key)
-        // TODO figure out if I actually need this content-type
-        .header(ACCEPT, "application/json")
-        .bearer_auth(&token)
-        .send()?
-        .json::<FilesResponse>()
+    // Sessions by uuid, with their tag and the uuid of each of their durations
+    fn contents(conn: &Connection) -> Vec<(String, String, String)> {
+        conn.prepare(
+            "SELECT s.uuid, s.tag, t.uuid FROM sessions s JOIN tags t ON t.session_id = s.id ORDER BY s.uuid",
+        )
         .unwrap()
-        .files[0]
-        .id;
-
-    // left off here
-    // un-wippify file creation and where it saves
-    // also, make a mechanism for saving the last 2-3 dbs so we don't totally fuck ourselves
-
-    let database_filepath = home_dir().unwrap().as_path().display().to_string()
-        + "/.productivity-timer"
-        + "/test-db-backup";
-
-    let mut file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(database_filepath)
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+    }
+
+    #[test]
+    fn databases_from_before_schema_versions_have_sessions_to_keep() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(!has_sessions(&conn).unwrap());
+
+        // What pt made before migrations were tracked, still at user_version 0
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        assert!(!has_sessions(&conn).unwrap());
+        conn.execute(
+            "INSERT INTO sessions (total_time, durations_count, durations_avg, tag)
+            VALUES ('00:25:00', 1, '00:25:00', 'pt')",
+            [],
+        )
         .unwrap();
+        assert!(has_sessions(&conn).unwrap());
+    }
+
+    #[test]
+    fn times_can_be_negative() {
+        assert_eq!(parse_hhmmss("01:02:03"), Some(3723));
+        assert_eq!(parse_hhmmss("-00:05:00"), Some(-300));
+        // Past a day, which sqlite's time fns would wrap
+        assert_eq!(parse_hhmmss("25:00:00"), Some(90000));
+        assert_eq!(parse_hhmmss("-00:05"), None);
+        assert_eq!(parse_hhmmss("00:05:00:00"), None);
+
+        let conn = Connection::open_in_memory().unwrap();
+        register_time_functions(&conn).unwrap();
+        let (total, formatted): (i64, String) = conn
+            .query_row(
+                "SELECT seconds('-00:05:00') + seconds('00:01:30'), hhmmss(-210)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(total, -210);
+        assert_eq!(formatted, "-00:03:30");
+    }
+
+    #[test]
+    fn merging_both_ways_converges() {
+        let (laptop, laptop_filepath) = database("laptop");
+        let (desktop, desktop_filepath) = database("desktop");
+
+        add_session(&laptop, "shared", "laptop", "pt", "2026-10-01 10:00:00");
+        add_session(&desktop, "shared", "desktop", "pt", "2026-10-01 10:00:00");
+        add_session(
+            &laptop,
+            "from-laptop",
+            "laptop",
+            "pt",
+            "2026-10-02 10:00:00",
+        );
+        add_session(
+            &desktop,
+            "from-desktop",
+            "desktop",
+            "pt",
+            "2026-10-03 10:00:00",
+        );
+        // Edited on the laptop after the desktop's copy was last touched
+        add_session(
+            &desktop,
+            "edited",
+            "desktop",
+            "before",
+            "2026-10-04 10:00:00",
+        );
+        add_session(&laptop, "edited", "laptop", "after", "2026-10-05 10:00:00");
 
-    let result = &client
-        .get(
-            DRIVE_FILE_URL.to_string()
-                + "/"
-                + drive_database_file_id
-                + "?key="
-                + &api_key
-                + "&alt=media",
+        let summary = merge(&laptop, &desktop_filepath).unwrap();
+        assert_eq!(summary.sessions_added, 1);
+        // The same time on both sides, so the greater device id wins
+        assert_eq!(summary.sessions_updated, 0);
+        let summary = merge(&desktop, &laptop_filepath).unwrap();
+        assert_eq!(summary.sessions_added, 1);
+        assert_eq!(summary.sessions_updated, 2);
+        assert_eq!(summary.durations_updated, 2);
+
+        assert_eq!(contents(&laptop), contents(&desktop));
+        assert_eq!(
+            contents(&laptop),
+            vec![
+                (
+                    "edited".to_string(),
+                    "after".to_string(),
+                    "edited-tag".to_string()
+                ),
+                (
+                    "from-desktop".to_string(),
+                    "pt".to_string(),
+                    "from-desktop-tag".to_string()
+                ),
+                (
+                    "from-laptop".to_string(),
+                    "pt".to_string(),
+                    "from-laptop-tag".to_string()
+                ),
+                (
+                    "shared".to_string(),
+                    "pt".to_string(),
+                    "shared-tag".to_string()
+                ),
+            ]
+        );
+
+        // Merging again changes nothing
+        assert_eq!(
+            merge(&laptop, &desktop_filepath).unwrap(),
+            MergeSummary::default()
+        );
+
+        remove_file(laptop_filepath).unwrap();
+        remove_file(desktop_filepath).unwrap();
+    }
+
+    #[test]
+    fn deletions_are_merged_as_tombstones() {
+        let (laptop, laptop_filepath) = database("laptop-deleting");
+        let (desktop, desktop_filepath) = database("desktop-deleting");
+
+        add_session(&laptop, "kept", "laptop", "pt", "2026-10-01 10:00:00");
+        add_session(&laptop, "deleted", "laptop", "pt", "2026-10-01 10:00:00");
+        merge(&desktop, &laptop_filepath).unwrap();
+
+        laptop
+            .execute_batch(
+                "INSERT INTO tombstones (uuid, deleted_at, device_id)
+                VALUES ('deleted', '2026-10-02 10:00:00', 'laptop'),
+                ('deleted-tag', '2026-10-02 10:00:00', 'laptop');
+                DELETE FROM tags WHERE uuid = 'deleted-tag';
+                DELETE FROM sessions WHERE uuid = 'deleted';",
+            )
+            .unwrap();
+
+        // Neither brings the deleted session back to the laptop
+        assert_eq!(merge(&laptop, &desktop_filepath).unwrap().sessions_added, 0);
+        assert_eq!(merge(&desktop, &laptop_filepath).unwrap().deleted, 2);
+        assert_eq!(contents(&laptop), contents(&desktop));
+        assert_eq!(contents(&desktop).len(), 1);
+
+        remove_file(laptop_filepath).unwrap();
+        remove_file(desktop_filepath).unwrap();
+    }
+
+    #[test]
+    fn legacy_rows_get_the_same_uuids_everywhere() {
+        // Two copies of a database from before rows had uuids
+        let legacy = |name: &str| {
+            let filepath = std::env::temp_dir()
+                .join(format!("pt-merge-{}-{}", name, std::process::id()))
+                .display()
+                .to_string();
+            let _ = remove_file(&filepath);
+            let conn = Connection::open(&filepath).unwrap();
+            for migration in &MIGRATIONS[..3] {
+                conn.execute_batch(migration).unwrap();
+            }
+            conn.execute_batch(
+                "PRAGMA user_version = 3;
+                INSERT INTO sessions (total_time, durations_count, tag, created_at, completed_at)
+                VALUES ('00:10:00', 1, 'pt', '2026-10-01 10:00:00', '2026-10-01 11:00:00');
+                INSERT INTO tags (session_id, value, time) VALUES (1, 'writing', '00:10:00');",
+            )
+            .unwrap();
+            drop(conn);
+            (open_database(&filepath, false).unwrap(), filepath)
+        };
+        let (laptop, laptop_filepath) = legacy("legacy-laptop");
+        let (desktop, desktop_filepath) = legacy("legacy-desktop");
+
+        let uuids = |conn: &Connection| -> (String, String, String) {
+            conn.query_row(
+                "SELECT s.uuid, t.uuid, t.session_uuid FROM sessions s JOIN tags t ON t.session_id = s.id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap()
+        };
+        let (session_uuid, _, tag_session_uuid) = uuids(&laptop);
+        assert!(session_uuid.starts_with("legacy-"));
+        assert_eq!(session_uuid, tag_session_uuid);
+        assert_eq!(uuids(&laptop), uuids(&desktop));
+
+        // So the shared history isn't doubled up
+        assert_eq!(
+            merge(&laptop, &desktop_filepath).unwrap(),
+            MergeSummary::default()
+        );
+
+        remove_file(laptop_filepath).unwrap();
+        remove_file(desktop_filepath).unwrap();
+    }
+
+    #[test]
+    fn issue_keys_are_taken_again_for_every_completed_duration() {
+        let (mut conn, filepath) = database("issues");
+        add_session(&conn, "done", "laptop", "pt", "2026-10-01 10:00:00");
+        conn.execute_batch(
+            "UPDATE sessions SET note = 'Fixed OPS-7' WHERE uuid = 'done';
+            INSERT INTO tags (session_id, value, time, uuid, session_uuid)
+            VALUES ((SELECT id FROM sessions WHERE uuid = 'done'), 'reading ABC-1', '00:05:00', 'done-reading', 'done');
+            INSERT INTO sessions (tag, uuid) VALUES (NULL, 'running');
+            INSERT INTO tags (session_id, value, time, uuid, session_uuid)
+            VALUES ((SELECT id FROM sessions WHERE uuid = 'running'), 'DEF-2', '00:01:00', 'running-tag', 'running');",
         )
-        .header(ACCEPT, "application/json")
-        .bearer_auth(token)
-        .send()?
-        .copy_to(&mut file);
+        .unwrap();
+        let issues = |conn: &Connection| -> Vec<(String, Option<String>, Option<String>)> {
+            conn.prepare("SELECT uuid, issue, device_id FROM tags ORDER BY uuid")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .map(Result::unwrap)
+                .collect()
+        };
+        let keys = vec![regex::Regex::new(r"\b[A-Z][A-Z0-9]+-\d+\b").unwrap()];
+        let numbers = vec![regex::Regex::new(r"#(\d+)").unwrap()];
 
-    println!("result: {:?}", result);
+        // Like durations from before keys were kept
+        let extract = |tag: Option<&str>, note: Option<&str>| issues::extract(&keys, tag, note);
+        assert_eq!(fill_issues(&mut conn, "desktop", extract).unwrap(), 2);
+        assert_eq!(
+            issues(&conn),
+            vec![
+                (
+                    "done-reading".to_string(),
+                    Some("ABC-1".to_string()),
+                    Some("desktop".to_string())
+                ),
+                (
+                    "done-tag".to_string(),
+                    Some("OPS-7".to_string()),
+                    Some("desktop".to_string())
+                ),
+                ("running-tag".to_string(), None, None),
+            ]
+        );
+        assert_eq!(fill_issues(&mut conn, "desktop", extract).unwrap(), 0);
 
-    Ok(())
+        // Then the patterns change
+        let extract = |tag: Option<&str>, note: Option<&str>| issues::extract(&numbers, tag, note);
+        assert_eq!(fill_issues(&mut conn, "desktop", extract).unwrap(), 2);
+        assert!(issues(&conn).iter().all(|(_, issue, _)| issue.is_none()));
+
+        drop(conn);
+        remove_file(filepath).unwrap();
+    }
 }
@@ -0,0 +1,67 @@
+// Changes to the timer's state, pushed to whoever's subscribed, e.g. the API's `/events` streams.
+// The daemon publishes `started`, `paused`, `switched` and `completed` as they happen, `flagged`
+// when a duration runs into a calendar event, and a `tick` with the status every second.
+// Subscribers that have gone away are dropped the next time something's published.
+use serde_json::Value;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+static SUBSCRIBERS: Subscribers = Subscribers::new();
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub name: &'static str,
+    pub data: Value,
+}
+
+pub fn subscribe() -> Receiver<Event> {
+    SUBSCRIBERS.subscribe()
+}
+
+pub fn publish(name: &'static str, data: Value) {
+    SUBSCRIBERS.publish(name, data)
+}
+
+// Everyone who's subscribed. There's only the one, but tests make their own.
+struct Subscribers(Mutex<Vec<Sender<Event>>>);
+
+impl Subscribers {
+    const fn new() -> Subscribers {
+        Subscribers(Mutex::new(Vec::new()))
+    }
+
+    fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = channel();
+        self.0.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn publish(&self, name: &'static str, data: Value) {
+        let event = Event { name, data };
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn every_subscriber_gets_every_event_until_it_goes_away() {
+        let subscribers = Subscribers::new();
+        let first = subscribers.subscribe();
+        let second = subscribers.subscribe();
+
+        subscribers.publish("started", json!({"tag": "writing"}));
+        drop(second);
+        subscribers.publish("paused", json!({"tag": "writing"}));
+
+        let names: Vec<&str> = first.try_iter().map(|event| event.name).collect();
+        assert_eq!(names, ["started", "paused"]);
+        assert_eq!(subscribers.0.lock().unwrap().len(), 1);
+    }
+}
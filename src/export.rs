@@ -0,0 +1,330 @@
+// Gets durations out of the database in formats other tools read: CSV for spreadsheets, JSON for
+// scripts, iCalendar so focused blocks can be shown in a calendar app next to meetings, and
+// worklogs for issue trackers. Each row, object, event or worklog is one duration.
+use crate::database::{get_session_durations, SessionDuration};
+use chrono::{DateTime, Duration, Local, NaiveDate, SecondsFormat, TimeZone, Utc};
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::{stdout, Error, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    Ics,
+    Worklog,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Option<Format> {
+        match format {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "ics" => Some(Format::Ics),
+            "worklog" => Some(Format::Worklog),
+            _ => None,
+        }
+    }
+}
+
+// What's exported for a duration. Times are RFC 3339 in local time.
+#[derive(Debug, Serialize)]
+struct Row {
+    id: String,
+    start: Option<String>,
+    end: Option<String>,
+    seconds: i64,
+    tag: Option<String>,
+    session_id: u64,
+    session_tag: Option<String>,
+    repo: Option<String>,
+    branch: Option<String>,
+    issue: Option<String>,
+}
+
+impl Row {
+    fn from_duration(duration: &SessionDuration) -> Row {
+        let rfc3339 = |timestamp: DateTime<Utc>| {
+            timestamp
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Secs, false)
+        };
+        Row {
+            id: duration.uuid.clone(),
+            start: duration.begin_at.map(rfc3339),
+            end: duration.end_at.map(rfc3339),
+            seconds: duration.seconds,
+            tag: duration.tag.clone(),
+            session_id: duration.session_id,
+            session_tag: duration.session_tag.clone(),
+            repo: duration.repo.clone(),
+            branch: duration.branch.clone(),
+            issue: duration.issue.clone(),
+        }
+    }
+}
+
+// Writes durations that began from `since` through `until` (both local dates, and both optional) to
+// stdout
+pub fn export(
+    format: Format,
+    session_tag: Option<&str>,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<(), Error> {
+    let until = until.map(|until| until + Duration::days(1));
+    let durations = get_session_durations(session_tag, since, until).map_err(Error::other)?;
+
+    let mut out = stdout();
+    match format {
+        Format::Csv => write_csv(&mut out, &durations),
+        Format::Json => write_json(&mut out, &durations),
+        Format::Ics => write_ics(&mut out, &durations, Utc::now()),
+        Format::Worklog => write_worklog(&mut out, &durations, &Local),
+    }
+}
+
+fn write_csv<W: Write>(out: W, durations: &[SessionDuration]) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    for duration in durations {
+        writer.serialize(Row::from_duration(duration))?;
+    }
+    writer.flush()
+}
+
+fn write_json<W: Write>(mut out: W, durations: &[SessionDuration]) -> Result<(), Error> {
+    let rows: Vec<Row> = durations.iter().map(Row::from_duration).collect();
+    serde_json::to_writer_pretty(&mut out, &rows)?;
+    writeln!(out)
+}
+
+// One event per duration, per RFC 5545. Durations without timestamps can't be placed on a
+// calendar, and subtractions aren't time spent, so neither becomes an event.
+fn write_ics<W: Write>(
+    mut out: W,
+    durations: &[SessionDuration],
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let timestamp = |at: DateTime<Utc>| at.format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//productivity-timer//pt//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for duration in durations {
+        let begin_at = match duration.begin_at {
+            Some(begin_at) if duration.seconds > 0 => begin_at,
+            _ => continue,
+        };
+        // Time added by hand has no real end, so it's as long as it counts for
+        let end_at = duration
+            .end_at
+            .filter(|end_at| *end_at > begin_at)
+            .unwrap_or_else(|| begin_at + Duration::seconds(duration.seconds));
+        let session_tag = duration.session_tag.as_deref().unwrap_or("untagged");
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}@productivity-timer", duration.uuid));
+        lines.push(format!("DTSTAMP:{}", timestamp(now)));
+        lines.push(format!("DTSTART:{}", timestamp(begin_at)));
+        lines.push(format!("DTEND:{}", timestamp(end_at)));
+        lines.push(format!(
+            "SUMMARY:{}",
+            escape_text(duration.tag.as_deref().unwrap_or(session_tag))
+        ));
+        lines.push(format!(
+            "DESCRIPTION:{}",
+            escape_text(&format!(
+                "Session {} ({})",
+                duration.session_id, session_tag
+            ))
+        ));
+        lines.push(format!("CATEGORIES:{}", escape_text(session_tag)));
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    for line in lines {
+        out.write_all(fold(&line).as_bytes())?;
+    }
+    Ok(())
+}
+
+// What a worklog is made of in Jira's REST API, for a script to post or a bulk importer to read
+#[derive(Debug, Serialize)]
+struct Worklog {
+    issue_key: String,
+    // Jira's format, e.g. 2026-10-19T09:00:00.000-0500
+    started: String,
+    time_spent_seconds: i64,
+    comment: String,
+}
+
+// A CSV row per duration with an issue key. Like with calendar events, durations without
+// timestamps and subtracted time are left out.
+fn write_worklog<W, Tz>(out: W, durations: &[SessionDuration], timezone: &Tz) -> Result<(), Error>
+where
+    W: Write,
+    Tz: TimeZone,
+    Tz::Offset: Display,
+{
+    let mut writer = csv::Writer::from_writer(out);
+    for duration in durations {
+        let (issue, begin_at) = match (&duration.issue, duration.begin_at) {
+            (Some(issue), Some(begin_at)) if duration.seconds > 0 => (issue, begin_at),
+            _ => continue,
+        };
+        writer.serialize(Worklog {
+            issue_key: issue.clone(),
+            started: begin_at
+                .with_timezone(timezone)
+                .format("%Y-%m-%dT%H:%M:%S%.3f%z")
+                .to_string(),
+            time_spent_seconds: duration.seconds,
+            comment: duration
+                .tag
+                .clone()
+                .or_else(|| duration.session_tag.clone())
+                .unwrap_or_default(),
+        })?;
+    }
+    writer.flush()
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 bytes are split, with each continuation starting with a space. Lines end in
+// CRLF.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded + "\r\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn duration(tag: Option<&str>, seconds: i64) -> SessionDuration {
+        let begin_at = Utc.with_ymd_and_hms(2026, 10, 19, 9, 0, 0).unwrap();
+        SessionDuration {
+            uuid: format!("uuid-{}", seconds),
+            session_id: 7,
+            session_tag: Some("pt".to_string()),
+            session_note: None,
+            tag: tag.map(|tag| tag.to_string()),
+            seconds,
+            begin_at: Some(begin_at),
+            end_at: Some(begin_at + Duration::seconds(seconds.max(0))),
+            repo: None,
+            branch: None,
+            issue: None,
+        }
+    }
+
+    #[test]
+    fn writes_an_event_per_duration_that_took_time() {
+        let durations = vec![
+            duration(Some("writing, editing; and so on"), 1500),
+            // Subtracted, so not an event
+            duration(None, -600),
+        ];
+        let now = Utc.with_ymd_and_hms(2026, 10, 20, 0, 0, 0).unwrap();
+
+        let mut out = Vec::new();
+        write_ics(&mut out, &durations, now).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                "BEGIN:VCALENDAR",
+                "VERSION:2.0",
+                "PRODID:-//productivity-timer//pt//EN",
+                "CALSCALE:GREGORIAN",
+                "BEGIN:VEVENT",
+                "UID:uuid-1500@productivity-timer",
+                "DTSTAMP:20261020T000000Z",
+                "DTSTART:20261019T090000Z",
+                "DTEND:20261019T092500Z",
+                "SUMMARY:writing\\, editing\\; and so on",
+                "DESCRIPTION:Session 7 (pt)",
+                "CATEGORIES:pt",
+                "END:VEVENT",
+                "END:VCALENDAR",
+                "",
+            ]
+            .join("\r\n")
+        );
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        let line = "SUMMARY:".to_string() + &"é".repeat(40);
+        let folded = fold(&line);
+
+        assert!(folded.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), line + "\r\n");
+    }
+
+    #[test]
+    fn writes_a_csv_row_per_duration() {
+        let mut out = Vec::new();
+        write_csv(&mut out, &[duration(Some("writing, editing"), 1500)]).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let mut lines = csv.lines();
+
+        assert_eq!(
+            lines.next(),
+            Some("id,start,end,seconds,tag,session_id,session_tag,repo,branch,issue")
+        );
+        let row = lines.next().unwrap();
+        assert!(row.starts_with("uuid-1500,"));
+        assert!(row.ends_with(",1500,\"writing, editing\",7,pt,,,"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn writes_a_worklog_per_duration_with_an_issue() {
+        let with_issue = |tag, seconds| SessionDuration {
+            issue: Some("ABC-123".to_string()),
+            ..duration(tag, seconds)
+        };
+        let durations = vec![
+            with_issue(Some("reading ABC-123"), 1500),
+            with_issue(None, 600),
+            // Subtracted time, and time that isn't on an issue, aren't worklogs
+            with_issue(None, -600),
+            duration(Some("review"), 900),
+        ];
+
+        let mut out = Vec::new();
+        write_worklog(&mut out, &durations, &Utc).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            [
+                "issue_key,started,time_spent_seconds,comment",
+                "ABC-123,2026-10-19T09:00:00.000+0000,1500,reading ABC-123",
+                "ABC-123,2026-10-19T09:00:00.000+0000,600,pt",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,230 @@
+// Which git repo and branch time goes into. With `enabled = true` in the `[git]` table of the
+// config, each duration is saved with the repo and branch of the configured `directory`, or of the
+// directory pt was run from when the duration started. `pt git-hook install` adds a
+// prepare-commit-msg hook to a repo that puts the time spent on its branch since the last commit
+// in the commit message as a trailer.
+use crate::config::{expand_home, get_config, GitConfig};
+use crate::daemon::{format_instant_to_hhmmss, get_status, is_running};
+use crate::database::get_session_durations;
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::env::current_exe;
+use std::fs::{create_dir_all, read_to_string, set_permissions, write, Permissions};
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// So installing again replaces pt's hook, but never anyone else's
+const HOOK_MARKER: &str = "# Installed by `pt git-hook install`";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Context {
+    // The name of the working copy's top-level directory
+    pub repo: String,
+    // HEAD when it's detached
+    pub branch: String,
+}
+
+// The context to save a duration started from `directory` with, if there is one and it's on
+pub fn resolve(config: &GitConfig, directory: Option<&str>) -> Option<Context> {
+    if !config.enabled {
+        return None;
+    }
+    let directory = match &config.directory {
+        Some(configured) => expand_home(configured),
+        None => directory?.to_string(),
+    };
+    context(&directory)
+}
+
+// None when `directory` isn't in a working copy
+pub fn context(directory: &str) -> Option<Context> {
+    let toplevel = git(directory, &["rev-parse", "--show-toplevel"]).ok()?;
+    // Unlike rev-parse, this works before the first commit, and fails when HEAD's detached
+    let branch = git(directory, &["symbolic-ref", "--short", "-q", "HEAD"])
+        .unwrap_or_else(|_| "HEAD".to_string());
+
+    Some(Context {
+        repo: Path::new(&toplevel)
+            .file_name()
+            .map_or(toplevel.clone(), |name| name.to_string_lossy().to_string()),
+        branch,
+    })
+}
+
+// The trimmed stdout of a git command run in `directory`
+fn git(directory: &str, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::other(format!("git {} failed", args.join(" "))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Adds the prepare-commit-msg hook to the repo the current directory is in
+pub fn install_hook() -> Result<String, Error> {
+    let hooks_directory = git(".", &["rev-parse", "--git-path", "hooks"])
+        .map_err(|_| Error::new(ErrorKind::NotFound, "Not in a git repository"))?;
+    create_dir_all(&hooks_directory)?;
+    let filepath = format!("{}/prepare-commit-msg", hooks_directory);
+
+    if let Ok(existing) = read_to_string(&filepath) {
+        if !existing.contains(HOOK_MARKER) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "{} is already there; add `pt git-hook run \"$1\" \"$2\"` to it instead",
+                    filepath
+                ),
+            ));
+        }
+    }
+
+    // A commit shouldn't fail because pt did
+    let hook = format!(
+        "#!/bin/sh\n{}\n{} git-hook run \"$1\" \"$2\" || true\n",
+        HOOK_MARKER,
+        shell_quote(&current_exe()?.display().to_string())
+    );
+    write(&filepath, hook)?;
+    set_permissions(&filepath, Permissions::from_mode(0o755))?;
+    Ok(filepath)
+}
+
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+// What the hook runs: adds the time spent on this repo and branch since the last commit to the
+// message in `message_filepath`. `source` is git's second argument to the hook.
+pub fn run_hook(message_filepath: &str, source: Option<&str>) -> Result<(), Error> {
+    // Merges and squashes aren't new work, and messages taken from another commit (-c, -C,
+    // --amend) keep whatever trailer they had
+    if matches!(source, Some("merge") | Some("squash") | Some("commit")) {
+        return Ok(());
+    }
+    let context = match context(".") {
+        Some(context) => context,
+        None => return Ok(()),
+    };
+    // Before the first commit, it's all the time spent on the repo
+    let since = git(".", &["log", "-1", "--format=%ct"])
+        .ok()
+        .and_then(|timestamp| timestamp.parse().ok())
+        .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single());
+
+    let seconds = time_spent(&context, since)?;
+    if seconds <= 0 {
+        return Ok(());
+    }
+
+    let trailer = format!(
+        "{}: {}",
+        get_config()?.git.trailer,
+        format_instant_to_hhmmss(Duration::seconds(seconds))
+    );
+    let status = Command::new("git")
+        .args(["interpret-trailers", "--in-place", "--if-exists", "replace"])
+        .args(["--trailer", &trailer, message_filepath])
+        .status()?;
+    if !status.success() {
+        return Err(Error::other("Problem adding the trailer"));
+    }
+    Ok(())
+}
+
+// Seconds spent in the context since `since`, in completed sessions and the running one
+fn time_spent(context: &Context, since: Option<DateTime<Utc>>) -> Result<i64, Error> {
+    let in_context = |repo: &Option<String>, branch: &Option<String>| {
+        repo.as_deref() == Some(&context.repo) && branch.as_deref() == Some(&context.branch)
+    };
+    let mut spans = Vec::new();
+
+    // A day early for durations that began the day before and went past midnight
+    let since_date =
+        since.map(|since| since.with_timezone(&Local).date_naive() - Duration::days(1));
+    for duration in get_session_durations(None, since_date, None).map_err(Error::other)? {
+        if let (Some(begin_at), Some(end_at)) = (duration.begin_at, duration.end_at) {
+            if in_context(&duration.repo, &duration.branch) {
+                spans.push((begin_at, end_at));
+            }
+        }
+    }
+
+    // The status of a daemon that's gone is of a session that was lost with it
+    if let Some(status) = get_status()?.filter(|_| is_running()) {
+        for duration in status.durations {
+            if let Some(begun_at) = Utc.timestamp_opt(duration.begun_at, 0).single() {
+                if in_context(&duration.repo, &duration.branch) {
+                    spans.push((begun_at, begun_at + Duration::seconds(duration.seconds)));
+                }
+            }
+        }
+    }
+
+    Ok(overlap(&spans, since))
+}
+
+// Seconds of the spans that fall after `since`
+fn overlap(spans: &[(DateTime<Utc>, DateTime<Utc>)], since: Option<DateTime<Utc>>) -> i64 {
+    spans
+        .iter()
+        .map(|(begin_at, end_at)| {
+            let begin_at = match since {
+                Some(since) => since.max(*begin_at),
+                None => *begin_at,
+            };
+            (*end_at - begin_at).num_seconds().max(0)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::remove_dir_all;
+
+    #[test]
+    fn only_counts_time_after_the_last_commit() {
+        let at = |hour, minute| Utc.with_ymd_and_hms(2026, 10, 19, hour, minute, 0).unwrap();
+        let spans = [
+            (at(8, 0), at(9, 0)),
+            // The commit was at 10:00, halfway through this one
+            (at(9, 30), at(10, 30)),
+            (at(11, 0), at(11, 15)),
+        ];
+
+        assert_eq!(overlap(&spans, Some(at(10, 0))), 45 * 60);
+        assert_eq!(overlap(&spans, None), 135 * 60);
+    }
+
+    #[test]
+    fn reads_the_repo_and_branch_of_a_working_copy() {
+        let directory = temp_dir().join(format!("pt-git-{}", std::process::id()));
+        let repo = directory.join("notes");
+        create_dir_all(&repo).unwrap();
+        let repo = repo.display().to_string();
+        git(&repo, &["init", "-q", "-b", "drafts"]).unwrap();
+
+        // No commits yet, and deeper in the working copy is still the working copy
+        let nested = repo.clone() + "/chapters";
+        create_dir_all(&nested).unwrap();
+        assert_eq!(
+            context(&nested),
+            Some(Context {
+                repo: "notes".to_string(),
+                branch: "drafts".to_string(),
+            })
+        );
+        assert_eq!(context(&directory.display().to_string()), None);
+
+        remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+// Commands the daemon runs when things happen, like git's hooks: an executable at
+// `~/.productivity-timer/hooks/<event>` is run for each event, with the details as `PT_*`
+// environment variables and as JSON on stdin. They're run on their own threads so a slow one
+// doesn't hold up the timer, are killed after `timeout_seconds` in the `[hooks]` table of the
+// config, and anything that goes wrong is logged to the daemon's err file.
+use crate::config::get_config;
+use chrono::{Local, SecondsFormat};
+use dirs::home_dir;
+use serde_json::{Map, Value};
+use std::io::{Error, ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    DurationStarted,
+    DurationPaused,
+    SessionCompleted,
+    GoalReached,
+    IdleDetected,
+    BackupFailed,
+}
+
+impl Event {
+    // Also the hook's filename
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::DurationStarted => "duration_started",
+            Event::DurationPaused => "duration_paused",
+            Event::SessionCompleted => "session_completed",
+            Event::GoalReached => "goal_reached",
+            Event::IdleDetected => "idle_detected",
+            Event::BackupFailed => "backup_failed",
+        }
+    }
+}
+
+fn hooks_directory() -> String {
+    home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer/hooks"
+}
+
+// Runs the event's hook, if there is one, in the background. `details` should be a JSON object.
+pub fn run(event: Event, details: Value) {
+    let filepath = format!("{}/{}", hooks_directory(), event.name());
+    if !Path::new(&filepath).exists() {
+        return;
+    }
+
+    let timeout = match get_config() {
+        Ok(config) => Duration::from_secs(config.hooks.timeout_seconds),
+        Err(e) => {
+            eprintln!("Error, {}", e);
+            return;
+        }
+    };
+    let payload = payload(event, details);
+
+    spawn(move || {
+        if let Err(e) = run_hook(&filepath, &payload, timeout) {
+            eprintln!("Error running the {} hook, {}", event.name(), e);
+        }
+    });
+}
+
+// The event and when it happened, followed by its details
+fn payload(event: Event, details: Value) -> Map<String, Value> {
+    let mut payload = Map::new();
+    payload.insert("event".to_string(), Value::from(event.name()));
+    payload.insert(
+        "at".to_string(),
+        Value::from(Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)),
+    );
+    if let Value::Object(details) = details {
+        payload.extend(details);
+    }
+    payload
+}
+
+// Each field as PT_<FIELD>, e.g. PT_SESSION_TAG; strings as they are, null as empty, and anything
+// else as JSON
+fn environment(payload: &Map<String, Value>) -> Vec<(String, String)> {
+    payload
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Null => String::new(),
+                value => value.to_string(),
+            };
+            (format!("PT_{}", key.to_uppercase()), value)
+        })
+        .collect()
+}
+
+fn run_hook(filepath: &str, payload: &Map<String, Value>, timeout: Duration) -> Result<(), Error> {
+    let mut child = Command::new(filepath)
+        .envs(environment(payload))
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    // A hook that doesn't read stdin closes it early, which isn't its failure
+    let mut stdin = child.stdin.take().unwrap();
+    match stdin.write_all(Value::from(payload.clone()).to_string().as_bytes()) {
+        Ok(()) => (),
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => (),
+        Err(e) => eprintln!("Error writing to the hook at {}, {}", filepath, e),
+    }
+    drop(stdin);
+
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return match status.success() {
+                true => Ok(()),
+                false => Err(Error::other(format!("{} exited with {}", filepath, status))),
+            };
+        }
+        if started.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            return Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("{} was killed after {}s", filepath, timeout.as_secs()),
+            ));
+        }
+        sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs::{create_dir_all, read_to_string, set_permissions, write, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    fn hook(name: &str, script: &str) -> String {
+        let directory = std::env::temp_dir().join(format!("pt-hooks-{}", std::process::id()));
+        create_dir_all(&directory).unwrap();
+        let filepath = directory.join(name);
+        write(&filepath, script).unwrap();
+        set_permissions(&filepath, Permissions::from_mode(0o755)).unwrap();
+        filepath.display().to_string()
+    }
+
+    #[test]
+    fn passes_details_as_environment_and_stdin() {
+        let filepath = hook(
+            "session_completed",
+            "#!/bin/sh\nset -e\nout=\"$0.out\"\necho \"$PT_EVENT $PT_SESSION_TAG $PT_TIME_GAINED [$PT_NOTE]\" > \"$out\"\ncat >> \"$out\"\n",
+        );
+        let payload = payload(
+            Event::SessionCompleted,
+            json!({"session_tag": "pt", "time_gained": 1500, "note": null}),
+        );
+
+        run_hook(&filepath, &payload, Duration::from_secs(5)).unwrap();
+
+        let out = read_to_string(filepath + ".out").unwrap();
+        let (environment, stdin) = out.split_once('\n').unwrap();
+        assert_eq!(environment, "session_completed pt 1500 []");
+        let stdin: Value = serde_json::from_str(stdin).unwrap();
+        assert_eq!(stdin["event"], "session_completed");
+        assert_eq!(stdin["session_tag"], "pt");
+        assert_eq!(stdin["time_gained"], 1500);
+    }
+
+    #[test]
+    fn failing_and_slow_hooks_are_errors() {
+        let failing = hook("duration_paused", "#!/bin/sh\nexit 3\n");
+        let slow = hook("duration_started", "#!/bin/sh\nsleep 10\n");
+        let payload = payload(Event::DurationStarted, json!({}));
+
+        let e = run_hook(&failing, &payload, Duration::from_secs(5)).unwrap_err();
+        assert!(e.to_string().contains("exit status: 3"));
+
+        let started = Instant::now();
+        let e = run_hook(&slow, &payload, Duration::from_millis(200)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,161 @@
+// Notices when a duration's running but nobody's at the computer, by asking the `command` in the
+// `[idle]` table of the config how long there's been no input. pt can't tell on its own, and how to
+// ask differs by platform (xprintidle on X11, for one), so without a command nothing's detected.
+use crate::config::IdleConfig;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+// Asking is a process spawn, so it's only done this often
+const CHECK_EVERY: Duration = Duration::from_secs(30);
+
+pub struct IdleWatcher {
+    command: Option<String>,
+    threshold: Duration,
+    // The command's run on the daemon's loop, so one that hangs is killed after this long
+    timeout: Duration,
+    checked_at: Option<Instant>,
+    // Whether the current idle stretch has been reported, so it's only reported once
+    reported: bool,
+}
+
+impl IdleWatcher {
+    pub fn new(config: IdleConfig, timeout: Duration) -> IdleWatcher {
+        IdleWatcher {
+            command: config.command,
+            threshold: Duration::from_secs(config.threshold_minutes * 60),
+            timeout,
+            checked_at: None,
+            reported: false,
+        }
+    }
+
+    // Called every time around the daemon's loop. Returns how long there's been no input when
+    // that's newly past the threshold during a duration.
+    pub fn tick(&mut self, active: bool) -> Option<Duration> {
+        let command = self.command.clone()?;
+        if !active {
+            self.reported = false;
+            return None;
+        }
+        if self
+            .checked_at
+            .is_some_and(|checked_at| checked_at.elapsed() < CHECK_EVERY)
+        {
+            return None;
+        }
+        self.checked_at = Some(Instant::now());
+
+        match idle_time(&command, self.timeout) {
+            Ok(idle) => self.observe(idle),
+            Err(e) => {
+                // It'd fail the same way every time, so stop asking
+                eprintln!("Error checking idle time, {}; not checking again", e);
+                self.command = None;
+                None
+            }
+        }
+    }
+
+    fn observe(&mut self, idle: Duration) -> Option<Duration> {
+        if idle < self.threshold {
+            self.reported = false;
+            return None;
+        }
+        if self.reported {
+            return None;
+        }
+        self.reported = true;
+        Some(idle)
+    }
+}
+
+fn idle_time(command: &str, timeout: Duration) -> Result<Duration, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            child.kill().map_err(|e| e.to_string())?;
+            child.wait().map_err(|e| e.to_string())?;
+            return Err(format!(
+                "`{}` was killed after {}s",
+                command,
+                timeout.as_secs()
+            ));
+        }
+        sleep(Duration::from_millis(50));
+    };
+    if !status.success() {
+        return Err(format!("`{}` exited with {}", command, status));
+    }
+
+    let mut output = Vec::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_end(&mut output)
+        .map_err(|e| e.to_string())?;
+    let stdout = String::from_utf8_lossy(&output);
+    stdout
+        .trim()
+        .parse()
+        .map(Duration::from_millis)
+        .map_err(|_| {
+            format!(
+                "`{}` should print milliseconds, not {:?}",
+                command,
+                stdout.trim()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_each_idle_stretch_once() {
+        let mut watcher = IdleWatcher::new(
+            IdleConfig {
+                command: Some("echo 0".to_string()),
+                threshold_minutes: 10,
+            },
+            Duration::from_secs(10),
+        );
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+
+        assert_eq!(watcher.observe(minutes(5)), None);
+        assert_eq!(watcher.observe(minutes(11)), Some(minutes(11)));
+        assert_eq!(watcher.observe(minutes(12)), None);
+        // Back at the keyboard, then away again
+        assert_eq!(watcher.observe(minutes(0)), None);
+        assert_eq!(watcher.observe(minutes(10)), Some(minutes(10)));
+
+        let timeout = Duration::from_secs(10);
+        assert_eq!(
+            idle_time("echo 1500", timeout),
+            Ok(Duration::from_millis(1500))
+        );
+        assert!(idle_time("echo soon", timeout).is_err());
+    }
+
+    #[test]
+    fn kills_a_command_that_hangs() {
+        let started = Instant::now();
+        let e = idle_time("sleep 10", Duration::from_millis(100)).unwrap_err();
+
+        assert!(e.contains("was killed"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,291 @@
+// Folds history from other time trackers into the database: Timewarrior's data files, Toggl's
+// detailed CSV export, and any CSV whose columns are described in the `[import.csv]` table of the
+// config. Entries already in the database are skipped, so importing the same file twice is fine.
+mod columns;
+mod timewarrior;
+mod toggl;
+
+use crate::config::get_config;
+use crate::daemon::format_instant_to_hhmmss;
+use crate::database::{get_duration_spans, insert_sessions, NewDuration};
+use crate::issues;
+use crate::snapshots;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Timewarrior,
+    Toggl,
+    Csv,
+}
+
+impl Source {
+    pub fn parse(source: &str) -> Option<Source> {
+        match source {
+            "timewarrior" => Some(Source::Timewarrior),
+            "toggl" => Some(Source::Toggl),
+            "csv" => Some(Source::Csv),
+            _ => None,
+        }
+    }
+}
+
+// A span of time as another tracker records it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub begin_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+    // What becomes the session tag, if the tracker has such a thing
+    pub project: Option<String>,
+    pub tags: Vec<String>,
+}
+
+// What an import would do
+#[derive(Debug, Default)]
+struct Plan {
+    // A session per session tag per (local) day
+    sessions: Vec<(String, Vec<NewDuration>)>,
+    duplicates: usize,
+    // No length, or no session tag to put them under
+    skipped: usize,
+}
+
+pub fn import(
+    source: Source,
+    path: &str,
+    default_session_tag: Option<&str>,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let config = get_config()?.import;
+
+    let entries = match source {
+        Source::Timewarrior => timewarrior::read(path)?,
+        Source::Toggl => toggl::read(path)?,
+        Source::Csv => columns::read(path, &config.csv)?,
+    };
+    let existing = get_duration_spans().map_err(Error::other)?;
+    let mut plan = plan(
+        entries,
+        &config.session_tags,
+        default_session_tag,
+        &existing,
+    );
+
+    print_preview(&plan);
+    if dry_run {
+        println!("Nothing imported; that was a dry run");
+        return Ok(());
+    }
+    if plan.sessions.is_empty() {
+        println!("Nothing to import");
+        return Ok(());
+    }
+
+    let patterns = issues::patterns()?;
+    for (_, durations) in &mut plan.sessions {
+        for duration in durations {
+            duration.issue = issues::extract(&patterns, duration.tag.as_deref(), None);
+        }
+    }
+
+    let snapshot_filepath = snapshots::take("import")?;
+    insert_sessions(&plan.sessions).map_err(Error::other)?;
+    println!("Imported; the old database is at {}", snapshot_filepath);
+    Ok(())
+}
+
+fn plan(
+    mut entries: Vec<Entry>,
+    session_tags: &BTreeMap<String, String>,
+    default_session_tag: Option<&str>,
+    existing: &HashSet<(DateTime<Utc>, DateTime<Utc>)>,
+) -> Plan {
+    entries.sort_by_key(|entry| entry.begin_at);
+
+    let mut plan = Plan::default();
+    let mut seen = HashSet::new();
+    let mut sessions: BTreeMap<(String, NaiveDate), Vec<NewDuration>> = BTreeMap::new();
+
+    for entry in entries {
+        let span = (entry.begin_at, entry.end_at);
+        if entry.end_at <= entry.begin_at {
+            plan.skipped += 1;
+            continue;
+        }
+        if existing.contains(&span) || !seen.insert(span) {
+            plan.duplicates += 1;
+            continue;
+        }
+
+        let (session_tag, tag) = map_entry(&entry, session_tags, default_session_tag);
+        let session_tag = match session_tag {
+            Some(session_tag) => session_tag,
+            None => {
+                plan.skipped += 1;
+                continue;
+            }
+        };
+
+        let day = entry.begin_at.with_timezone(&Local).date_naive();
+        sessions
+            .entry((session_tag, day))
+            .or_default()
+            .push(NewDuration {
+                tag,
+                issue: None,
+                begin_at: entry.begin_at,
+                end_at: entry.end_at,
+            });
+    }
+
+    plan.sessions = sessions
+        .into_iter()
+        .map(|((session_tag, _), durations)| (session_tag, durations))
+        .collect();
+    plan
+}
+
+// Projects become session tags and everything else the tag, with `[import.session_tags]` renaming
+// projects along the way. Timewarrior has only tags, so the first with a mapping (or else the
+// first) is taken as the project.
+fn map_entry(
+    entry: &Entry,
+    session_tags: &BTreeMap<String, String>,
+    default_session_tag: Option<&str>,
+) -> (Option<String>, Option<String>) {
+    let mut tags = entry.tags.clone();
+    let project = match &entry.project {
+        Some(project) => Some(project.clone()),
+        None if tags.is_empty() => None,
+        None => {
+            let i = tags
+                .iter()
+                .position(|tag| session_tags.contains_key(tag))
+                .unwrap_or(0);
+            Some(tags.remove(i))
+        }
+    };
+
+    let session_tag = match project {
+        Some(project) => Some(session_tags.get(&project).cloned().unwrap_or(project)),
+        None => default_session_tag.map(|session_tag| session_tag.to_string()),
+    };
+    let tag = if tags.is_empty() {
+        None
+    } else {
+        Some(tags.join(" "))
+    };
+    (session_tag, tag)
+}
+
+fn print_preview(plan: &Plan) {
+    let mut count = 0;
+    for (session_tag, durations) in &plan.sessions {
+        let seconds: i64 = durations
+            .iter()
+            .map(|duration| (duration.end_at - duration.begin_at).num_seconds())
+            .sum();
+        println!(
+            "{}  {:<20} {:>4} durations  {}",
+            durations[0]
+                .begin_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d"),
+            session_tag,
+            durations.len(),
+            format_instant_to_hhmmss(chrono::Duration::seconds(seconds))
+        );
+        count += durations.len();
+    }
+    println!(
+        "{} durations in {} sessions to import; {} already here; {} skipped for having no length or session tag",
+        count,
+        plan.sessions.len(),
+        plan.duplicates,
+        plan.skipped
+    );
+}
+
+// Times other trackers write without a zone are in local time. When the clocks go back, the first
+// of the two possible times is taken.
+fn local_to_utc(local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|local| local.with_timezone(&Utc))
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(hour: u32, project: Option<&str>, tags: &[&str]) -> Entry {
+        let begin_at = Utc.with_ymd_and_hms(2026, 10, 19, hour, 0, 0).unwrap();
+        Entry {
+            begin_at,
+            end_at: begin_at + chrono::Duration::minutes(30),
+            project: project.map(|project| project.to_string()),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn maps_entries_onto_sessions_and_skips_what_is_already_here() {
+        let session_tags = BTreeMap::from([("Client X".to_string(), "work".to_string())]);
+        let already_here = entry(8, Some("Client X"), &["standup"]);
+        let existing = HashSet::from([(already_here.begin_at, already_here.end_at)]);
+
+        let mut empty = entry(9, None, &["reading"]);
+        empty.end_at = empty.begin_at;
+
+        let plan = plan(
+            vec![
+                entry(10, Some("Client X"), &["review"]),
+                // Timewarrior-style: the mapped tag is the project, wherever it is
+                entry(11, None, &["writing", "Client X"]),
+                entry(12, None, &["reading", "paper"]),
+                // No project or tags, so it goes under the default
+                entry(13, None, &[]),
+                entry(13, None, &[]),
+                already_here,
+                empty,
+            ],
+            &session_tags,
+            Some("misc"),
+            &existing,
+        );
+
+        let sessions: Vec<(String, Vec<Option<String>>)> = plan
+            .sessions
+            .iter()
+            .map(|(session_tag, durations)| {
+                (
+                    session_tag.clone(),
+                    durations
+                        .iter()
+                        .map(|duration| duration.tag.clone())
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            sessions,
+            vec![
+                ("misc".to_string(), vec![None]),
+                ("reading".to_string(), vec![Some("paper".to_string())]),
+                (
+                    "work".to_string(),
+                    vec![Some("review".to_string()), Some("writing".to_string())]
+                ),
+            ]
+        );
+        assert_eq!(plan.duplicates, 2);
+        assert_eq!(plan.skipped, 1);
+    }
+}
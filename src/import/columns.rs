@@ -0,0 +1,134 @@
+// Any CSV, given which of its columns hold what in the `[import.csv]` table of the config. The
+// defaults match what `pt export` writes.
+use super::{invalid, local_to_utc, Entry};
+use crate::config::CsvImportConfig;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use std::io::{Error, Read};
+
+pub fn read(path: &str, config: &CsvImportConfig) -> Result<Vec<Entry>, Error> {
+    parse(std::fs::File::open(path)?, config)
+}
+
+fn parse<R: Read>(csv: R, config: &CsvImportConfig) -> Result<Vec<Entry>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(config.delimiter as u8)
+        .from_reader(csv);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| invalid(format!("There's no {} column", name)))
+    };
+    let optional_column = |name: &Option<String>| name.as_deref().map(column).transpose();
+
+    let start = column(&config.start)?;
+    let end = optional_column(&config.end)?;
+    let duration = optional_column(&config.duration)?;
+    let session_tag = optional_column(&config.session_tag)?;
+    let tag = optional_column(&config.tag)?;
+    if end.is_none() && duration.is_none() {
+        return Err(invalid(
+            "Set either end or duration in the [import.csv] table of the config".to_string(),
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .map(|field| field.trim())
+                .filter(|field| !field.is_empty())
+        };
+        let bad = |what: &str| invalid(format!("Row {} has a bad {}", i + 1, what));
+
+        let begin_at = field(Some(start))
+            .and_then(|start| parse_time(start, &config.time_format))
+            .ok_or_else(|| bad("start"))?;
+        let end_at = match field(end) {
+            Some(end) => parse_time(end, &config.time_format).ok_or_else(|| bad("end"))?,
+            None => {
+                let seconds = field(duration)
+                    .and_then(parse_duration)
+                    .ok_or_else(|| bad("end or duration"))?;
+                begin_at + Duration::seconds(seconds)
+            }
+        };
+
+        entries.push(Entry {
+            begin_at,
+            end_at,
+            project: field(session_tag).map(|session_tag| session_tag.to_string()),
+            tags: field(tag)
+                .map(|tag| vec![tag.to_string()])
+                .unwrap_or_default(),
+        });
+    }
+    Ok(entries)
+}
+
+fn parse_time(time: &str, format: &Option<String>) -> Option<DateTime<Utc>> {
+    match format {
+        Some(format) => local_to_utc(NaiveDateTime::parse_from_str(time, format).ok()?),
+        None => DateTime::parse_from_rfc3339(time)
+            .ok()
+            .map(|time| time.with_timezone(&Utc)),
+    }
+}
+
+// Seconds, or HH:MM:SS
+fn parse_duration(duration: &str) -> Option<i64> {
+    if let Ok(seconds) = duration.parse() {
+        return Some(seconds);
+    }
+
+    let mut seconds = 0;
+    for part in duration.split(':') {
+        seconds = seconds * 60 + part.parse::<i64>().ok()?;
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn reads_the_configured_columns() {
+        let config = CsvImportConfig {
+            start: "When".to_string(),
+            end: None,
+            duration: Some("How long".to_string()),
+            session_tag: Some("Project".to_string()),
+            tag: None,
+            time_format: None,
+            delimiter: ';',
+        };
+        let csv = "When;How long;Project\n2026-10-19T09:00:00+02:00;01:30:00;pt\n2026-10-19T12:00:00Z;600;\n";
+
+        let entries = parse(csv.as_bytes(), &config).unwrap();
+
+        let utc = |hour, minute| Utc.with_ymd_and_hms(2026, 10, 19, hour, minute, 0).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    begin_at: utc(7, 0),
+                    end_at: utc(8, 30),
+                    project: Some("pt".to_string()),
+                    tags: Vec::new(),
+                },
+                Entry {
+                    begin_at: utc(12, 0),
+                    end_at: utc(12, 10),
+                    project: None,
+                    tags: Vec::new(),
+                },
+            ]
+        );
+        assert!(parse("When;Project\n".as_bytes(), &config).is_err());
+    }
+}
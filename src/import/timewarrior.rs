@@ -0,0 +1,133 @@
+// Timewarrior keeps a file per month in its data directory (e.g. ~/.timewarrior/data/2026-10.data)
+// with a line per interval:
+//
+//   inc 20261019T090000Z - 20261019T100000Z # tag "another tag" # "an annotation"
+//
+// Intervals that are still open have no end and are left out.
+use super::{invalid, Entry};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::fs::{read_dir, read_to_string};
+use std::io::Error;
+use std::path::Path;
+
+// `path` is either the data directory or one of its files
+pub fn read(path: &str) -> Result<Vec<Entry>, Error> {
+    let mut filepaths = Vec::new();
+    if Path::new(path).is_dir() {
+        for entry in read_dir(path)? {
+            let filepath = entry?.path();
+            if filepath
+                .extension()
+                .is_some_and(|extension| extension == "data")
+            {
+                filepaths.push(filepath);
+            }
+        }
+        filepaths.sort();
+    } else {
+        filepaths.push(Path::new(path).to_path_buf());
+    }
+
+    let mut entries = Vec::new();
+    for filepath in filepaths {
+        for (i, line) in read_to_string(&filepath)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_line(line) {
+                Ok(Some(entry)) => entries.push(entry),
+                Ok(None) => (),
+                Err(e) => {
+                    return Err(invalid(format!(
+                        "{}, line {}: {}",
+                        filepath.display(),
+                        i + 1,
+                        e
+                    )))
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_line(line: &str) -> Result<Option<Entry>, String> {
+    let rest = line
+        .trim()
+        .strip_prefix("inc ")
+        .ok_or("expected a line starting with inc")?;
+    let (times, tags) = match rest.split_once(" # ") {
+        Some((times, tags)) => (times, tags.split(" # ").next().unwrap_or("")),
+        None => (rest, ""),
+    };
+
+    let (begin_at, end_at) = match times.trim().split_once(" - ") {
+        Some((begin_at, end_at)) => (parse_time(begin_at)?, parse_time(end_at)?),
+        None => return Ok(None),
+    };
+
+    Ok(Some(Entry {
+        begin_at,
+        end_at,
+        project: None,
+        tags: split_tags(tags),
+    }))
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Utc>, String> {
+    NaiveDateTime::parse_from_str(time.trim(), "%Y%m%dT%H%M%SZ")
+        .map(|time| Utc.from_utc_datetime(&time))
+        .map_err(|e| format!("bad time {}: {}", time, e))
+}
+
+// Tags are separated by spaces; those with spaces in them are quoted
+fn split_tags(tags: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut tag = String::new();
+    let mut quoted = false;
+    let mut chars = tags.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => tag.extend(chars.next()),
+            ' ' if !quoted => {
+                if !tag.is_empty() {
+                    split.push(std::mem::take(&mut tag));
+                }
+            }
+            c => tag.push(c),
+        }
+    }
+    if !tag.is_empty() {
+        split.push(tag);
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_intervals_and_skips_open_ones() {
+        let entry = parse_line(
+            r#"inc 20261019T090000Z - 20261019T100000Z # pt "code \"review\"" # "an annotation""#,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            entry.begin_at,
+            Utc.with_ymd_and_hms(2026, 10, 19, 9, 0, 0).unwrap()
+        );
+        assert_eq!(
+            entry.end_at,
+            Utc.with_ymd_and_hms(2026, 10, 19, 10, 0, 0).unwrap()
+        );
+        assert_eq!(entry.tags, vec!["pt", "code \"review\""]);
+
+        assert_eq!(parse_line("inc 20261019T110000Z # pt").unwrap(), None);
+        assert!(parse_line("inc 2026-10-19 - 2026-10-20").is_err());
+    }
+}
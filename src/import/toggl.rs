@@ -0,0 +1,109 @@
+// Toggl Track's detailed report, exported as CSV. Times are local, with the date and time in
+// separate columns. The project becomes the session tag and the description (or Toggl's tags, if
+// there's no description) the tag.
+use super::{invalid, local_to_utc, Entry};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use csv::StringRecord;
+use std::io::{Error, Read};
+
+pub fn read(path: &str) -> Result<Vec<Entry>, Error> {
+    parse(std::fs::File::open(path)?)
+}
+
+fn parse<R: Read>(csv: R) -> Result<Vec<Entry>, Error> {
+    let mut reader = csv::Reader::from_reader(csv);
+    let headers = reader.headers()?.clone();
+    let column = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| headers.iter().position(|header| header == *name))
+            .ok_or_else(|| {
+                invalid(format!(
+                    "There's no {} column; is this Toggl's detailed report?",
+                    names[0]
+                ))
+            })
+    };
+
+    let project = column(&["Project"])?;
+    let description = column(&["Description"])?;
+    let tags = column(&["Tags"]).ok();
+    let start_date = column(&["Start date"])?;
+    let start_time = column(&["Start time"])?;
+    let end_date = column(&["End date", "Stop date"])?;
+    let end_time = column(&["End time", "Stop time"])?;
+
+    let mut entries = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let field = |column: usize| record.get(column).unwrap_or("").trim();
+        let time = |date: usize, time: usize| {
+            parse_time(&record, date, time)
+                .ok_or_else(|| invalid(format!("Row {} has a bad date or time", i + 1)))
+        };
+
+        let mut entry_tags = Vec::new();
+        if !field(description).is_empty() {
+            entry_tags.push(field(description).to_string());
+        } else if let Some(tags) = tags {
+            entry_tags.extend(
+                field(tags)
+                    .split(", ")
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| tag.to_string()),
+            );
+        }
+
+        entries.push(Entry {
+            begin_at: time(start_date, start_time)?,
+            end_at: time(end_date, end_time)?,
+            project: Some(field(project).to_string()).filter(|project| !project.is_empty()),
+            tags: entry_tags,
+        });
+    }
+    Ok(entries)
+}
+
+fn parse_time(record: &StringRecord, date: usize, time: usize) -> Option<DateTime<Utc>> {
+    let local = format!("{} {}", record.get(date)?.trim(), record.get(time)?.trim());
+    local_to_utc(NaiveDateTime::parse_from_str(&local, "%Y-%m-%d %H:%M:%S").ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn parses_the_detailed_report() {
+        let csv = "User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags,Amount ()
+Me,me@example.com,,Client X,,\"Review, then merge\",No,2026-10-19,09:00:00,2026-10-19,09:30:00,00:30:00,,
+Me,me@example.com,,,,,No,2026-10-19,10:00:00,2026-10-19,10:15:00,00:15:00,\"reading, papers\",
+";
+        let entries = parse(csv.as_bytes()).unwrap();
+
+        let local = |hour, minute| {
+            Local
+                .with_ymd_and_hms(2026, 10, 19, hour, minute, 0)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    begin_at: local(9, 0),
+                    end_at: local(9, 30),
+                    project: Some("Client X".to_string()),
+                    tags: vec!["Review, then merge".to_string()],
+                },
+                Entry {
+                    begin_at: local(10, 0),
+                    end_at: local(10, 15),
+                    project: None,
+                    tags: vec!["reading".to_string(), "papers".to_string()],
+                },
+            ]
+        );
+    }
+}
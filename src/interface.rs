@@ -1,21 +1,58 @@
 // https://monkeypatch.io/blog/2021/2021-05-31-rust-tui/
 // https://github.com/ilaborie/plop-tui/blob/blog/step-1/src/app/ui.rs
-use crate::database;
+mod app;
+mod calendar;
+mod events;
+mod session_chart;
 
-use std::convert::TryInto;
-use std::io::{stdin, stdout, Error, Read};
+use crate::daemon::format_instant_to_hhmmss;
+use app::{App, View};
+use calendar::Calendar;
+use events::{Event, Events};
+use session_chart::SessionChart;
+
+use std::io::{stdout, Error, ErrorKind};
+use std::time::Duration;
 use termion::raw::IntoRawMode;
+use tui::backend::Backend;
 use tui::backend::TermionBackend;
-use tui::layout::{Alignment, Constraint, Direction, Layout};
+use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Style};
-use tui::widgets::{BarChart, Block, Borders, Paragraph};
-use tui::Terminal;
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, Clear, Gauge, Paragraph, Tabs, Wrap};
+use tui::{Frame, Terminal};
+
+// How often the interface re-reads the database and the daemon's status when no keys are pressed
+const TICK_RATE: Duration = Duration::from_secs(1);
+
+// Colors for tags when session bars are stacked; LightCyan is left for untagged time
+const TAG_COLORS: [Color; 6] = [
+    Color::Magenta,
+    Color::Yellow,
+    Color::Green,
+    Color::LightRed,
+    Color::Blue,
+    Color::LightMagenta,
+];
+
+const HELP: &str = "space             pause / resume the timer
+t                 switch to a new tag
++ / -             add / subtract minutes
+c                 complete the session
+tab / shift-tab   next / previous session tag
+up / down         switch between all time, weeks and months
+left / right      previous / next week or month
+h / l             select previous / next session
+[ / ]             scroll sessions left / right
+s                 stack sessions by tag
+v                 switch between sessions and the calendar
+h / l / j / k     in the calendar, move a week back / forward, a day down / up
+esc               back to the session tag's totals
+?                 toggle this help
+q                 quit";
 
 pub fn draw(session_tag: String) -> Result<(), Error> {
     let stdout = stdout().into_raw_mode()?;
-    // TODO: why do I need the lock?
-    let stdin = stdin();
-    let stdin = stdin.lock();
 
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
@@ -23,48 +60,199 @@ pub fn draw(session_tag: String) -> Result<(), Error> {
     terminal.clear()?;
     terminal.hide_cursor()?;
 
-    let mut bytes = stdin.bytes();
+    let events = Events::new(TICK_RATE);
+    let mut app = App::new(session_tag);
+    app.refresh();
+
     loop {
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(1)
-                .constraints([Constraint::Percentage(10), Constraint::Percentage(80)])
-                .split(f.size());
+        // draw() resizes the terminal's buffers when the window size changes, so ticks double as
+        // resize handling
+        terminal.draw(|f| draw_app(f, &mut app))?;
 
-            let overview = database::get_lifetime_overview(&session_tag).unwrap()[0].to_string();
-            f.render_widget(draw_overview(overview), chunks[0]);
+        match events
+            .next()
+            .map_err(|e| Error::new(ErrorKind::BrokenPipe, e))?
+        {
+            Event::Input(key) => app.on_key(key),
+            Event::Tick => app.refresh(),
+        }
 
-            let body_chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .margin(1)
-                .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
-                .split(chunks[1]);
+        if app.should_quit {
+            break;
+        }
+    }
 
-            let mut total_times: Vec<(&str, u64)> = vec![];
-            let times = database::get_total_time_as_seconds(&session_tag).unwrap();
+    terminal.clear()?;
+    terminal.show_cursor()?;
+    Ok(())
+}
 
-            for time in times {
-                total_times.push(("", time.total_time.try_into().unwrap()));
-            }
+fn draw_app<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(5),
+            Constraint::Percentage(10),
+            Constraint::Min(0),
+        ])
+        .split(f.size());
 
-            let durations_barchart = draw_barchart(&total_times);
-            f.render_widget(durations_barchart, body_chunks[0]);
+    f.render_widget(draw_session_tags(app), chunks[0]);
+    draw_live(f, app, chunks[1]);
+    f.render_widget(draw_overview(app.overview.clone()), chunks[2]);
 
-            let tags = database::get_tags_pane(&session_tag).unwrap();
-            f.render_widget(draw_tags(tags), body_chunks[1]);
-        })?;
+    let body_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .margin(1)
+        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
+        .split(chunks[3]);
 
-        let b = bytes.next().unwrap().unwrap();
+    match app.view {
+        View::Sessions => draw_sessions(f, app, body_chunks[0]),
+        View::Calendar => f.render_widget(
+            Calendar::new(
+                &app.daily_times,
+                app.today(),
+                app.selected_day,
+                app.daily_goal,
+            )
+            .block(
+                Block::default()
+                    .title(format!("Calendar ({} a day)", hhmmss(app.daily_goal)))
+                    .borders(Borders::ALL),
+            ),
+            body_chunks[0],
+        ),
+    }
 
-        // quit on q
-        if b == b'q' {
-            break;
-        }
+    let tags_title = match (app.view, app.selected_session) {
+        (View::Calendar, _) => "Durations".to_string(),
+        (View::Sessions, Some(i)) => match app.sessions[i].completed_on() {
+            Some(date) => format!("Tags (session {}, {})", app.sessions[i].id, date),
+            None => format!("Tags (session {})", app.sessions[i].id),
+        },
+        (View::Sessions, None) => "Tags".to_string(),
+    };
+    f.render_widget(draw_tags(app.tags.clone(), tags_title), body_chunks[1]);
+
+    if let Some(prompt) = &app.prompt {
+        let area = centered_rect(40, 20, f.size());
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(format!("{}_", prompt.text)).block(
+                Block::default()
+                    .title(prompt.kind.title())
+                    .borders(Borders::ALL),
+            ),
+            area,
+        );
     }
 
-    terminal.clear()?;
-    Ok(())
+    if app.show_help {
+        let area = centered_rect(60, 60, f.size());
+        f.render_widget(Clear, area);
+        f.render_widget(draw_help(), area);
+    }
+}
+
+// The running session, as last reported by the daemon
+fn draw_live<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+        ])
+        .split(area);
+
+    let title = match &app.error {
+        Some(error) => format!("Timer: {}", error),
+        None => "Timer".to_string(),
+    };
+
+    let timer = match &app.status {
+        Some(status) => {
+            let state = match (status.active, &status.tag) {
+                (true, Some(tag)) => format!("{} {}", tag, hhmmss(status.current_duration)),
+                (true, None) => format!("untagged {}", hhmmss(status.current_duration)),
+                (false, _) => "paused".to_string(),
+            };
+            format!("{}\nsession {}", state, hhmmss(status.time_gained))
+        }
+        None => "daemon not running".to_string(),
+    };
+    let time_gained = app.time_gained_today();
+
+    f.render_widget(
+        Paragraph::new(timer)
+            .style(Style::default().fg(Color::LightCyan))
+            .alignment(Alignment::Center)
+            .block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .style(Style::default().fg(Color::White)),
+            ),
+        chunks[0],
+    );
+
+    f.render_widget(
+        Gauge::default()
+            .block(Block::default().title("Goal").borders(Borders::ALL))
+            .gauge_style(Style::default().fg(Color::LightCyan).bg(Color::Black))
+            .ratio((time_gained as f64 / app.daily_goal as f64).clamp(0.0, 1.0))
+            .label(format!(
+                "{} / {}",
+                hhmmss(time_gained),
+                hhmmss(app.daily_goal)
+            )),
+        chunks[1],
+    );
+
+    let todays_tags = app
+        .todays_tags
+        .iter()
+        .map(|(tag, seconds)| format!("{} :: {}", tag, hhmmss(*seconds)))
+        .collect::<Vec<String>>()
+        .join("  ");
+
+    f.render_widget(
+        Paragraph::new(todays_tags)
+            .style(Style::default().fg(Color::LightCyan))
+            .wrap(Wrap { trim: true })
+            .block(
+                Block::default()
+                    .title("Goal")
+                    .borders(Borders::ALL)
+                    .style(Style::default().fg(Color::White)),
+            ),
+        chunks[2],
+    );
+}
+
+fn hhmmss(seconds: i64) -> String {
+    format_instant_to_hhmmss(chrono::Duration::seconds(seconds))
+}
+
+fn draw_session_tags(app: &App) -> Tabs<'_> {
+    let titles = app
+        .session_tags
+        .iter()
+        .map(|tag| Spans::from(tag.as_str()))
+        .collect();
+
+    Tabs::new(titles)
+        .select(app.session_tag_index)
+        .block(
+            Block::default()
+                .title("Session tags (? for help)")
+                .borders(Borders::ALL),
+        )
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().fg(Color::Black).bg(Color::LightCyan))
 }
 
 fn draw_overview<'a>(overview: String) -> Paragraph<'a> {
@@ -79,23 +267,89 @@ fn draw_overview<'a>(overview: String) -> Paragraph<'a> {
         )
 }
 
-fn draw_tags<'a>(tags: String) -> Paragraph<'a> {
+fn draw_tags<'a>(tags: String, title: String) -> Paragraph<'a> {
     Paragraph::new(tags)
         .style(Style::default().fg(Color::LightCyan))
         .alignment(Alignment::Center)
         .block(
             Block::default()
-                .title("Tags")
+                .title(title)
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::White)),
         )
 }
 
-fn draw_barchart<'a>(data: &'a Vec<(&'a str, u64)>) -> BarChart<'a> {
-    BarChart::default()
-        .block(Block::default().title("Durations").borders(Borders::ALL))
-        .data(&data)
-        .bar_width(9)
-        .bar_style(Style::default().fg(Color::LightCyan))
-        .value_style(Style::default().fg(Color::Black).bg(Color::LightCyan))
+fn draw_help<'a>() -> Paragraph<'a> {
+    Paragraph::new(HELP)
+        .style(Style::default().fg(Color::LightCyan))
+        .block(
+            Block::default()
+                .title("Help")
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White)),
+        )
+}
+
+fn draw_sessions<B: Backend>(f: &mut Frame<B>, app: &mut App, area: Rect) {
+    // Tags are colored in name order so a tag keeps its color as the range changes
+    let mut tags: Vec<&str> = app
+        .session_tag_times
+        .iter()
+        .map(|tag_time| tag_time.value.as_str())
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+    let tag_color = |tag: &str| {
+        let i = tags.binary_search(&tag).unwrap();
+        TAG_COLORS[i % TAG_COLORS.len()]
+    };
+
+    let tag_times = match app.stacked {
+        true => &app.session_tag_times[..],
+        false => &[],
+    };
+    let bars = session_chart::bars(&app.sessions, tag_times, tag_color);
+
+    let mut title = vec![Span::raw(format!(
+        "Durations ({}) ",
+        app.range.title(app.today())
+    ))];
+    if app.stacked {
+        for tag in &tags {
+            title.push(Span::styled(
+                format!("■ {} ", tag),
+                Style::default().fg(tag_color(tag)),
+            ));
+        }
+    }
+
+    let chart = SessionChart::new(&bars)
+        .block(
+            Block::default()
+                .title(Spans::from(title))
+                .borders(Borders::ALL),
+        )
+        .select(app.selected_session);
+    f.render_stateful_widget(chart, area, &mut app.chart);
+}
+
+// A rect taking up the given percentages of `r`, centered in it
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
 }
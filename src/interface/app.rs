@@ -0,0 +1,591 @@
+use super::calendar;
+use super::session_chart::SessionChartState;
+use crate::config::daily_goal;
+use crate::daemon;
+use crate::daemon::format_instant_to_hhmmss;
+use crate::database;
+use crate::session::Status;
+use chrono::{Datelike, Duration, Local, Months, NaiveDate};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use termion::event::Key;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangeKind {
+    All,
+    Week,
+    Month,
+}
+
+// A window of sessions to show, relative to today: an offset of 0 is the current week or month,
+// -1 the one before it, and so on
+#[derive(Debug)]
+pub struct DateRange {
+    pub kind: RangeKind,
+    pub offset: i32,
+}
+
+impl DateRange {
+    // Half-open [since, until) span of local dates, or None when showing all time
+    pub fn bounds(&self, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+        match self.kind {
+            RangeKind::All => None,
+            RangeKind::Week => {
+                let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                let since = monday + Duration::weeks(self.offset as i64);
+                Some((since, since + Duration::weeks(1)))
+            }
+            RangeKind::Month => {
+                let first = today.with_day(1).unwrap();
+                let since = if self.offset < 0 {
+                    first - Months::new(self.offset.unsigned_abs())
+                } else {
+                    first + Months::new(self.offset as u32)
+                };
+                Some((since, since + Months::new(1)))
+            }
+        }
+    }
+
+    pub fn title(&self, today: NaiveDate) -> String {
+        match self.bounds(today) {
+            None => "all time".to_string(),
+            Some((since, until)) => match self.kind {
+                RangeKind::Month => since.format("%B %Y").to_string(),
+                _ => format!(
+                    "{} - {}",
+                    since.format("%b %d"),
+                    (until - Duration::days(1)).format("%b %d")
+                ),
+            },
+        }
+    }
+
+    fn next_kind(&mut self) {
+        self.kind = match self.kind {
+            RangeKind::All => RangeKind::Week,
+            RangeKind::Week => RangeKind::Month,
+            RangeKind::Month => RangeKind::All,
+        };
+        self.offset = 0;
+    }
+
+    fn previous_kind(&mut self) {
+        self.kind = match self.kind {
+            RangeKind::All => RangeKind::Month,
+            RangeKind::Week => RangeKind::All,
+            RangeKind::Month => RangeKind::Week,
+        };
+        self.offset = 0;
+    }
+}
+
+// What the main pane shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Sessions,
+    Calendar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptKind {
+    Tag,
+    Add,
+    Subtract,
+    Complete,
+}
+
+impl PromptKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            PromptKind::Tag => "Switch to tag",
+            PromptKind::Add => "Minutes to add",
+            PromptKind::Subtract => "Minutes to subtract",
+            PromptKind::Complete => "Complete session with session tag",
+        }
+    }
+}
+
+// Text being typed in for a command to the daemon
+#[derive(Debug)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub text: String,
+}
+
+// Everything the interface shows, refreshed from the database and the daemon on each tick
+pub struct App {
+    pub session_tags: Vec<String>,
+    pub session_tag_index: usize,
+    pub range: DateRange,
+    pub sessions: Vec<database::TotalTimeAsSeconds>,
+    pub session_tag_times: Vec<database::SessionTagTime>,
+    pub selected_session: Option<usize>,
+    pub chart: SessionChartState,
+    // Whether session bars are split up by tag
+    pub stacked: bool,
+    pub view: View,
+    // Seconds gained per day over the calendar's year
+    pub daily_times: HashMap<NaiveDate, i64>,
+    pub selected_day: NaiveDate,
+    // Seconds, per `[goals]` in the config
+    pub daily_goal: i64,
+    pub overview: String,
+    pub tags: String,
+    // None when the daemon isn't running
+    pub status: Option<Status>,
+    // Completed sessions and the running one, combined
+    pub todays_tags: Vec<(String, i64)>,
+    // Seconds gained in sessions completed today, the way the daemon counts them for the goal
+    pub completed_today: i64,
+    pub prompt: Option<Prompt>,
+    pub error: Option<String>,
+    pub show_help: bool,
+    pub should_quit: bool,
+}
+
+impl App {
+    pub fn new(session_tag: String) -> App {
+        App {
+            session_tags: vec![session_tag],
+            session_tag_index: 0,
+            range: DateRange {
+                kind: RangeKind::All,
+                offset: 0,
+            },
+            sessions: Vec::new(),
+            session_tag_times: Vec::new(),
+            selected_session: None,
+            chart: SessionChartState { offset: usize::MAX },
+            stacked: false,
+            view: View::Sessions,
+            daily_times: HashMap::new(),
+            selected_day: Local::now().date_naive(),
+            daily_goal: daily_goal(),
+            overview: String::new(),
+            tags: String::new(),
+            status: None,
+            todays_tags: Vec::new(),
+            completed_today: 0,
+            prompt: None,
+            error: None,
+            show_help: false,
+            should_quit: false,
+        }
+    }
+
+    pub fn session_tag(&self) -> &str {
+        &self.session_tags[self.session_tag_index]
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        Local::now().date_naive()
+    }
+
+    pub fn refresh(&mut self) {
+        let session_tag = self.session_tag().to_string();
+
+        // Keep whichever session tag we were looking at selected, even as new ones show up
+        let mut session_tags = database::get_session_tags().unwrap();
+        if !session_tags.contains(&session_tag) {
+            session_tags.push(session_tag.clone());
+        }
+        self.session_tag_index = session_tags
+            .iter()
+            .position(|tag| tag == &session_tag)
+            .unwrap();
+        self.session_tags = session_tags;
+
+        self.overview = database::get_lifetime_overview(&session_tag).unwrap()[0].to_string();
+        let range = self.range.bounds(self.today());
+        self.sessions = database::get_total_time_as_seconds(&session_tag, range).unwrap();
+        self.session_tag_times = database::get_session_tag_times(&session_tag, range).unwrap();
+
+        self.selected_session = match self.selected_session {
+            Some(_) if self.sessions.is_empty() => None,
+            Some(i) => Some(i.min(self.sessions.len() - 1)),
+            None => None,
+        };
+
+        self.tags = match (self.view, self.selected_session) {
+            (View::Calendar, _) => self.selected_day_durations(&session_tag),
+            (View::Sessions, Some(i)) => {
+                database::get_session_tags_pane(self.sessions[i].id).unwrap()
+            }
+            (View::Sessions, None) => database::get_tags_pane(&session_tag).unwrap(),
+        };
+
+        if self.view == View::Calendar {
+            self.daily_times =
+                database::get_daily_time(&session_tag, calendar::first_day(self.today()))
+                    .unwrap()
+                    .into_iter()
+                    .map(|daily_time| (daily_time.date, daily_time.seconds))
+                    .collect();
+        }
+
+        // The status of a daemon that's gone is of a session that was lost with it
+        self.status = daemon::get_status()
+            .unwrap()
+            .filter(|_| daemon::is_running());
+
+        let mut todays_tags: Vec<(String, i64)> = database::get_todays_tags()
+            .unwrap()
+            .into_iter()
+            .map(|tag| (tag.value, tag.seconds))
+            .collect();
+        if let Some(status) = &self.status {
+            for (tag, seconds) in &status.tags {
+                match todays_tags.iter_mut().find(|(value, _)| value == tag) {
+                    Some((_, total)) => *total += seconds,
+                    None => todays_tags.push((tag.clone(), *seconds)),
+                }
+            }
+        }
+        todays_tags.sort_by_key(|(_, seconds)| Reverse(*seconds));
+        self.todays_tags = todays_tags;
+        self.completed_today = database::get_todays_time_gained().unwrap();
+    }
+
+    // Towards the daily goal: completed sessions and the running one
+    pub fn time_gained_today(&self) -> i64 {
+        self.completed_today + self.status.as_ref().map_or(0, |status| status.time_gained)
+    }
+
+    pub fn on_key(&mut self, key: Key) {
+        if self.handle_key(key) {
+            self.refresh();
+        }
+    }
+
+    // Returns whether what's shown needs refreshing
+    fn handle_key(&mut self, key: Key) -> bool {
+        if self.prompt.is_some() {
+            self.on_prompt_key(key);
+            return false;
+        }
+
+        if self.show_help {
+            // Any key dismisses help, but q still quits
+            self.show_help = false;
+            if key != Key::Char('q') {
+                return false;
+            }
+        }
+
+        match key {
+            Key::Char('q') | Key::Ctrl('c') => self.should_quit = true,
+            Key::Char('?') => self.show_help = true,
+            Key::Char('\t') => {
+                self.session_tag_index = (self.session_tag_index + 1) % self.session_tags.len();
+                self.show_latest_sessions();
+            }
+            Key::BackTab => {
+                self.session_tag_index = (self.session_tag_index + self.session_tags.len() - 1)
+                    % self.session_tags.len();
+                self.show_latest_sessions();
+            }
+            Key::Left if self.range.kind != RangeKind::All => {
+                self.range.offset -= 1;
+                self.show_latest_sessions();
+            }
+            Key::Right if self.range.kind != RangeKind::All => {
+                self.range.offset += 1;
+                self.show_latest_sessions();
+            }
+            Key::Up => {
+                self.range.next_kind();
+                self.show_latest_sessions();
+            }
+            Key::Down => {
+                self.range.previous_kind();
+                self.show_latest_sessions();
+            }
+            Key::Char('v') => {
+                self.view = match self.view {
+                    View::Sessions => View::Calendar,
+                    View::Calendar => View::Sessions,
+                }
+            }
+            Key::Char('h') if self.view == View::Calendar => self.select_day(-7),
+            Key::Char('l') if self.view == View::Calendar => self.select_day(7),
+            Key::Char('k') if self.view == View::Calendar => self.select_day(-1),
+            Key::Char('j') if self.view == View::Calendar => self.select_day(1),
+            Key::Esc if self.view == View::Calendar => self.selected_day = self.today(),
+            Key::Char('l') => self.select_next_session(),
+            Key::Char('h') => self.select_previous_session(),
+            Key::Esc => self.selected_session = None,
+            Key::Char('[') => self.chart.offset = self.chart.offset.saturating_sub(1),
+            Key::Char(']') => self.chart.offset = self.chart.offset.saturating_add(1),
+            Key::Char('s') => self.stacked = !self.stacked,
+            Key::Char(' ') => self.toggle(),
+            Key::Char('t') => self.open_prompt(PromptKind::Tag, String::new()),
+            Key::Char('+') => self.open_prompt(PromptKind::Add, String::new()),
+            Key::Char('-') => self.open_prompt(PromptKind::Subtract, String::new()),
+            Key::Char('c') => {
+                let session_tag = self.session_tag().to_string();
+                self.open_prompt(PromptKind::Complete, session_tag)
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind, text: String) {
+        self.error = None;
+        self.prompt = Some(Prompt { kind, text });
+    }
+
+    fn on_prompt_key(&mut self, key: Key) {
+        let prompt = self.prompt.as_mut().unwrap();
+
+        match key {
+            Key::Char('\n') => {
+                let prompt = self.prompt.take().unwrap();
+                self.submit(prompt);
+            }
+            Key::Char(c) => prompt.text.push(c),
+            Key::Backspace => {
+                prompt.text.pop();
+            }
+            Key::Esc | Key::Ctrl('c') => self.prompt = None,
+            _ => (),
+        }
+    }
+
+    fn submit(&mut self, prompt: Prompt) {
+        let text = prompt.text.trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+
+        let result = match prompt.kind {
+            PromptKind::Tag => daemon::switch_tag(text),
+            // The daemon asserts on anything that isn't a whole number of minutes
+            PromptKind::Add | PromptKind::Subtract if text.parse::<u64>().is_err() => {
+                self.error = Some(format!("{} isn't a number of minutes", text));
+                return;
+            }
+            PromptKind::Add => daemon::add_minutes(text),
+            PromptKind::Subtract => daemon::subtract_minutes(text),
+            PromptKind::Complete => daemon::trigger_session_completion(text),
+        };
+
+        if let Err(e) = result {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    // Pauses the running duration, or resumes the last tag; with nothing to resume, asks for one
+    fn toggle(&mut self) {
+        self.error = None;
+
+        let status = self
+            .status
+            .as_ref()
+            .map(|status| (status.active, status.tag.clone()));
+
+        let result = match status {
+            Some((true, _)) => daemon::trigger_time(None),
+            Some((false, Some(tag))) => daemon::trigger_time(Some(tag)),
+            Some((false, None)) => {
+                self.open_prompt(PromptKind::Tag, String::new());
+                return;
+            }
+            None => {
+                self.error = Some("the daemon isn't running; start it with pt -d".to_string());
+                return;
+            }
+        };
+
+        if let Err(e) = result {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    // Moves the calendar's selection, staying within the year it shows
+    fn select_day(&mut self, days: i64) {
+        let today = self.today();
+        self.selected_day =
+            (self.selected_day + Duration::days(days)).clamp(calendar::first_day(today), today);
+    }
+
+    fn selected_day_durations(&self, session_tag: &str) -> String {
+        let day = self.selected_day;
+        let mut durations = format!("{}\n\n", day.format("%a %b %d %Y"));
+
+        for duration in
+            database::get_durations(session_tag, Some((day, day + Duration::days(1)))).unwrap()
+        {
+            let end_at = match duration.end_at {
+                Some(end_at) => end_at.with_timezone(&Local).format("%H:%M").to_string(),
+                None => "?".to_string(),
+            };
+            durations.push_str(&format!(
+                "{}-{} {} :: {}\n",
+                duration.begin_at.with_timezone(&Local).format("%H:%M"),
+                end_at,
+                duration.tag.unwrap_or_default(),
+                format_instant_to_hhmmss(Duration::seconds(duration.seconds))
+            ));
+        }
+
+        durations
+    }
+
+    // Clears the selection and scrolls the chart to its end
+    fn show_latest_sessions(&mut self) {
+        self.selected_session = None;
+        self.chart.offset = usize::MAX;
+    }
+
+    fn select_next_session(&mut self) {
+        if self.sessions.is_empty() {
+            return;
+        }
+
+        self.selected_session = match self.selected_session {
+            Some(i) if i + 1 < self.sessions.len() => Some(i + 1),
+            Some(i) => Some(i),
+            None => Some(0),
+        };
+    }
+
+    fn select_previous_session(&mut self) {
+        if self.sessions.is_empty() {
+            return;
+        }
+
+        self.selected_session = match self.selected_session {
+            Some(i) => Some(i.saturating_sub(1)),
+            None => Some(self.sessions.len() - 1),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn range(kind: RangeKind, offset: i32) -> DateRange {
+        DateRange { kind, offset }
+    }
+
+    #[test]
+    fn weeks_and_months_cross_into_the_year_before() {
+        // A Thursday, in a week that started in December
+        let new_years_day = date(2026, 1, 1);
+        assert_eq!(
+            range(RangeKind::Week, 0).bounds(new_years_day),
+            Some((date(2025, 12, 29), date(2026, 1, 5)))
+        );
+        assert_eq!(
+            range(RangeKind::Week, 0).title(new_years_day),
+            "Dec 29 - Jan 04"
+        );
+        assert_eq!(
+            range(RangeKind::Week, -1).bounds(new_years_day),
+            Some((date(2025, 12, 22), date(2025, 12, 29)))
+        );
+
+        assert_eq!(
+            range(RangeKind::Month, -1).bounds(date(2026, 1, 31)),
+            Some((date(2025, 12, 1), date(2026, 1, 1)))
+        );
+        assert_eq!(
+            range(RangeKind::Month, -1).title(date(2026, 1, 31)),
+            "December 2025"
+        );
+        assert_eq!(
+            range(RangeKind::Month, 2).bounds(date(2025, 12, 15)),
+            Some((date(2026, 2, 1), date(2026, 3, 1)))
+        );
+        assert_eq!(range(RangeKind::All, -3).bounds(new_years_day), None);
+    }
+
+    #[test]
+    fn paging_moves_by_week_or_month_and_switching_starts_over() {
+        let mut app = App::new("pt".to_string());
+
+        // There's nothing to page through in all time
+        assert!(!app.handle_key(Key::Left));
+        assert_eq!(app.range.offset, 0);
+
+        app.handle_key(Key::Up);
+        assert_eq!(app.range.kind, RangeKind::Week);
+        app.handle_key(Key::Left);
+        app.handle_key(Key::Left);
+        app.handle_key(Key::Right);
+        assert_eq!(app.range.offset, -1);
+
+        app.handle_key(Key::Up);
+        assert_eq!(app.range.kind, RangeKind::Month);
+        assert_eq!(app.range.offset, 0);
+        app.handle_key(Key::Down);
+        app.handle_key(Key::Down);
+        assert_eq!(app.range.kind, RangeKind::All);
+    }
+
+    #[test]
+    fn the_goal_counts_todays_completed_sessions_and_the_running_one() {
+        let mut app = App::new("pt".to_string());
+        app.completed_today = 3600;
+        assert_eq!(app.time_gained_today(), 3600);
+
+        app.status = Some(Status {
+            updated_at: 0,
+            active: true,
+            tag: Some("writing".to_string()),
+            current_duration: 600,
+            time_gained: 900,
+            tags: vec![("writing".to_string(), 900)],
+            durations: Vec::new(),
+        });
+        assert_eq!(app.time_gained_today(), 4500);
+    }
+
+    #[test]
+    fn selections_stay_in_bounds() {
+        let mut app = App::new("pt".to_string());
+        let today = app.today();
+
+        // No sessions, nothing to select
+        app.handle_key(Key::Char('l'));
+        assert_eq!(app.selected_session, None);
+
+        app.sessions = (0..2)
+            .map(|id| database::TotalTimeAsSeconds {
+                id,
+                total_time: 60,
+                completed_at: None,
+            })
+            .collect();
+        app.handle_key(Key::Char('h'));
+        assert_eq!(app.selected_session, Some(1));
+        app.handle_key(Key::Char('l'));
+        assert_eq!(app.selected_session, Some(1));
+        app.handle_key(Key::Char('h'));
+        app.handle_key(Key::Char('h'));
+        assert_eq!(app.selected_session, Some(0));
+        app.handle_key(Key::Esc);
+        assert_eq!(app.selected_session, None);
+
+        app.handle_key(Key::Char('v'));
+        app.handle_key(Key::Char('j'));
+        assert_eq!(app.selected_day, today);
+        app.handle_key(Key::Char('k'));
+        assert_eq!(app.selected_day, today - Duration::days(1));
+
+        let first_day = calendar::first_day(today);
+        app.selected_day = first_day + Duration::days(3);
+        app.handle_key(Key::Char('h'));
+        assert_eq!(app.selected_day, first_day);
+        app.handle_key(Key::Esc);
+        assert_eq!(app.selected_day, today);
+    }
+}
@@ -0,0 +1,182 @@
+// A year of days laid out like GitHub's contribution calendar: one column per week, Monday at the
+// top, each day shaded by how close it came to the daily goal
+use chrono::{Datelike, Duration, NaiveDate};
+use std::collections::HashMap;
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Block, Widget};
+
+pub const WEEKS: i64 = 53;
+
+// Room on the left for weekday labels
+const LABEL_WIDTH: u16 = 4;
+// Each day is a square and a space
+const DAY_WIDTH: u16 = 2;
+
+// From no time at all up to reaching the goal
+const SHADES: [Color; 5] = [
+    Color::DarkGray,
+    Color::Indexed(23),
+    Color::Indexed(30),
+    Color::Indexed(37),
+    Color::LightCyan,
+];
+
+// The Monday starting the first week shown for a calendar ending on `today`
+pub fn first_day(today: NaiveDate) -> NaiveDate {
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    monday - Duration::weeks(WEEKS - 1)
+}
+
+pub struct Calendar<'a> {
+    block: Option<Block<'a>>,
+    // Seconds gained per day
+    days: &'a HashMap<NaiveDate, i64>,
+    today: NaiveDate,
+    selected: NaiveDate,
+    goal: i64,
+}
+
+impl<'a> Calendar<'a> {
+    pub fn new(
+        days: &'a HashMap<NaiveDate, i64>,
+        today: NaiveDate,
+        selected: NaiveDate,
+        goal: i64,
+    ) -> Calendar<'a> {
+        Calendar {
+            block: None,
+            days,
+            today,
+            selected,
+            goal,
+        }
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Calendar<'a> {
+        self.block = Some(block);
+        self
+    }
+}
+
+// A shade for each quarter of the way to the goal, and one for none at all
+fn shade(seconds: i64, goal: i64) -> Color {
+    if seconds <= 0 {
+        return SHADES[0];
+    }
+
+    let quarters = seconds * 4 / goal.max(1);
+    SHADES[(quarters as usize + 1).min(SHADES.len() - 1)]
+}
+
+impl<'a> Widget for Calendar<'a> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(b) => {
+                let inner_area = b.inner(area);
+                b.render(area, buf);
+                inner_area
+            }
+            None => area,
+        };
+
+        // A row of month labels above the seven weekdays
+        if area.height < 8 || area.width <= LABEL_WIDTH {
+            return;
+        }
+
+        // When the whole year doesn't fit, the most recent weeks win
+        let weeks = (((area.width - LABEL_WIDTH) / DAY_WIDTH) as i64).min(WEEKS);
+        let first_day = first_day(self.today) + Duration::weeks(WEEKS - weeks);
+        let label_style = Style::default().fg(Color::White);
+
+        for (row, weekday) in [(0, "Mon"), (2, "Wed"), (4, "Fri")] {
+            buf.set_string(area.left(), area.top() + 1 + row, weekday, label_style);
+        }
+
+        let mut last_month = None;
+        for week in 0..weeks {
+            let x = area.left() + LABEL_WIDTH + week as u16 * DAY_WIDTH;
+            let monday = first_day + Duration::weeks(week);
+
+            // Label a month over the first week that starts in it, skipping a month that's only
+            // partly shown in the first column
+            if last_month != Some(monday.month()) {
+                if x + 3 <= area.right() && (week > 0 || monday.day() <= 7) {
+                    buf.set_string(x, area.top(), monday.format("%b").to_string(), label_style);
+                }
+                last_month = Some(monday.month());
+            }
+
+            for weekday in 0..7 {
+                let day = monday + Duration::days(weekday);
+                if day > self.today {
+                    break;
+                }
+
+                let seconds = self.days.get(&day).copied().unwrap_or(0);
+                let mut style = Style::default().fg(shade(seconds, self.goal));
+                let symbol = if day == self.selected {
+                    style = style.add_modifier(Modifier::REVERSED);
+                    "▣"
+                } else {
+                    "■"
+                };
+
+                buf.set_string(x, area.top() + 1 + weekday as u16, symbol, style);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn starts_on_the_monday_a_year_of_weeks_back() {
+        // A Monday, and the Sunday at the end of its week
+        assert_eq!(first_day(date(2026, 10, 19)), date(2025, 10, 20));
+        assert_eq!(first_day(date(2026, 10, 25)), date(2025, 10, 20));
+        assert_eq!(first_day(date(2026, 1, 1)), date(2024, 12, 30));
+    }
+
+    #[test]
+    fn shades_by_quarters_of_the_goal() {
+        let goal = 4 * 60 * 60;
+        assert_eq!(shade(0, goal), SHADES[0]);
+        assert_eq!(shade(-300, goal), SHADES[0]);
+        assert_eq!(shade(60, goal), SHADES[1]);
+        assert_eq!(shade(goal / 4, goal), SHADES[2]);
+        assert_eq!(shade(goal * 3 / 4 - 1, goal), SHADES[3]);
+        assert_eq!(shade(goal, goal), SHADES[4]);
+        assert_eq!(shade(goal * 2, goal), SHADES[4]);
+        // No goal still shades days that had time
+        assert_eq!(shade(60, 0), SHADES[4]);
+    }
+
+    #[test]
+    fn the_most_recent_weeks_win_when_the_year_doesnt_fit() {
+        let today = date(2026, 10, 21);
+        let days = HashMap::from([(today, 60), (date(2026, 10, 12), 0)]);
+        // Room for the labels and 3 weeks
+        let area = Rect::new(0, 0, LABEL_WIDTH + 3 * DAY_WIDTH, 8);
+        let mut buf = Buffer::empty(area);
+        Calendar::new(&days, today, today, 4 * 60 * 60).render(area, &mut buf);
+
+        // Wednesday of the last week is today, and Thursday's still to come
+        let last_week = LABEL_WIDTH + 2 * DAY_WIDTH;
+        assert_eq!(buf.get(last_week, 3).symbol, "▣");
+        assert_eq!(buf.get(last_week, 3).fg, SHADES[1]);
+        assert_eq!(buf.get(last_week, 4).symbol, " ");
+        // The first column is two weeks back, the week of Oct 5
+        assert_eq!(buf.get(LABEL_WIDTH, 1).symbol, "■");
+        assert_eq!(buf.get(LABEL_WIDTH, 1).fg, SHADES[0]);
+        assert_eq!(buf.get(0, 1).symbol, "M");
+    }
+}
@@ -0,0 +1,45 @@
+use std::io::stdin;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use termion::event::Key;
+use termion::input::TermRead;
+
+pub enum Event {
+    Input(Key),
+    Tick,
+}
+
+// Keys are read on their own thread so the interface can keep refreshing on ticks, rather than
+// blocking on stdin until the next keypress
+pub struct Events {
+    rx: mpsc::Receiver<Event>,
+}
+
+impl Events {
+    pub fn new(tick_rate: Duration) -> Events {
+        let (tx, rx) = mpsc::channel();
+
+        let input_tx = tx.clone();
+        thread::spawn(move || {
+            for key in stdin().keys().flatten() {
+                if input_tx.send(Event::Input(key)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        thread::spawn(move || loop {
+            if tx.send(Event::Tick).is_err() {
+                return;
+            }
+            thread::sleep(tick_rate);
+        });
+
+        Events { rx }
+    }
+
+    pub fn next(&self) -> Result<Event, mpsc::RecvError> {
+        self.rx.recv()
+    }
+}
@@ -0,0 +1,312 @@
+// A bar chart of sessions, modeled on tui's BarChart, which can't label values as anything but
+// raw numbers, stack bars, or scroll once there are more bars than fit
+use crate::database::{SessionTagTime, TotalTimeAsSeconds};
+use tui::buffer::Buffer;
+use tui::layout::Rect;
+use tui::style::{Color, Modifier, Style};
+use tui::symbols;
+use tui::widgets::{Block, StatefulWidget, Widget};
+
+// One session; segments are stacked bottom to top, in seconds
+pub struct Bar {
+    pub label: String,
+    pub segments: Vec<(u64, Color)>,
+}
+
+impl Bar {
+    fn total(&self) -> u64 {
+        self.segments.iter().map(|(seconds, _)| seconds).sum()
+    }
+}
+
+// A bar for each session, split into a segment per tag for the tags' times given
+pub fn bars(
+    sessions: &[TotalTimeAsSeconds],
+    tag_times: &[SessionTagTime],
+    tag_color: impl Fn(&str) -> Color,
+) -> Vec<Bar> {
+    sessions
+        .iter()
+        .map(|session| {
+            // Bars can't go below zero, so sessions that lost time show as empty
+            let total_time = session.total_time.max(0) as u64;
+
+            let mut segments: Vec<(u64, Color)> = tag_times
+                .iter()
+                .filter(|tag_time| tag_time.session_id == session.id)
+                .map(|tag_time| (tag_time.seconds.max(0) as u64, tag_color(&tag_time.value)))
+                .collect();
+            // Whatever isn't attributed to a tag (additions, subtractions, or everything when
+            // not stacking) goes on top
+            let tagged: u64 = segments.iter().map(|(seconds, _)| seconds).sum();
+            if total_time > tagged {
+                segments.push((total_time - tagged, Color::LightCyan));
+            }
+
+            let label = match session.completed_on() {
+                Some(date) => date.format("%m/%d").to_string(),
+                None => format!("#{}", session.id),
+            };
+
+            Bar { label, segments }
+        })
+        .collect()
+}
+
+// Which bar is the first one on screen; render keeps it in bounds and keeps the selected bar
+// visible, so callers can set it to usize::MAX to show the most recent sessions
+#[derive(Debug, Default)]
+pub struct SessionChartState {
+    pub offset: usize,
+}
+
+pub struct SessionChart<'a> {
+    block: Option<Block<'a>>,
+    bars: &'a [Bar],
+    selected: Option<usize>,
+    bar_width: u16,
+    bar_gap: u16,
+}
+
+impl<'a> SessionChart<'a> {
+    pub fn new(bars: &'a [Bar]) -> SessionChart<'a> {
+        SessionChart {
+            block: None,
+            bars,
+            selected: None,
+            bar_width: 9,
+            bar_gap: 1,
+        }
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> SessionChart<'a> {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn select(mut self, selected: Option<usize>) -> SessionChart<'a> {
+        self.selected = selected;
+        self
+    }
+
+    // How many bars fit in a chart of the given width
+    fn visible_bars(&self, width: u16) -> usize {
+        ((width + self.bar_gap) / (self.bar_width + self.bar_gap)) as usize
+    }
+}
+
+impl<'a> StatefulWidget for SessionChart<'a> {
+    type State = SessionChartState;
+
+    fn render(mut self, area: Rect, buf: &mut Buffer, state: &mut SessionChartState) {
+        let chart_area = match self.block.take() {
+            Some(b) => {
+                let inner_area = b.inner(area);
+                b.render(area, buf);
+                inner_area
+            }
+            None => area,
+        };
+
+        if chart_area.height < 3 {
+            return;
+        }
+
+        let visible = self.visible_bars(chart_area.width);
+        state.offset = scroll(state.offset, self.selected, visible, self.bars.len());
+
+        // Scale against every bar, not just the visible ones, so heights don't jump while scrolling
+        let max = self.bars.iter().map(Bar::total).max().unwrap_or(0).max(1);
+        // The bottom two rows are for values and labels
+        let bar_rows = u64::from(chart_area.height - 2);
+
+        for (i, bar) in self
+            .bars
+            .iter()
+            .enumerate()
+            .skip(state.offset)
+            .take(visible)
+        {
+            let left =
+                chart_area.left() + (i - state.offset) as u16 * (self.bar_width + self.bar_gap);
+
+            let segments = segment_tops(bar, bar_rows, max);
+            let height = segments.last().map(|(top, _)| *top).unwrap_or(0);
+
+            for row in 0..bar_rows {
+                let bottom = row * 8;
+                if bottom >= height {
+                    break;
+                }
+
+                let symbol = match height - bottom {
+                    1 => symbols::bar::ONE_EIGHTH,
+                    2 => symbols::bar::ONE_QUARTER,
+                    3 => symbols::bar::THREE_EIGHTHS,
+                    4 => symbols::bar::HALF,
+                    5 => symbols::bar::FIVE_EIGHTHS,
+                    6 => symbols::bar::THREE_QUARTERS,
+                    7 => symbols::bar::SEVEN_EIGHTHS,
+                    _ => symbols::bar::FULL,
+                };
+                // Each cell takes the color of whichever segment its bottom edge falls in
+                let color = segments
+                    .iter()
+                    .find(|(top, _)| *top > bottom)
+                    .map(|(_, color)| *color)
+                    .unwrap_or(Color::LightCyan);
+
+                let y = chart_area.bottom() - 3 - row as u16;
+                for x in 0..self.bar_width {
+                    buf.get_mut(left + x, y)
+                        .set_symbol(symbol)
+                        .set_style(Style::default().fg(color));
+                }
+            }
+
+            let value = format_hours(bar.total());
+            let value_width = value.chars().count() as u16;
+            if value_width <= self.bar_width {
+                buf.set_string(
+                    left + (self.bar_width - value_width) / 2,
+                    chart_area.bottom() - 2,
+                    value,
+                    Style::default().fg(Color::White),
+                );
+            }
+
+            let label_style = if self.selected == Some(i) {
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::LightCyan)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            buf.set_stringn(
+                left,
+                chart_area.bottom() - 1,
+                format!("{:^width$}", bar.label, width = self.bar_width as usize),
+                self.bar_width as usize,
+                label_style,
+            );
+        }
+    }
+}
+
+// The first bar to show, keeping the selected bar on screen and not scrolling past the last bar
+fn scroll(offset: usize, selected: Option<usize>, visible: usize, bars: usize) -> usize {
+    let mut offset = offset;
+    if let Some(selected) = selected {
+        if selected < offset {
+            offset = selected;
+        } else if selected >= offset.saturating_add(visible) {
+            offset = selected + 1 - visible;
+        }
+    }
+    offset.min(bars.saturating_sub(visible))
+}
+
+// Where each segment's top is, stacked from the bottom, in eighths of a row to match the partial
+// block symbols. `max` is the biggest bar's total, which fills all `bar_rows`.
+fn segment_tops(bar: &Bar, bar_rows: u64, max: u64) -> Vec<(u64, Color)> {
+    let mut top = 0;
+    bar.segments
+        .iter()
+        .map(|(seconds, color)| {
+            top += seconds;
+            (top * bar_rows * 8 / max, *color)
+        })
+        .collect()
+}
+
+fn format_hours(seconds: u64) -> String {
+    format!("{:.1}h", seconds as f64 / 60.0 / 60.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: u64, total_time: i64, completed_at: Option<&str>) -> TotalTimeAsSeconds {
+        TotalTimeAsSeconds {
+            id,
+            total_time,
+            completed_at: completed_at.map(str::to_string),
+        }
+    }
+
+    fn tag_time(session_id: u64, value: &str, seconds: i64) -> SessionTagTime {
+        SessionTagTime {
+            session_id,
+            value: value.to_string(),
+            seconds,
+        }
+    }
+
+    #[test]
+    fn stacks_tags_under_whatever_isnt_tagged() {
+        let sessions = [
+            // Midday UTC is the same date nearly everywhere
+            session(1, 5400, Some("2026-10-19 12:00:00")),
+            session(2, -600, None),
+        ];
+        let tag_times = [
+            tag_time(1, "reading", 1800),
+            tag_time(1, "writing", 2700),
+            tag_time(2, "reading", 300),
+        ];
+        let tag_color = |tag: &str| match tag {
+            "reading" => Color::Yellow,
+            _ => Color::Magenta,
+        };
+
+        let bars = bars(&sessions, &tag_times, tag_color);
+        assert_eq!(bars[0].label, "10/19");
+        assert_eq!(
+            bars[0].segments,
+            [
+                (1800, Color::Yellow),
+                (2700, Color::Magenta),
+                (900, Color::LightCyan)
+            ]
+        );
+        // Sessions that lost time have nothing to show past their tags
+        assert_eq!(bars[1].label, "#2");
+        assert_eq!(bars[1].segments, [(300, Color::Yellow)]);
+
+        let unstacked = super::bars(&sessions, &[], tag_color);
+        assert_eq!(unstacked[0].segments, [(5400, Color::LightCyan)]);
+        assert!(unstacked[1].segments.is_empty());
+        assert_eq!(format_hours(5400), "1.5h");
+    }
+
+    #[test]
+    fn scrolls_to_keep_the_selection_in_view() {
+        // 10 bars, 4 on screen
+        assert_eq!(scroll(usize::MAX, None, 4, 10), 6);
+        assert_eq!(scroll(2, None, 4, 10), 2);
+        assert_eq!(scroll(6, Some(1), 4, 10), 1);
+        assert_eq!(scroll(0, Some(5), 4, 10), 2);
+        // Everything fits
+        assert_eq!(scroll(usize::MAX, Some(1), 4, 3), 0);
+
+        let chart = SessionChart::new(&[]);
+        // Bars are 9 wide with a gap of 1, and the last one needs no gap
+        assert_eq!(chart.visible_bars(39), 4);
+        assert_eq!(chart.visible_bars(38), 3);
+    }
+
+    #[test]
+    fn segments_stack_against_the_biggest_bar() {
+        let bar = Bar {
+            label: String::new(),
+            segments: vec![(1800, Color::Yellow), (900, Color::LightCyan)],
+        };
+        // The biggest bar's an hour over 4 rows, or 32 eighths
+        assert_eq!(
+            segment_tops(&bar, 4, 3600),
+            [(16, Color::Yellow), (24, Color::LightCyan)]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+// Issue-tracker keys, e.g. ABC-123, pulled out of durations' tags and their sessions' notes with
+// the regexes in the `[issues]` table of the config, and saved on each duration. Patterns are tried
+// in order, on the tag before the note, and the first match (or its first group, if the pattern
+// has one) is the key.
+use crate::config::get_config;
+use crate::database::{backfill_issues, get_issue_sources, set_issues};
+use dirs::home_dir;
+use regex::Regex;
+use std::fs::{read_to_string, write};
+use std::io::{Error, ErrorKind};
+
+// The patterns every duration's key was last taken with
+fn patterns_filepath() -> String {
+    home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer/issue-patterns"
+}
+
+// The patterns in the config
+pub fn patterns() -> Result<Vec<Regex>, Error> {
+    get_config()?
+        .issues
+        .patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Problem with the issue pattern {}: {}", pattern, e),
+                )
+            })
+        })
+        .collect()
+}
+
+pub fn extract(patterns: &[Regex], tag: Option<&str>, note: Option<&str>) -> Option<String> {
+    tag.into_iter().chain(note).find_map(|text| {
+        patterns.iter().find_map(|pattern| {
+            let captures = pattern.captures(text)?;
+            let key = captures.get(1).or_else(|| captures.get(0))?;
+            Some(key.as_str().to_string())
+        })
+    })
+}
+
+// Extracts the keys again for a session's durations, e.g. after its note changes. Returns how many
+// durations' keys changed.
+pub fn refresh(session_id: u64) -> Result<usize, Error> {
+    let patterns = patterns()?;
+    let issues: Vec<(i64, Option<String>)> = get_issue_sources(session_id)
+        .map_err(Error::other)?
+        .into_iter()
+        .map(|source| {
+            let issue = extract(&patterns, source.tag.as_deref(), source.note.as_deref());
+            (source.id, issue)
+        })
+        .collect();
+    set_issues(&issues).map_err(Error::other)
+}
+
+// Takes every duration's key again when the patterns have changed since it was last done. The first
+// time, that's durations from before keys were kept, which have none. Returns how many durations'
+// keys changed.
+pub fn backfill() -> Result<usize, Error> {
+    let taken_with = get_config()?.issues.patterns.join("\n");
+    if read_to_string(patterns_filepath()).is_ok_and(|last| last == taken_with) {
+        return Ok(0);
+    }
+
+    let patterns = patterns()?;
+    let changed =
+        backfill_issues(|tag, note| extract(&patterns, tag, note)).map_err(Error::other)?;
+    write(patterns_filepath(), taken_with)?;
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_first_key_from_the_tag_then_the_note() {
+        let patterns = vec![
+            Regex::new(r"\b[A-Z][A-Z0-9]+-\d+\b").unwrap(),
+            Regex::new(r"#(\d+)").unwrap(),
+        ];
+        let extract = |tag, note| extract(&patterns, tag, note);
+
+        assert_eq!(
+            extract(Some("reading ABC-123"), None),
+            Some("ABC-123".to_string())
+        );
+        assert_eq!(
+            extract(Some("review"), Some("Fixed OPS-7, then OPS-8")),
+            Some("OPS-7".to_string())
+        );
+        assert_eq!(
+            extract(Some("fix #42"), Some("ABC-1")),
+            Some("42".to_string())
+        );
+        assert_eq!(extract(Some("reading"), Some("abc-123")), None);
+        assert_eq!(extract(None, None), None);
+    }
+}
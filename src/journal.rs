@@ -0,0 +1,387 @@
+// A day's or week's time gained, written for daily notes: as org-mode headings with CLOCK lines (so
+// org's clock tables can sum them) or as a Markdown section. Each section is wrapped in begin/end
+// comments, so appending to a notes file again replaces it rather than adding it twice.
+use crate::daemon::format_instant_to_hhmmss;
+use crate::database::{get_session_durations, SessionDuration};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, Utc};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, rename, write};
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Org,
+    Markdown,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Option<Format> {
+        match format {
+            "org" => Some(Format::Org),
+            "markdown" | "md" => Some(Format::Markdown),
+            _ => None,
+        }
+    }
+}
+
+// A duration in local time
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    begin_at: NaiveDateTime,
+    end_at: NaiveDateTime,
+    seconds: i64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct SessionTag {
+    seconds: i64,
+    // By tag, with "untagged" for durations without one
+    tags: BTreeMap<String, Vec<Span>>,
+    // Session ids and notes, for sessions that have them
+    notes: Vec<(u64, String)>,
+}
+
+// `date` is the day, or any day of the week (Monday to Sunday), to write up. Without `append`, it's
+// written to stdout.
+pub fn journal(
+    format: Format,
+    date: NaiveDate,
+    week: bool,
+    append: Option<&str>,
+) -> Result<(), Error> {
+    let (since, until, title) = if week {
+        let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+        (
+            monday,
+            monday + Duration::days(7),
+            format!("week of {}", monday),
+        )
+    } else {
+        (date, date + Duration::days(1), date.to_string())
+    };
+
+    let durations = get_session_durations(None, Some(since), Some(until)).map_err(Error::other)?;
+    let session_tags = group(&durations, |at| at.with_timezone(&Local).naive_local());
+    let section = match format {
+        Format::Org => render_org(&title, &session_tags),
+        Format::Markdown => render_markdown(&title, &session_tags, week),
+    };
+    let section = wrap(format, &title, &section);
+
+    match append {
+        Some(filepath) => {
+            let contents = match read_to_string(filepath) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e),
+            };
+            let tmp_filepath = filepath.to_string() + ".tmp";
+            write(
+                &tmp_filepath,
+                replace_or_append(&contents, &section, format, &title),
+            )?;
+            rename(tmp_filepath, filepath)?;
+            println!("Wrote the journal for {} to {}", title, filepath);
+        }
+        None => print!("{}", section),
+    }
+    Ok(())
+}
+
+fn group<F>(durations: &[SessionDuration], to_local: F) -> BTreeMap<String, SessionTag>
+where
+    F: Fn(DateTime<Utc>) -> NaiveDateTime,
+{
+    let mut session_tags: BTreeMap<String, SessionTag> = BTreeMap::new();
+
+    for duration in durations {
+        let begin_at = match duration.begin_at {
+            Some(begin_at) => begin_at,
+            None => continue,
+        };
+        let session_tag = session_tags
+            .entry(
+                duration
+                    .session_tag
+                    .clone()
+                    .unwrap_or_else(|| "untagged".to_string()),
+            )
+            .or_default();
+
+        session_tag.seconds += duration.seconds;
+        session_tag
+            .tags
+            .entry(
+                duration
+                    .tag
+                    .clone()
+                    .unwrap_or_else(|| "untagged".to_string()),
+            )
+            .or_default()
+            .push(Span {
+                begin_at: to_local(begin_at),
+                end_at: to_local(duration.end_at.unwrap_or(begin_at)),
+                seconds: duration.seconds,
+            });
+
+        if let Some(note) = &duration.session_note {
+            if !session_tag
+                .notes
+                .iter()
+                .any(|(session_id, _)| *session_id == duration.session_id)
+            {
+                session_tag.notes.push((duration.session_id, note.clone()));
+            }
+        }
+    }
+
+    session_tags
+}
+
+fn total(session_tags: &BTreeMap<String, SessionTag>) -> i64 {
+    session_tags
+        .values()
+        .map(|session_tag| session_tag.seconds)
+        .sum()
+}
+
+// Org's own H:MM, as used after `=>` in CLOCK lines
+fn org_duration(seconds: i64) -> String {
+    let sign = if seconds < 0 { "-" } else { "" };
+    let minutes = seconds.abs() / 60;
+    format!("{}{}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+fn render_org(title: &str, session_tags: &BTreeMap<String, SessionTag>) -> String {
+    let timestamp = |at: NaiveDateTime| at.format("[%Y-%m-%d %a %H:%M]").to_string();
+
+    let mut lines = vec![format!(
+        "* Time gained, {}: {}",
+        title,
+        org_duration(total(session_tags))
+    )];
+    for (name, session_tag) in session_tags {
+        lines.push(format!(
+            "** {}: {}",
+            name,
+            org_duration(session_tag.seconds)
+        ));
+        for (session_id, note) in &session_tag.notes {
+            lines.push(format!("- Session {}: {}", session_id, note));
+        }
+        for (tag, spans) in &session_tag.tags {
+            let seconds = spans.iter().map(|span| span.seconds).sum();
+            lines.push(format!("*** {}: {}", tag, org_duration(seconds)));
+            for span in spans {
+                // Org can't clock negative time, so subtractions are noted instead
+                if span.seconds < 0 {
+                    lines.push(format!(
+                        "- Subtracted {} at {}",
+                        org_duration(-span.seconds),
+                        timestamp(span.begin_at)
+                    ));
+                } else {
+                    lines.push(format!(
+                        "CLOCK: {}--{} => {:>5}",
+                        timestamp(span.begin_at),
+                        timestamp(span.end_at),
+                        org_duration(span.seconds)
+                    ));
+                }
+            }
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+fn render_markdown(title: &str, session_tags: &BTreeMap<String, SessionTag>, week: bool) -> String {
+    let hhmmss = |seconds| format_instant_to_hhmmss(Duration::seconds(seconds));
+    let time = |at: NaiveDateTime| {
+        if week {
+            at.format("%a %H:%M").to_string()
+        } else {
+            at.format("%H:%M").to_string()
+        }
+    };
+
+    let mut lines = vec![format!(
+        "## Time gained, {}: {}",
+        title,
+        hhmmss(total(session_tags))
+    )];
+    if session_tags.is_empty() {
+        lines.push(String::new());
+        lines.push("Nothing recorded.".to_string());
+    }
+    for (name, session_tag) in session_tags {
+        lines.push(String::new());
+        lines.push(format!("### {}: {}", name, hhmmss(session_tag.seconds)));
+        lines.push(String::new());
+        for (tag, spans) in &session_tag.tags {
+            let seconds = spans.iter().map(|span| span.seconds).sum();
+            let spans: Vec<String> = spans
+                .iter()
+                .map(|span| {
+                    if span.seconds < 0 {
+                        format!("{} subtracted", hhmmss(-span.seconds))
+                    } else {
+                        format!("{}–{}", time(span.begin_at), time(span.end_at))
+                    }
+                })
+                .collect();
+            lines.push(format!(
+                "- {}: {} ({})",
+                tag,
+                hhmmss(seconds),
+                spans.join(", ")
+            ));
+        }
+        if !session_tag.notes.is_empty() {
+            lines.push("- Notes:".to_string());
+            for (session_id, note) in &session_tag.notes {
+                lines.push(format!("  - Session {}: {}", session_id, note));
+            }
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+fn markers(format: Format, title: &str) -> (String, String) {
+    match format {
+        Format::Org => (
+            format!("# pt-journal begin {}", title),
+            format!("# pt-journal end {}", title),
+        ),
+        Format::Markdown => (
+            format!("<!-- pt-journal begin {} -->", title),
+            format!("<!-- pt-journal end {} -->", title),
+        ),
+    }
+}
+
+fn wrap(format: Format, title: &str, section: &str) -> String {
+    let (begin, end) = markers(format, title);
+    format!("{}\n{}{}\n", begin, section, end)
+}
+
+// Swaps out the section for the same day or week if it's already there, leaving everything around
+// it alone; otherwise adds it to the end
+fn replace_or_append(contents: &str, section: &str, format: Format, title: &str) -> String {
+    let (begin, end) = markers(format, title);
+
+    if let Some(start) = contents.find(&(begin + "\n")) {
+        if let Some(length) = contents[start..].find(&(end.clone() + "\n")) {
+            let finish = start + length + end.len() + 1;
+            return format!("{}{}{}", &contents[..start], section, &contents[finish..]);
+        }
+    }
+
+    let separator = match contents {
+        "" => "",
+        contents if contents.ends_with("\n\n") => "",
+        contents if contents.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    format!("{}{}{}", contents, separator, section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn duration(
+        session_id: u64,
+        session_tag: &str,
+        tag: Option<&str>,
+        hour: u32,
+        minutes: i64,
+    ) -> SessionDuration {
+        let begin_at = Utc.with_ymd_and_hms(2026, 10, 19, hour, 0, 0).unwrap();
+        SessionDuration {
+            uuid: format!("{}-{}", session_id, hour),
+            session_id,
+            session_tag: Some(session_tag.to_string()),
+            session_note: Some(format!("note for {}", session_id)),
+            tag: tag.map(|tag| tag.to_string()),
+            seconds: minutes * 60,
+            begin_at: Some(begin_at),
+            end_at: Some(begin_at + Duration::minutes(minutes.max(0))),
+            repo: None,
+            branch: None,
+            issue: None,
+        }
+    }
+
+    fn session_tags() -> BTreeMap<String, SessionTag> {
+        group(
+            &[
+                duration(1, "work", Some("review"), 9, 30),
+                duration(1, "work", Some("review"), 10, 15),
+                duration(1, "work", None, 11, -10),
+                duration(2, "pt", Some("writing"), 13, 90),
+            ],
+            |at| at.naive_utc(),
+        )
+    }
+
+    #[test]
+    fn renders_org_clock_lines() {
+        assert_eq!(
+            render_org("2026-10-19", &session_tags()),
+            "* Time gained, 2026-10-19: 2:05
+** pt: 1:30
+- Session 2: note for 2
+*** writing: 1:30
+CLOCK: [2026-10-19 Mon 13:00]--[2026-10-19 Mon 14:30] =>  1:30
+** work: 0:35
+- Session 1: note for 1
+*** review: 0:45
+CLOCK: [2026-10-19 Mon 09:00]--[2026-10-19 Mon 09:30] =>  0:30
+CLOCK: [2026-10-19 Mon 10:00]--[2026-10-19 Mon 10:15] =>  0:15
+*** untagged: -0:10
+- Subtracted 0:10 at [2026-10-19 Mon 11:00]
+"
+        );
+    }
+
+    #[test]
+    fn renders_markdown() {
+        assert_eq!(
+            render_markdown("2026-10-19", &session_tags(), false),
+            "## Time gained, 2026-10-19: 02:05:00
+
+### pt: 01:30:00
+
+- writing: 01:30:00 (13:00–14:30)
+- Notes:
+  - Session 2: note for 2
+
+### work: 00:35:00
+
+- review: 00:45:00 (09:00–09:30, 10:00–10:15)
+- untagged: -00:10:00 (00:10:00 subtracted)
+- Notes:
+  - Session 1: note for 1
+"
+        );
+    }
+
+    #[test]
+    fn appending_again_replaces_the_section() {
+        let notes = "# Monday\n\nSome notes\n";
+        let first = wrap(Format::Markdown, "2026-10-19", "first\n");
+        let second = wrap(Format::Markdown, "2026-10-19", "second\n");
+
+        let appended = replace_or_append(notes, &first, Format::Markdown, "2026-10-19");
+        assert_eq!(appended, format!("{}\n{}", notes, first));
+
+        let appended = appended + "\nMore notes\n";
+        assert_eq!(
+            replace_or_append(&appended, &second, Format::Markdown, "2026-10-19"),
+            format!("{}\n{}\nMore notes\n", notes, second)
+        );
+    }
+}
@@ -1,12 +1,33 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 
 mod analytics;
+mod api;
+mod backup;
+mod calendar;
+mod config;
+mod credentials;
+mod crypto;
 mod daemon;
 mod database;
+mod events;
+mod export;
+mod git;
+mod hooks;
+mod idle;
+mod import;
 mod interface;
+mod issues;
+mod journal;
+mod metrics;
 mod oauth;
 mod pt_duration;
+mod report;
+mod restore;
 mod session;
+mod snapshots;
+mod sync;
+#[cfg(test)]
+mod test_server;
 
 fn main() {
     let matches = App::new("Productivity Timer")
@@ -30,7 +51,7 @@ fn main() {
             Arg::with_name("print")
                 .short("p")
                 .long("print")
-                .help("Prints from two places, either `db` for what's been saved or `tmp` for what's in /var/tmp/productivity-timer/time-gained.")
+                .help("Prints the running session's time gained.")
         )
         .arg(
             Arg::with_name("interface")
@@ -72,13 +93,248 @@ fn main() {
             Arg::with_name("backup")
                 .short("b")
                 .long("backup")
-                .help("Back up database to Google Drive. Requires a `.env` with API_KEY, GOOGLE_CLIENT_ID, and GOOGLE_CLIENT_SECRET.")
+                .help("Back up the database with the configured backup backend; same as `pt backup`.")
         )
         .arg(
             Arg::with_name("sync")
                 .short("y")
                 .long("sync")
-                .help("Syncs local database to what's been backed up in Google Drive. Requires a `.env` with API_KEY, GOOGLE_CLIENT_ID, and GOOGLE_CLIENT_SECRET.")
+                .help("Merges the backup into the local database and uploads the result; same as `pt sync`.")
+        )
+        .subcommand(
+            SubCommand::with_name("backup")
+                .about("Backs up the database to Google Drive, a directory, or a WebDAV server, as set in ~/.productivity-timer/config.toml.")
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists what's been backed up.")
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Shows the backup schedule and when backing up last worked and failed.")
+                )
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .about("Deletes a backup.")
+                        .arg(Arg::with_name("name").required(true))
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Downloads the backup, checks it over, shows how it differs from the local database, and swaps it in. The old database is kept in ~/.productivity-timer/snapshots.")
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Doesn't ask before replacing the local database.")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .about("Merges the backup into the local database, session by session, and uploads the result, so machines sharing a backup don't overwrite each other.")
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes every duration, with its session, to stdout as CSV, JSON, iCalendar events, or worklogs for issue trackers.")
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["csv", "json", "ics", "worklog"])
+                        .default_value("csv")
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .help("Only durations that began on or after this date, as YYYY-MM-DD.")
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .takes_value(true)
+                        .help("Only durations that began on or before this date, as YYYY-MM-DD.")
+                )
+                .arg(
+                    Arg::with_name("session-tag")
+                        .long("session-tag")
+                        .takes_value(true)
+                        .help("Only durations from sessions with this tag.")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Totals the time gained in completed sessions by session tag, tag, day, git repo or branch, or issue.")
+                .arg(
+                    Arg::with_name("by")
+                        .short("b")
+                        .long("by")
+                        .takes_value(true)
+                        .possible_values(report::By::NAMES)
+                        .default_value("session_tag")
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .help("Only durations that began on or after this date, as YYYY-MM-DD.")
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .takes_value(true)
+                        .help("Only durations that began on or before this date, as YYYY-MM-DD.")
+                )
+                .arg(
+                    Arg::with_name("session-tag")
+                        .long("session-tag")
+                        .takes_value(true)
+                        .help("Only durations from sessions with this tag.")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Adds history from Timewarrior, Toggl's detailed CSV export, or any CSV described in the config, as a session per session tag per day. Durations already in the database are skipped.")
+                .arg(
+                    Arg::with_name("source")
+                        .required(true)
+                        .possible_values(&["timewarrior", "toggl", "csv"])
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .help("The file to import, or Timewarrior's data directory.")
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Shows what would be imported without importing it.")
+                )
+                .arg(
+                    Arg::with_name("session-tag")
+                        .long("session-tag")
+                        .takes_value(true)
+                        .help("The session tag for entries with no project (or Timewarrior tags); they're skipped without one.")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("journal")
+                .about("Writes up a day's or week's time gained, per session tag and tag, for daily notes in org-mode or Markdown.")
+                .arg(
+                    Arg::with_name("date")
+                        .long("date")
+                        .takes_value(true)
+                        .help("The day to write up, as YYYY-MM-DD; today if left out.")
+                )
+                .arg(
+                    Arg::with_name("week")
+                        .short("w")
+                        .long("week")
+                        .help("Writes up the week (Monday to Sunday) the date is in.")
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["org", "markdown", "md"])
+                        .default_value("markdown")
+                )
+                .arg(
+                    Arg::with_name("append")
+                        .short("a")
+                        .long("append")
+                        .takes_value(true)
+                        .help("Adds it to the end of this file instead of printing it, or replaces it if it's already there.")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("calendar")
+                .about("Lists today's busy events from the calendar files in the config, and what's done about each.")
+        )
+        .subcommand(
+            SubCommand::with_name("git-hook")
+                .about("Puts the time spent on a repo's branch since the last commit in commit messages, as a trailer.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("install")
+                        .about("Adds a prepare-commit-msg hook to the repo you're in.")
+                )
+                .subcommand(
+                    SubCommand::with_name("run")
+                        .about("What the hook runs, with the arguments git gives it.")
+                        .arg(Arg::with_name("message-file").required(true))
+                        .arg(Arg::with_name("source"))
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("sessions")
+                .about("Manages saved sessions.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .about("Deletes a session and its durations, here and, once they sync, on other machines.")
+                        .arg(Arg::with_name("id").required(true))
+                )
+                .subcommand(
+                    SubCommand::with_name("note")
+                        .about("Notes something about a session, replacing any note it had.")
+                        .arg(
+                            Arg::with_name("id")
+                                .required(true)
+                                .help("The session's id, or `last` for the one completed most recently.")
+                        )
+                        .arg(Arg::with_name("note").required(true))
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("snapshots")
+                .about("Manages the local copies of the database taken daily and before restores, migrations and imports.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists snapshots, newest first.")
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("Replaces the local database with a snapshot, after checking it over.")
+                        .arg(Arg::with_name("name").required(true))
+                        .arg(
+                            Arg::with_name("yes")
+                                .short("y")
+                                .long("yes")
+                                .help("Doesn't ask before replacing the local database.")
+                        )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("auth")
+                .about("Manages the Google credentials used for backups.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("login")
+                        .about("Saves client credentials, if they aren't saved yet, and gets a new token through the browser or another device.")
+                        .arg(
+                            Arg::with_name("encrypt")
+                                .short("e")
+                                .long("encrypt")
+                                .help("Encrypts the token file with a passphrase. Set PT_TOKEN_PASSPHRASE to skip the prompt, e.g. for the daemon.")
+                        )
+                        .arg(
+                            Arg::with_name("device")
+                                .long("device")
+                                .help("Logs in by entering a code on another device instead of opening a browser here, e.g. over SSH.")
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("logout")
+                        .about("Revokes the token with Google and deletes it.")
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Shows whether you're logged in, without printing any secrets.")
+                )
         )
         .get_matches();
 
@@ -90,13 +346,14 @@ fn main() {
     let subtracting_minutes = matches.is_present("subtract");
     let completing_session = matches.is_present("complete");
     let tag_time = matches.is_present("tag-time");
-    let backing_up = matches.is_present("backup");
-    let syncing = matches.is_present("sync");
+    // NB is_present would also be true for the `backup` subcommand
+    let backing_up = matches.occurrences_of("backup") > 0;
+    let syncing = matches.occurrences_of("sync") > 0;
 
     if completing_session {
         let tag = matches.value_of("complete").unwrap().to_string();
         daemon::trigger_session_completion(tag).unwrap();
-        let times = database::get_times();
+        let times = database::get_times().unwrap();
         for time in times {
             println!("gained time: {:?}", time);
         }
@@ -134,8 +391,6 @@ fn main() {
     if tag_time {
         let tag = matches.value_of("tag-time").unwrap().to_string();
         daemon::print_tags(tag).unwrap();
-        //let tag_time = database::get_tag_time(&tag).unwrap();
-        //println!("{}: {}", tag, tag_time);
     }
 
     if daemonizing {
@@ -148,11 +403,156 @@ fn main() {
     }
 
     if backing_up {
-        database::backup().unwrap();
+        backup::backup().unwrap();
+    }
+
+    if syncing || matches.subcommand_matches("sync").is_some() {
+        sync::sync(true).unwrap();
+    }
+
+    if let Some(matches) = matches.subcommand_matches("export") {
+        // In case the issue patterns have changed since the daemon last took the keys
+        if let Err(e) = issues::backfill() {
+            eprintln!("Error taking issue keys, {}", e);
+        }
+        let date = |name| {
+            matches.value_of(name).map(|date| {
+                chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .unwrap_or_else(|_| panic!("--{} should be YYYY-MM-DD", name))
+            })
+        };
+        export::export(
+            export::Format::parse(matches.value_of("format").unwrap()).unwrap(),
+            matches.value_of("session-tag"),
+            date("since"),
+            date("until"),
+        )
+        .unwrap();
+    }
+
+    if let Some(matches) = matches.subcommand_matches("report") {
+        // In case the issue patterns have changed since the daemon last took the keys
+        if let Err(e) = issues::backfill() {
+            eprintln!("Error taking issue keys, {}", e);
+        }
+        let date = |name| {
+            matches.value_of(name).map(|date| {
+                chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .unwrap_or_else(|_| panic!("--{} should be YYYY-MM-DD", name))
+            })
+        };
+        report::print(
+            report::By::parse(matches.value_of("by").unwrap()).unwrap(),
+            matches.value_of("session-tag"),
+            date("since"),
+            date("until"),
+        )
+        .unwrap();
+    }
+
+    if let Some(matches) = matches.subcommand_matches("import") {
+        import::import(
+            import::Source::parse(matches.value_of("source").unwrap()).unwrap(),
+            matches.value_of("path").unwrap(),
+            matches.value_of("session-tag"),
+            matches.is_present("dry-run"),
+        )
+        .unwrap();
     }
 
-    if syncing {
-        // TODO add a prompt for user confirmation--this will wipe the local db
-        database::sync_local_to_remote().unwrap();
+    if let Some(matches) = matches.subcommand_matches("journal") {
+        let date = match matches.value_of("date") {
+            Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .expect("--date should be YYYY-MM-DD"),
+            None => chrono::Local::now().date_naive(),
+        };
+        journal::journal(
+            journal::Format::parse(matches.value_of("format").unwrap()).unwrap(),
+            date,
+            matches.is_present("week"),
+            matches.value_of("append"),
+        )
+        .unwrap();
+    }
+
+    if matches.subcommand_matches("calendar").is_some() {
+        calendar::print_today().unwrap();
+    }
+
+    if let Some(matches) = matches.subcommand_matches("git-hook") {
+        match matches.subcommand() {
+            ("install", _) => println!("Installed {}", git::install_hook().unwrap()),
+            ("run", Some(matches)) => {
+                let message_file = matches.value_of("message-file").unwrap();
+                // Printed rather than panicking, since it's in the middle of someone's commit
+                if let Err(e) = git::run_hook(message_file, matches.value_of("source")) {
+                    eprintln!("pt: {}", e);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("sessions") {
+        match matches.subcommand() {
+            ("delete", Some(matches)) => {
+                let id = matches
+                    .value_of("id")
+                    .unwrap()
+                    .parse()
+                    .expect("The id should be a number");
+                sync::delete_session(id).unwrap()
+            }
+            ("note", Some(matches)) => {
+                let id = match matches.value_of("id").unwrap() {
+                    "last" => None,
+                    id => Some(id.parse().expect("The id should be a number or `last`")),
+                };
+                match database::set_session_note(id, matches.value_of("note").unwrap()).unwrap() {
+                    Some(id) => {
+                        // The note may have an issue key the tags don't
+                        issues::refresh(id).unwrap();
+                        println!("Noted session {}", id)
+                    }
+                    None => println!("There's no such session"),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("restore") {
+        restore::restore(matches.is_present("yes")).unwrap();
+    }
+
+    if let Some(matches) = matches.subcommand_matches("backup") {
+        match matches.subcommand() {
+            ("list", _) => backup::list().unwrap(),
+            ("status", _) => backup::print_status().unwrap(),
+            ("delete", Some(matches)) => backup::delete(matches.value_of("name").unwrap()).unwrap(),
+            _ => backup::backup().unwrap(),
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("snapshots") {
+        match matches.subcommand() {
+            ("list", _) => snapshots::list().unwrap(),
+            ("restore", Some(matches)) => {
+                snapshots::restore(matches.value_of("name").unwrap(), matches.is_present("yes"))
+                    .unwrap()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("auth") {
+        match matches.subcommand() {
+            ("login", Some(matches)) => {
+                oauth::login(matches.is_present("encrypt"), matches.is_present("device")).unwrap()
+            }
+            ("logout", _) => oauth::logout().unwrap(),
+            ("status", _) => oauth::status().unwrap(),
+            _ => unreachable!(),
+        }
     }
 }
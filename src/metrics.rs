@@ -0,0 +1,290 @@
+// Prometheus metrics for dashboards, served by the daemon at `/metrics` on the `listen` address in
+// the `[metrics]` table of the config. The running session's numbers come from the daemon's loop;
+// today's totals, completed sessions and backups from the database and the backup
+// status file. There's no token, so it only listens on 127.0.0.1 unless told otherwise.
+use crate::api::{read_request, write_response, Response, READ_TIMEOUT};
+use crate::backup;
+use crate::backup::BackupStatus;
+use crate::daemon::{ask, Command, Request as DaemonRequest};
+use crate::database::{get_completed_sessions, get_todays_tags, SessionTagSummary};
+use crate::session::Status;
+use chrono::{DateTime, Local, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::Error;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread::spawn;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Everything the metrics are made from, read fresh for each scrape
+struct Sources {
+    // None if the daemon's loop didn't answer
+    status: Option<Status>,
+    today: Vec<SessionTagSummary>,
+    // Tags' time in sessions completed today
+    todays_tags: Vec<(String, i64)>,
+    completed: Vec<SessionTagSummary>,
+    backup: BackupStatus,
+}
+
+pub fn serve(listen: &str, requests: Sender<DaemonRequest>) -> Result<(), Error> {
+    let listener = TcpListener::bind(listen)?;
+    println!("Serving metrics on http://{}/metrics", listen);
+
+    spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let requests = requests.clone();
+                    spawn(move || handle_connection(stream, &requests));
+                }
+                Err(e) => eprintln!("Error accepting a metrics connection, {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, requests: &Sender<DaemonRequest>) {
+    if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+        eprintln!("Error setting up a metrics connection, {}", e);
+        return;
+    }
+    let response = match read_request(&stream) {
+        Ok(request) if (request.method.as_str(), request.path.as_str()) == ("GET", "/metrics") => {
+            match gather(requests) {
+                Ok(sources) => Response {
+                    status: "200 OK",
+                    content_type: CONTENT_TYPE,
+                    body: render(&sources),
+                },
+                Err(e) => {
+                    eprintln!("Error gathering metrics, {}", e);
+                    Response::error("500 Internal Server Error", &e.to_string())
+                }
+            }
+        }
+        Ok(_) => Response::error("404 Not Found", "Metrics are at GET /metrics"),
+        Err(e) => Response::error("400 Bad Request", &e.to_string()),
+    };
+    write_response(&mut stream, &response);
+}
+
+fn gather(requests: &Sender<DaemonRequest>) -> Result<Sources, Error> {
+    let today = Local::now().date_naive();
+    Ok(Sources {
+        status: ask(requests, Command::Status)
+            .and_then(|status| serde_json::from_value(status).ok()),
+        today: get_completed_sessions(Some(today)).map_err(Error::other)?,
+        todays_tags: get_todays_tags()
+            .map_err(Error::other)?
+            .into_iter()
+            .map(|tag| (tag.value, tag.seconds))
+            .collect(),
+        completed: get_completed_sessions(None).map_err(Error::other)?,
+        backup: backup::get_status()?,
+    })
+}
+
+fn render(sources: &Sources) -> String {
+    let mut out = String::new();
+    let status = sources.status.as_ref();
+    let session_tag = |summary: &SessionTagSummary| {
+        summary
+            .tag
+            .clone()
+            .unwrap_or_else(|| "untagged".to_string())
+    };
+
+    metric(
+        &mut out,
+        "pt_active",
+        "gauge",
+        "Whether a duration is running",
+        [(None, status.is_some_and(|status| status.active) as i64)],
+    );
+    metric(
+        &mut out,
+        "pt_current_duration_seconds",
+        "gauge",
+        "How long the running duration has been going",
+        [(None, status.map_or(0, |status| status.current_duration))],
+    );
+    metric(
+        &mut out,
+        "pt_session_time_gained_seconds",
+        "gauge",
+        "Time gained in the session that hasn't been completed yet",
+        [(None, status.map_or(0, |status| status.time_gained))],
+    );
+
+    metric(
+        &mut out,
+        "pt_time_gained_today_seconds",
+        "gauge",
+        "Time gained in sessions completed today, by session tag",
+        sources.today.iter().map(|summary| {
+            (
+                Some(("session_tag", session_tag(summary))),
+                summary.total_seconds,
+            )
+        }),
+    );
+
+    // Completed sessions' tags, and the running session's, like the interface shows
+    let mut tags: BTreeMap<String, i64> = sources.todays_tags.iter().cloned().collect();
+    for (tag, seconds) in status.map_or(&[][..], |status| &status.tags) {
+        let tag = match tag.as_str() {
+            "" => "untagged".to_string(),
+            tag => tag.to_string(),
+        };
+        *tags.entry(tag).or_default() += seconds;
+    }
+    metric(
+        &mut out,
+        "pt_tag_time_gained_today_seconds",
+        "gauge",
+        "Time gained today by tag, including the running session",
+        tags.into_iter()
+            .map(|(tag, seconds)| (Some(("tag", tag)), seconds)),
+    );
+
+    // A count of what's in the database, which goes down when sessions are deleted, so not a counter
+    metric(
+        &mut out,
+        "pt_sessions_completed",
+        "gauge",
+        "Sessions completed, by session tag",
+        sources.completed.iter().map(|summary| {
+            (
+                Some(("session_tag", session_tag(summary))),
+                summary.sessions,
+            )
+        }),
+    );
+
+    let timestamp = |at: Option<DateTime<Utc>>| at.map_or(0, |at| at.timestamp());
+    metric(
+        &mut out,
+        "pt_backup_last_success_timestamp_seconds",
+        "gauge",
+        "When a backup last succeeded, or 0 if one never has",
+        [(None, timestamp(sources.backup.last_success_at))],
+    );
+    metric(
+        &mut out,
+        "pt_backup_last_failure_timestamp_seconds",
+        "gauge",
+        "When a backup last failed, or 0 if one never has",
+        [(None, timestamp(sources.backup.last_error_at))],
+    );
+    metric(
+        &mut out,
+        "pt_backup_failures",
+        "gauge",
+        "Backups that have failed since the last success",
+        [(None, sources.backup.failures as i64)],
+    );
+
+    out
+}
+
+// Writes the HELP and TYPE lines, then a sample for each label and value
+fn metric<I>(out: &mut String, name: &str, kind: &str, help: &str, samples: I)
+where
+    I: IntoIterator<Item = (Option<(&'static str, String)>, i64)>,
+{
+    // Writing to a String can't fail
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+    for (label, value) in samples {
+        match label {
+            Some((label, label_value)) => writeln!(
+                out,
+                "{}{{{}=\"{}\"}} {}",
+                name,
+                label,
+                escape_label_value(&label_value),
+                value
+            ),
+            None => writeln!(out, "{} {}", name, value),
+        }
+        .unwrap();
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn renders_the_running_session_with_todays_totals() {
+        let sources = Sources {
+            status: Some(Status {
+                updated_at: 0,
+                active: true,
+                tag: Some("writing".to_string()),
+                current_duration: 90,
+                time_gained: 600,
+                tags: vec![("writing".to_string(), 600)],
+                durations: Vec::new(),
+            }),
+            today: vec![SessionTagSummary {
+                tag: Some("pt \"core\"".to_string()),
+                sessions: 2,
+                total_seconds: 3600,
+            }],
+            todays_tags: vec![("review".to_string(), 1200), ("writing".to_string(), 2400)],
+            completed: vec![
+                SessionTagSummary {
+                    tag: None,
+                    sessions: 1,
+                    total_seconds: 60,
+                },
+                SessionTagSummary {
+                    tag: Some("pt \"core\"".to_string()),
+                    sessions: 5,
+                    total_seconds: 9000,
+                },
+            ],
+            backup: BackupStatus {
+                last_success_at: Some(Utc.with_ymd_and_hms(2026, 10, 19, 3, 0, 0).unwrap()),
+                ..Default::default()
+            },
+        };
+
+        let rendered = render(&sources);
+        let samples: Vec<&str> = rendered
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+
+        assert_eq!(
+            samples,
+            [
+                "pt_active 1",
+                "pt_current_duration_seconds 90",
+                "pt_session_time_gained_seconds 600",
+                "pt_time_gained_today_seconds{session_tag=\"pt \\\"core\\\"\"} 3600",
+                "pt_tag_time_gained_today_seconds{tag=\"review\"} 1200",
+                "pt_tag_time_gained_today_seconds{tag=\"writing\"} 3000",
+                "pt_sessions_completed{session_tag=\"untagged\"} 1",
+                "pt_sessions_completed{session_tag=\"pt \\\"core\\\"\"} 5",
+                "pt_backup_last_success_timestamp_seconds 1792378800",
+                "pt_backup_last_failure_timestamp_seconds 0",
+                "pt_backup_failures 0",
+            ]
+        );
+        assert!(rendered.contains("# TYPE pt_sessions_completed gauge\n"));
+    }
+}
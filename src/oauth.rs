@@ -1,137 +1,736 @@
-use dirs::home_dir;
-use dotenv;
+use crate::config::{get_config, Flow};
+use crate::credentials::{self, ClientCredentials, ClientCredentialsSource};
+use chrono::{DateTime, Duration, Local, Utc};
+use oauth2::basic::{BasicClient, BasicErrorResponseType, BasicTokenResponse};
+use oauth2::devicecode::StandardDeviceAuthorizationResponse;
 use oauth2::reqwest::http_client;
 use oauth2::url::Url;
-use oauth2::{basic::BasicClient, TokenResponse};
 use oauth2::{
-    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge, RedirectUrl,
-    RevocationUrl, Scope, TokenUrl,
+    AccessToken, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
+    DeviceAuthorizationUrl, ErrorResponse, PkceCodeChallenge, RedirectUrl, RefreshToken,
+    RequestTokenError, RevocationUrl, Scope, StandardRevocableToken, TokenResponse, TokenUrl,
 };
-use std::fs::{read_to_string, write};
-use std::io::{BufRead, BufReader, Error, Write};
-use std::net::TcpListener;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{stdin, stdout, BufRead, BufReader, Error, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
 
-fn get_token_from_file() -> Result<String, Error> {
-    let token_filepath =
-        home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer" + "/token";
-    read_to_string(&token_filepath)
+// Tokens are refreshed this long before they expire, so one doesn't lapse mid-request
+const EXPIRY_MARGIN_SECONDS: i64 = 60;
+// How long to wait on a connection from the browser before moving on to the next
+const REQUEST_TIMEOUT_SECONDS: u64 = 10;
+const DRIVE_SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
+
+// Everything from the token response worth keeping, saved as JSON in the token file
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredToken {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+    scopes: Vec<String>,
+}
+
+impl StoredToken {
+    fn from_response(token_response: &BasicTokenResponse) -> StoredToken {
+        StoredToken {
+            access_token: token_response.access_token().secret().to_string(),
+            refresh_token: token_response
+                .refresh_token()
+                .map(|refresh_token| refresh_token.secret().to_string()),
+            expires_at: token_response
+                .expires_in()
+                .and_then(|expires_in| Duration::from_std(expires_in).ok())
+                .map(|expires_in| Utc::now() + expires_in),
+            scopes: token_response
+                .scopes()
+                .map(|scopes| scopes.iter().map(|scope| scope.to_string()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => Utc::now() + Duration::seconds(EXPIRY_MARGIN_SECONDS) >= expires_at,
+            None => false,
+        }
+    }
 }
 
+fn get_token_from_file() -> Result<StoredToken, Error> {
+    let token = credentials::get_token()?;
+
+    match serde_json::from_str(&token) {
+        Ok(token) => Ok(token),
+        // Token files used to hold only a quoted access token. Keep using it until it's rejected,
+        // at which point there's no refresh token, so we'll go through the browser again.
+        Err(_) => Ok(StoredToken {
+            access_token: token.trim().trim_matches('"').to_string(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: Vec::new(),
+        }),
+    }
+}
+
+fn save_token(token: &StoredToken, encrypt: bool) -> Result<(), Error> {
+    credentials::save_token(&serde_json::to_string_pretty(token)?, encrypt)
+}
+
+// An access token that's good for now, refreshing or re-authenticating as needed
 pub fn get_token() -> String {
-    // TODO add refresh flow; uncomment these lines for generating new tokens when needed
-    //oauth();
-    //get_token_from_file().unwrap()
     match get_token_from_file() {
-        Ok(token) => token,
-        _ => {
-            // TODO error handling, logic for checking token is active, refresh, etc
-            oauth();
-            get_token_from_file().unwrap()
+        Ok(token) if token.is_expired() => refresh(token),
+        Ok(token) => token.access_token,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            oauth(false).expect("Problem logging in");
+            get_token_from_file().unwrap().access_token
+        }
+        Err(e) => panic!("Problem reading token file: {}", e),
+    }
+}
+
+// For when an API rejects the token we have (401) even though it hasn't expired
+pub fn refresh_token() -> String {
+    match get_token_from_file() {
+        Ok(token) => refresh(token),
+        Err(_) => {
+            oauth(false).expect("Problem logging in");
+            get_token_from_file().unwrap().access_token
+        }
+    }
+}
+
+fn refresh(token: StoredToken) -> String {
+    let encrypt = credentials::token_is_encrypted().unwrap_or(false);
+
+    match exchange_refresh_token(&client(), token, encrypt) {
+        Ok(Some(access_token)) => access_token,
+        // Only the browser can get a new refresh token
+        Ok(None) => {
+            oauth(encrypt).expect("Problem logging in");
+            get_token_from_file().unwrap().access_token
+        }
+        Err(e) => panic!("Problem refreshing token: {}", e),
+    }
+}
+
+// For the daemon, which has no one to log in: a token from the token file, refreshed if need be,
+// or an error saying to log in when it would take the browser to get one
+pub fn get_token_unattended() -> Result<String, Error> {
+    match get_token_from_file() {
+        Ok(token) if token.is_expired() => refresh_unattended(token),
+        Ok(token) => Ok(token.access_token),
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(login_needed()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn refresh_token_unattended() -> Result<String, Error> {
+    match get_token_from_file() {
+        Ok(token) => refresh_unattended(token),
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(login_needed()),
+        Err(e) => Err(e),
+    }
+}
+
+fn refresh_unattended(token: StoredToken) -> Result<String, Error> {
+    let encrypt = credentials::token_is_encrypted().unwrap_or(false);
+    exchange_refresh_token(&client(), token, encrypt)?.ok_or_else(login_needed)
+}
+
+fn login_needed() -> Error {
+    Error::new(
+        ErrorKind::PermissionDenied,
+        "Not logged in, or the login has lapsed; run `pt auth login`",
+    )
+}
+
+// Swaps the refresh token for a new access token and saves it. None when there's no refresh
+// token, or it's been revoked or has expired.
+fn exchange_refresh_token(
+    client: &BasicClient,
+    token: StoredToken,
+    encrypt: bool,
+) -> Result<Option<String>, Error> {
+    let refresh_token = match &token.refresh_token {
+        Some(refresh_token) => RefreshToken::new(refresh_token.to_string()),
+        None => return Ok(None),
+    };
+
+    match client
+        .exchange_refresh_token(&refresh_token)
+        .request(http_client)
+    {
+        Ok(token_response) => {
+            let mut refreshed = StoredToken::from_response(&token_response);
+            // Google only sends a refresh token with the first exchange, so hold on to ours
+            if refreshed.refresh_token.is_none() {
+                refreshed.refresh_token = token.refresh_token;
+            }
+            if refreshed.scopes.is_empty() {
+                refreshed.scopes = token.scopes;
+            }
+            save_token(&refreshed, encrypt)?;
+            Ok(Some(refreshed.access_token))
         }
+        Err(RequestTokenError::ServerResponse(e))
+            if *e.error() == BasicErrorResponseType::InvalidGrant =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(token_error(e)),
     }
 }
 
-fn oauth() {
-    dotenv::dotenv().ok();
-    let google_client_id = dotenv::var("GOOGLE_CLIENT_ID").unwrap();
-    println!("google_client_id: {}", google_client_id);
-    let google_client_secret = dotenv::var("GOOGLE_CLIENT_SECRET").unwrap();
-    println!("google_client_secret: {}", google_client_secret);
+// Where the authorization server's endpoints are; only tests point these anywhere but Google
+struct Endpoints {
+    auth_url: String,
+    token_url: String,
+    device_authorization_url: String,
+    revocation_url: String,
+}
 
-    let google_client_id = ClientId::new(google_client_id);
+fn google() -> Endpoints {
+    Endpoints {
+        auth_url: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+        token_url: "https://www.googleapis.com/oauth2/v3/token".to_string(),
+        device_authorization_url: "https://oauth2.googleapis.com/device/code".to_string(),
+        revocation_url: "https://oauth2.googleapis.com/revoke".to_string(),
+    }
+}
+
+fn client() -> BasicClient {
+    let client_credentials =
+        credentials::get_client_credentials().expect("Problem reading client credentials");
+    new_client(client_credentials, &google())
+}
+
+fn new_client(client_credentials: ClientCredentials, endpoints: &Endpoints) -> BasicClient {
+    let google_client_id = ClientId::new(client_credentials.client_id);
 
-    let google_client_secret = ClientSecret::new(google_client_secret);
+    let google_client_secret = ClientSecret::new(client_credentials.client_secret);
 
-    let auth_url = AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())
-        .expect("Invalid authorization endpoint URL");
-    let token_url = TokenUrl::new("https://www.googleapis.com/oauth2/v3/token".to_string())
-        .expect("Invalid token endpoint URL");
+    let auth_url =
+        AuthUrl::new(endpoints.auth_url.clone()).expect("Invalid authorization endpoint URL");
+    let token_url = TokenUrl::new(endpoints.token_url.clone()).expect("Invalid token endpoint URL");
 
-    let client = BasicClient::new(
+    BasicClient::new(
         google_client_id,
         Some(google_client_secret),
         auth_url,
         Some(token_url),
     )
-    .set_redirect_uri(
-        RedirectUrl::new("http://localhost:8080".to_string()).expect("Invalid redirect URL"),
+    .set_device_authorization_url(
+        DeviceAuthorizationUrl::new(endpoints.device_authorization_url.clone())
+            .expect("Invalid device authorization endpoint URL"),
     )
     .set_revocation_uri(
-        RevocationUrl::new("https://oauth2.googleapis.com/revoke".to_string())
+        RevocationUrl::new(endpoints.revocation_url.clone())
             .expect("Invalid revocation endpoint URL"),
-    );
+    )
+}
+
+// Gets a new token however the config says to, and saves it
+fn oauth(encrypt: bool) -> Result<(), Error> {
+    let config = get_config()?.auth;
+    authorize(config.flow, config.redirect_port, encrypt)
+}
+
+fn authorize(flow: Flow, redirect_port: u16, encrypt: bool) -> Result<(), Error> {
+    let token_response = match flow {
+        Flow::Browser => authorize_in_browser(client(), redirect_port, |authorize_url| {
+            println!("Open this URL in your browser:\n{}\n", authorize_url)
+        })?,
+        Flow::Device => authorize_on_another_device(&client(), |verification_url, user_code| {
+            println!(
+                "On any device, go to {} and enter the code {}\n",
+                verification_url, user_code
+            )
+        })?,
+    };
+
+    save_token(&StoredToken::from_response(&token_response), encrypt)
+}
+
+fn authorize_in_browser<F>(
+    client: BasicClient,
+    redirect_port: u16,
+    open: F,
+) -> Result<BasicTokenResponse, Error>
+where
+    F: FnOnce(&Url),
+{
+    let listener = TcpListener::bind(("127.0.0.1", redirect_port)).map_err(|e| match e.kind() {
+        ErrorKind::AddrInUse => Error::new(
+            ErrorKind::AddrInUse,
+            format!(
+                "Port {} is in use; set redirect_port in the [auth] table of the config to another port, or to 0 for any free one",
+                redirect_port
+            ),
+        ),
+        _ => e,
+    })?;
+    let redirect_url = format!("http://localhost:{}", listener.local_addr()?.port());
+    let client = client.set_redirect_uri(RedirectUrl::new(redirect_url).map_err(Error::other)?);
 
     let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
 
-    let (authorize_url, _) = client
+    let (authorize_url, state) = client
         .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new(
-            "https://www.googleapis.com/auth/drive.file".to_string(),
-        ))
-        .add_scope(Scope::new(
-            "https://www.googleapis.com/auth/plus.me".to_string(),
-        ))
+        .add_scope(Scope::new(DRIVE_SCOPE.to_string()))
+        // Google only hands out refresh tokens for offline access, and only on consent
+        .add_extra_param("access_type", "offline")
+        .add_extra_param("prompt", "consent")
         .set_pkce_challenge(pkce_code_challenge)
         .url();
 
+    open(&authorize_url);
+
+    let code = wait_for_redirect(&listener, &state)?;
+
+    client
+        .exchange_code(code)
+        .set_pkce_verifier(pkce_code_verifier)
+        .request(http_client)
+        .map_err(token_error)
+}
+
+// Answers whatever the browser sends until it's sent back from the consent page. Anything else
+// (favicons, requests without our state) gets an error page and is otherwise ignored.
+fn wait_for_redirect(
+    listener: &TcpListener,
+    state: &CsrfToken,
+) -> Result<AuthorizationCode, Error> {
+    for mut stream in listener.incoming().flatten() {
+        // Browsers open connections they never send anything on
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(
+            REQUEST_TIMEOUT_SECONDS,
+        )))?;
+
+        let target = match read_request_target(&stream) {
+            Some(target) => target,
+            None => continue,
+        };
+        let url = match Url::parse(&("http://localhost".to_string() + &target)) {
+            Ok(url) => url,
+            Err(_) => {
+                respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    "That isn't a request pt understands.",
+                );
+                continue;
+            }
+        };
+        if url.path() != "/" {
+            respond(&mut stream, "404 Not Found", "Nothing here.");
+            continue;
+        }
+
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        if param("state").as_deref() != Some(state.secret().as_str()) {
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "This isn't the login pt is waiting for. Use the URL pt printed in your terminal.",
+            );
+            continue;
+        }
+
+        if let Some(error) = param("error") {
+            respond(
+                &mut stream,
+                "403 Forbidden",
+                &format!(
+                    "pt wasn't given access ({}). You can close this tab.",
+                    error
+                ),
+            );
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("Access wasn't granted: {}", error),
+            ));
+        }
+
+        match param("code") {
+            Some(code) => {
+                respond(
+                    &mut stream,
+                    "200 OK",
+                    "You're logged in. Go back to your terminal :)",
+                );
+                return Ok(AuthorizationCode::new(code));
+            }
+            None => respond(
+                &mut stream,
+                "400 Bad Request",
+                "There's no authorization code here.",
+            ),
+        }
+    }
+
+    Err(Error::other("Stopped listening for the browser"))
+}
+
+// The path and query of an HTTP request, e.g. /?state=...&code=...
+fn read_request_target(stream: &TcpStream) -> Option<String> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let target = request_line.split_whitespace().nth(1)?.to_string();
+
+    // The headers aren't needed, but have to be read before responding
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    Some(target)
+}
+
+fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let page = format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>Productivity Timer</title></head><body><p>{}</p></body></html>",
+        message
+    );
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: text/html; charset=utf-8\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        page.len(),
+        page
+    );
+    // The browser's gone if this fails, and there's nobody left to tell
+    let _ = stream.write_all(response.as_bytes());
+}
+
+// The OAuth 2.0 device authorization grant: shows a code to enter on another device, then polls
+// until it's been entered
+fn authorize_on_another_device<F>(
+    client: &BasicClient,
+    show: F,
+) -> Result<BasicTokenResponse, Error>
+where
+    F: FnOnce(&str, &str),
+{
+    let details: StandardDeviceAuthorizationResponse = client
+        .exchange_device_code()
+        .map_err(Error::other)?
+        .add_scope(Scope::new(DRIVE_SCOPE.to_string()))
+        .request(http_client)
+        .map_err(token_error)?;
+
+    show(details.verification_uri(), details.user_code().secret());
+
+    client
+        .exchange_device_access_token(&details)
+        .request(http_client, std::thread::sleep, None)
+        .map_err(token_error)
+}
+
+fn token_error<RE, TE>(e: RequestTokenError<RE, TE>) -> Error
+where
+    RE: std::error::Error + 'static,
+    TE: ErrorResponse + fmt::Display + 'static,
+{
+    match e {
+        // e.g. access_denied or expired_token
+        RequestTokenError::ServerResponse(e) => Error::new(
+            ErrorKind::PermissionDenied,
+            format!("The authorization server said no: {}", e),
+        ),
+        e => Error::other(format!("Problem getting a token: {}", e)),
+    }
+}
+
+// Sets up client credentials if we don't have them in the data dir yet, then gets a new token
+// through the browser, or another device if `device` is set or the config says to
+pub fn login(encrypt: bool, device: bool) -> Result<(), Error> {
+    match credentials::client_credentials_source() {
+        ClientCredentialsSource::DataDir => (),
+        // Move them out of the .env so pt works from any directory
+        ClientCredentialsSource::Env => {
+            credentials::save_client_credentials(&credentials::get_client_credentials()?)?
+        }
+        ClientCredentialsSource::Missing => {
+            println!("Set up an OAuth client for the Google Drive API in GCP's console: a Desktop app client, or a TVs and Limited Input devices one for logging in from another device.");
+            let client_id = prompt("Client ID: ")?;
+            let client_secret = rpassword::prompt_password("Client secret: ")?;
+            let api_key = rpassword::prompt_password("API key: ")?;
+
+            credentials::save_client_credentials(&ClientCredentials {
+                client_id,
+                client_secret,
+                api_key,
+            })?;
+        }
+    }
+
+    let config = get_config()?.auth;
+    let flow = if device { Flow::Device } else { config.flow };
+    authorize(flow, config.redirect_port, encrypt)?;
+    println!("Logged in");
+    Ok(())
+}
+
+// Revokes the token with Google and forgets it locally
+pub fn logout() -> Result<(), Error> {
+    let token = match get_token_from_file() {
+        Ok(token) => token,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            println!("Not logged in");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    // Revoking a refresh token revokes its access tokens, too
+    let revocable_token = match token.refresh_token {
+        Some(refresh_token) => {
+            StandardRevocableToken::RefreshToken(RefreshToken::new(refresh_token))
+        }
+        None => StandardRevocableToken::AccessToken(AccessToken::new(token.access_token)),
+    };
+
+    let revoked = client()
+        .revoke_token(revocable_token)
+        .map_err(Error::other)?
+        .request(http_client);
+
+    credentials::delete_token()?;
+
+    match revoked {
+        Ok(()) => println!("Logged out"),
+        Err(e) => println!(
+            "Logged out locally, but Google didn't accept the revocation: {:?}",
+            e
+        ),
+    }
+    Ok(())
+}
+
+pub fn status() -> Result<(), Error> {
+    match credentials::client_credentials_source() {
+        ClientCredentialsSource::DataDir => {
+            println!("client credentials: ~/.productivity-timer/credentials")
+        }
+        ClientCredentialsSource::Env => println!("client credentials: .env"),
+        ClientCredentialsSource::Missing => println!("client credentials: missing"),
+    }
+
+    let token = match get_token_from_file() {
+        Ok(token) => token,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            println!("token: not logged in");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    let encrypted = if credentials::token_is_encrypted()? {
+        " (encrypted)"
+    } else {
+        ""
+    };
+    println!("token: logged in{}", encrypted);
+
+    match token.expires_at {
+        Some(expires_at) if token.is_expired() => println!(
+            "expires: {} (expired; refreshed on next use)",
+            expires_at.with_timezone(&Local)
+        ),
+        Some(expires_at) => println!("expires: {}", expires_at.with_timezone(&Local)),
+        None => println!("expires: unknown"),
+    }
     println!(
-        "Open this URL in your browser:\n{}\n",
-        authorize_url.to_string()
+        "refresh token: {}",
+        if token.refresh_token.is_some() {
+            "yes"
+        } else {
+            "no; you'll need to log in again when the access token stops working"
+        }
     );
+    println!("scopes: {}", token.scopes.join(" "));
 
-    let listener = TcpListener::bind("127.0.0.1:8080").unwrap();
-    for stream in listener.incoming() {
-        if let Ok(mut stream) = stream {
-            let code;
-            {
-                let mut reader = BufReader::new(&stream);
+    Ok(())
+}
 
-                let mut request_line = String::new();
-                reader.read_line(&mut request_line).unwrap();
+fn prompt(message: &str) -> Result<String, Error> {
+    print!("{}", message);
+    stdout().flush()?;
 
-                let redirect_url = request_line.split_whitespace().nth(1).unwrap();
-                let url = Url::parse(&("http://localhost".to_string() + redirect_url)).unwrap();
+    let mut line = String::new();
+    stdin().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
 
-                let code_pair = url
-                    .query_pairs()
-                    .find(|pair| {
-                        let &(ref key, _) = pair;
-                        key == "code"
-                    })
-                    .unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{serve, Request, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
 
-                let (_, value) = code_pair;
-                code = AuthorizationCode::new(value.into_owned());
-            }
+    const TOKEN: &str = r#"{"access_token": "access", "token_type": "bearer", "expires_in": 3600, "refresh_token": "refresh"}"#;
+
+    fn fake_client(base_url: &str) -> BasicClient {
+        new_client(
+            ClientCredentials {
+                client_id: "id".to_string(),
+                client_secret: "secret".to_string(),
+                api_key: "key".to_string(),
+            },
+            &Endpoints {
+                auth_url: base_url.to_string() + "/auth",
+                token_url: base_url.to_string() + "/token",
+                device_authorization_url: base_url.to_string() + "/device",
+                revocation_url: base_url.to_string() + "/revoke",
+            },
+        )
+    }
+
+    fn body(request: &Request) -> String {
+        String::from_utf8_lossy(&request.body).to_string()
+    }
 
-            let message = "Go back to your terminal :)";
-            let response = format!(
-                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
-                message.len(),
-                message
+    // Plays the browser: follows the redirect Google would send it on, after some noise
+    fn browse(
+        authorize_url: &Url,
+        query: impl Fn(&str) -> String + Send + 'static,
+    ) -> thread::JoinHandle<()> {
+        let param = |name: &str| {
+            authorize_url
+                .query_pairs()
+                .find(|(key, _)| key == name)
+                .unwrap()
+                .1
+                .into_owned()
+        };
+        let redirect_url = param("redirect_uri");
+        let state = param("state");
+
+        thread::spawn(move || {
+            let get = |url: String| reqwest::blocking::get(url).unwrap().status().as_u16();
+            assert_eq!(get(redirect_url.clone() + "/favicon.ico"), 404);
+            assert_eq!(
+                get(redirect_url.clone() + "/?state=forged&code=stolen"),
+                400
             );
+            get(redirect_url + "/?" + &query(&state));
+        })
+    }
 
-            stream.write_all(response.as_bytes()).unwrap();
+    #[test]
+    fn logs_in_through_the_browser() {
+        let base_url = serve(|request| match request.path.as_str() {
+            "/token"
+                if body(&request).contains("code=granted")
+                    && body(&request).contains("code_verifier=") =>
+            {
+                Response::new(200)
+                    .header("content-type", "application/json")
+                    .body(TOKEN)
+            }
+            _ => Response::new(400).body(r#"{"error": "invalid_grant"}"#),
+        });
 
-            let token_response = client
-                .exchange_code(code)
-                .set_pkce_verifier(pkce_code_verifier)
-                .request(http_client);
+        let mut browser = None;
+        let token_response = authorize_in_browser(fake_client(&base_url), 0, |authorize_url| {
+            browser = Some(browse(authorize_url, |state| {
+                format!("state={}&code=granted", state)
+            }))
+        })
+        .unwrap();
+        browser.unwrap().join().unwrap();
 
-            let token_response = token_response.unwrap();
-            println!("token_response: {:?}", token_response);
+        assert_eq!(token_response.access_token().secret(), "access");
+        assert_eq!(token_response.refresh_token().unwrap().secret(), "refresh");
+    }
 
-            let token_filepath = home_dir().unwrap().as_path().display().to_string()
-                + "/.productivity-timer"
-                + "/token";
+    #[test]
+    fn reports_denied_consent() {
+        let base_url = serve(|_| Response::new(500));
 
-            let access_token: String = format!("{:?}", token_response.access_token().secret());
-            // TODO figure out a better way to store this
-            write(token_filepath, access_token).expect("Problem writing to token file");
+        let mut browser = None;
+        let e = authorize_in_browser(fake_client(&base_url), 0, |authorize_url| {
+            browser = Some(browse(authorize_url, |state| {
+                format!("state={}&error=access_denied", state)
+            }))
+        })
+        .unwrap_err();
+        browser.unwrap().join().unwrap();
 
-            // TODO refresh logic
+        assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+        assert!(e.to_string().contains("access_denied"));
+    }
 
-            break;
+    #[test]
+    fn logs_in_from_another_device() {
+        let polls = AtomicUsize::new(0);
+        let base_url = serve(move |request| {
+            match request.path.as_str() {
+            "/device" => Response::new(200)
+                .header("content-type", "application/json")
+                .body(
+                    r#"{"device_code": "device", "user_code": "ABC-DEF", "verification_url": "https://example.com/device", "expires_in": 60, "interval": 0}"#,
+                ),
+            "/token" if body(&request).contains("device_code=device") => {
+                // Entering the code takes a moment
+                if polls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Response::new(428).body(r#"{"error": "authorization_pending"}"#)
+                } else {
+                    Response::new(200)
+                        .header("content-type", "application/json")
+                        .body(TOKEN)
+                }
+            }
+            _ => Response::new(400).body(r#"{"error": "invalid_request"}"#),
         }
+        });
+
+        let mut shown = None;
+        let token_response =
+            authorize_on_another_device(&fake_client(&base_url), |url, user_code| {
+                shown = Some((url.to_string(), user_code.to_string()))
+            })
+            .unwrap();
+
+        assert_eq!(
+            shown,
+            Some((
+                "https://example.com/device".to_string(),
+                "ABC-DEF".to_string()
+            ))
+        );
+        assert_eq!(token_response.access_token().secret(), "access");
+    }
+
+    // Where the daemon has to stop and ask for `pt auth login`, rather than open the browser
+    #[test]
+    fn a_revoked_or_missing_refresh_token_needs_a_login() {
+        let base_url = serve(|_| Response::new(400).body(r#"{"error": "invalid_grant"}"#));
+        let token = |refresh_token: Option<&str>| StoredToken {
+            access_token: "expired".to_string(),
+            refresh_token: refresh_token.map(str::to_string),
+            expires_at: Some(Utc::now()),
+            scopes: Vec::new(),
+        };
+
+        let client = fake_client(&base_url);
+        assert_eq!(
+            exchange_refresh_token(&client, token(Some("revoked")), false).unwrap(),
+            None
+        );
+        assert_eq!(
+            exchange_refresh_token(&client, token(None), false).unwrap(),
+            None
+        );
     }
 }
@@ -1,15 +1,20 @@
-// TODO: apparently chrono supports negative durations (or some representation of time); it'd
-// probably be smart to pull out std::time in favor of that to make -s, --subtract easier
-
-use std::time::{Duration, Instant};
+use crate::git;
+use chrono::{DateTime, Duration, Utc};
+use std::time::Instant;
 
 // TODO: only private data fields; getters/setters for updating
 #[derive(Debug)]
 pub struct PTDuration {
     pub tag: Option<String>,
+    // Signed so that subtractions can carry negative time and totals can dip below zero
     pub time_gained: Option<Duration>,
     pub begin: Instant,
     pub end: Option<Instant>,
+    // Wall-clock counterparts to begin and end, for saving; Instants can't be turned into dates
+    pub begun_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    // The repo and branch it was spent in, when the git context is on
+    pub context: Option<git::Context>,
 }
 
 impl PTDuration {
@@ -19,6 +24,9 @@ impl PTDuration {
             time_gained: None,
             begin: Instant::now(),
             end: None,
+            begun_at: Utc::now(),
+            ended_at: None,
+            context: None,
         }
     }
 
@@ -28,5 +36,14 @@ impl PTDuration {
 
     pub fn end(&mut self) {
         self.end = Some(Instant::now());
+        self.ended_at = Some(Utc::now());
+    }
+
+    // Time gained so far, counting up to now for a duration that hasn't ended
+    pub fn elapsed(&self) -> Duration {
+        match self.time_gained {
+            Some(time_gained) => time_gained,
+            None => Duration::from_std(self.begin.elapsed()).unwrap_or_else(|_| Duration::zero()),
+        }
     }
 }
@@ -0,0 +1,213 @@
+// Time gained in completed sessions, totalled by session tag, tag, day, git repo or branch, or issue
+// key rather than listed duration by duration
+use crate::daemon::format_instant_to_hhmmss;
+use crate::database::{get_session_durations, SessionDuration};
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum By {
+    SessionTag,
+    Tag,
+    Day,
+    Repo,
+    // The repo and branch, as repo:branch
+    Branch,
+    Issue,
+}
+
+impl By {
+    // What `parse` takes, as a CLI or the API would list them
+    pub const NAMES: &'static [&'static str] =
+        &["session_tag", "tag", "day", "repo", "branch", "issue"];
+
+    pub fn parse(by: &str) -> Option<By> {
+        match by {
+            "session_tag" | "session-tag" => Some(By::SessionTag),
+            "tag" => Some(By::Tag),
+            "day" => Some(By::Day),
+            "repo" => Some(By::Repo),
+            "branch" => Some(By::Branch),
+            "issue" => Some(By::Issue),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Total {
+    // The session tag, tag, local date (YYYY-MM-DD), repo, repo:branch, or issue key
+    pub key: String,
+    pub seconds: i64,
+    pub durations: usize,
+}
+
+// Durations that began from `since` through `until` (both local dates, and both optional), sorted
+// by key
+pub fn totals(
+    by: By,
+    session_tag: Option<&str>,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<Vec<Total>, Error> {
+    let until = until.map(|until| until + Duration::days(1));
+    let durations = get_session_durations(session_tag, since, until).map_err(Error::other)?;
+    Ok(group(&durations, by, |at| {
+        at.with_timezone(&Local).date_naive()
+    }))
+}
+
+// Writes the totals to stdout, a line each
+pub fn print(
+    by: By,
+    session_tag: Option<&str>,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<(), Error> {
+    let totals = totals(by, session_tag, since, until)?;
+    let width = totals
+        .iter()
+        .map(|total| total.key.len())
+        .max()
+        .unwrap_or(0);
+    for total in totals {
+        println!(
+            "{:width$}  {}  {} durations",
+            total.key,
+            format_instant_to_hhmmss(Duration::seconds(total.seconds)),
+            total.durations,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+fn group<F>(durations: &[SessionDuration], by: By, to_local_date: F) -> Vec<Total>
+where
+    F: Fn(DateTime<Utc>) -> NaiveDate,
+{
+    let mut totals: BTreeMap<String, Total> = BTreeMap::new();
+
+    for duration in durations {
+        let key = match by {
+            By::SessionTag => duration.session_tag.clone(),
+            By::Tag => duration.tag.clone(),
+            By::Day => duration
+                .begin_at
+                .map(|begin_at| to_local_date(begin_at).to_string()),
+            By::Repo => duration.repo.clone(),
+            By::Branch => duration
+                .repo
+                .as_ref()
+                .map(|repo| format!("{}:{}", repo, duration.branch.as_deref().unwrap_or("HEAD"))),
+            By::Issue => duration.issue.clone(),
+        };
+        // Durations from before pt kept timestamps have no day, and ones from before the git
+        // context was on (or spent outside a repo) have no repo
+        let key = key.unwrap_or_else(|| match by {
+            By::Day => "undated".to_string(),
+            By::Repo | By::Branch => "no repo".to_string(),
+            By::Issue => "no issue".to_string(),
+            _ => "untagged".to_string(),
+        });
+
+        let total = totals.entry(key.clone()).or_insert(Total {
+            key,
+            seconds: 0,
+            durations: 0,
+        });
+        total.seconds += duration.seconds;
+        total.durations += 1;
+    }
+
+    totals.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn duration(session_tag: &str, tag: Option<&str>, day: u32, minutes: i64) -> SessionDuration {
+        let begin_at = Utc.with_ymd_and_hms(2026, 10, day, 9, 0, 0).unwrap();
+        SessionDuration {
+            uuid: format!("{}-{}", day, minutes),
+            session_id: day as u64,
+            session_tag: Some(session_tag.to_string()),
+            session_note: None,
+            tag: tag.map(|tag| tag.to_string()),
+            seconds: minutes * 60,
+            begin_at: Some(begin_at),
+            end_at: Some(begin_at + Duration::minutes(minutes.max(0))),
+            repo: None,
+            branch: None,
+            issue: None,
+        }
+    }
+
+    #[test]
+    fn totals_by_session_tag_tag_and_day() {
+        let durations = vec![
+            duration("work", Some("review"), 19, 30),
+            duration("work", None, 19, -10),
+            duration("pt", Some("review"), 20, 45),
+        ];
+        let group = |by| group(&durations, by, |at| at.date_naive());
+        let total = |key: &str, minutes: i64, durations| Total {
+            key: key.to_string(),
+            seconds: minutes * 60,
+            durations,
+        };
+
+        assert_eq!(
+            group(By::SessionTag),
+            vec![total("pt", 45, 1), total("work", 20, 2)]
+        );
+        assert_eq!(
+            group(By::Tag),
+            vec![total("review", 75, 2), total("untagged", -10, 1)]
+        );
+        assert_eq!(
+            group(By::Day),
+            vec![total("2026-10-19", 20, 2), total("2026-10-20", 45, 1)]
+        );
+    }
+
+    #[test]
+    fn totals_by_repo_and_branch() {
+        let in_repo = |repo: &str, branch: &str, minutes| SessionDuration {
+            repo: Some(repo.to_string()),
+            branch: Some(branch.to_string()),
+            ..duration("work", None, 19, minutes)
+        };
+        let durations = vec![
+            in_repo("pt", "main", 30),
+            in_repo("pt", "git-context", 45),
+            in_repo("notes", "main", 15),
+            duration("work", None, 19, 10),
+        ];
+        let keys = |by| -> Vec<(String, i64)> {
+            group(&durations, by, |at| at.date_naive())
+                .into_iter()
+                .map(|total| (total.key, total.seconds / 60))
+                .collect()
+        };
+        let key = |key: &str, minutes| (key.to_string(), minutes);
+
+        assert_eq!(
+            keys(By::Repo),
+            vec![key("no repo", 10), key("notes", 15), key("pt", 75)]
+        );
+        assert_eq!(
+            keys(By::Branch),
+            vec![
+                key("no repo", 10),
+                key("notes:main", 15),
+                key("pt:git-context", 45),
+                key("pt:main", 30)
+            ]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+// Replaces the local database with a backup or snapshot, but only once it's been checked over and
+// the current database has been set aside
+use crate::backup::{backend, open};
+use crate::config::get_config;
+use crate::credentials::backup_passphrase;
+use crate::daemon;
+use crate::daemon::format_instant_to_hhmmss;
+use crate::database::{database_filename, summarize_database, DatabaseSummary};
+use crate::snapshots;
+use chrono::Duration;
+use std::collections::BTreeMap;
+use std::fs::{remove_file, rename, write};
+use std::io::{stdin, stdout, Error, ErrorKind, Write};
+use std::path::Path;
+
+pub fn restore(skip_confirmation: bool) -> Result<(), Error> {
+    let config = get_config()?.backup;
+    let backup = backend(&config, true)?.download(&config.filename)?;
+    let backup = open(backup, || backup_passphrase(false))?;
+
+    // Next to the database, so the swap is a rename on the same filesystem
+    let download_filepath = database_filename() + ".restore";
+    write(&download_filepath, backup)?;
+
+    restore_from_file(&download_filepath, "backup", skip_confirmation)
+}
+
+// Swaps in the database at `filepath`, which has to be next to the local one so it can be renamed
+// into place. It's removed if it isn't swapped in. `what` is what to call it in messages.
+pub fn restore_from_file(filepath: &str, what: &str, skip_confirmation: bool) -> Result<(), Error> {
+    let result = check_and_swap(filepath, what, skip_confirmation);
+    if Path::new(filepath).exists() {
+        remove_file(filepath)?;
+    }
+    result
+}
+
+fn check_and_swap(filepath: &str, what: &str, skip_confirmation: bool) -> Result<(), Error> {
+    let backup = check_database(filepath, what)?;
+
+    let local = if Path::new(&database_filename()).exists() {
+        Some(summarize_database(&database_filename()).map_err(Error::other)?)
+    } else {
+        None
+    };
+    print_differences(local.as_ref(), &backup, what);
+
+    let daemon_running = daemon::is_running();
+    if daemon_running {
+        if let Some(status) = daemon::get_status()? {
+            if status.active || status.time_gained != 0 || !status.tags.is_empty() {
+                return Err(Error::other(
+                    "There's a session in progress; complete it first, or it'll be lost when the daemon restarts",
+                ));
+            }
+        }
+    }
+
+    if !skip_confirmation
+        && !confirm(&format!(
+            "Replace the local database with the {}? [y/N] ",
+            what
+        ))?
+    {
+        println!("Nothing restored");
+        return Ok(());
+    }
+
+    with_daemon_stopped(daemon_running, daemon::stop, daemon::start, || {
+        if local.is_some() {
+            let snapshot_filepath = snapshots::take("restore")?;
+            println!("The old database is at {}", snapshot_filepath);
+        }
+
+        rename(filepath, database_filename())?;
+        println!("Restored");
+        Ok(())
+    })
+}
+
+// Runs `swap` with the daemon stopped, if it was running, and starts it again whether or not the
+// swap worked, so a failed restore doesn't leave the timer dead
+fn with_daemon_stopped(
+    daemon_running: bool,
+    stop: impl FnOnce() -> Result<(), Error>,
+    start: impl FnOnce() -> Result<(), Error>,
+    swap: impl FnOnce() -> Result<(), Error>,
+) -> Result<(), Error> {
+    if !daemon_running {
+        return swap();
+    }
+
+    stop()?;
+    let swapped = swap();
+    let started = start();
+    swapped.and(started)
+}
+
+// Makes sure the database at `filepath` is one of ours, intact, and one this pt can read
+pub fn check_database(filepath: &str, what: &str) -> Result<DatabaseSummary, Error> {
+    let summary = summarize_database(filepath).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("The {} isn't a database we can read: {}", what, e),
+        )
+    })?;
+    if summary.integrity != "ok" {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("The {} is corrupt: {}", what, summary.integrity),
+        ));
+    }
+    if !summary.has_sessions {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("The {} isn't a productivity timer database", what),
+        ));
+    }
+    if summary.is_newer_than_us() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "The {} is at schema version {}, which is newer than this pt; upgrade pt first",
+                what, summary.schema_version
+            ),
+        ));
+    }
+    Ok(summary)
+}
+
+// Sessions and total time per session tag, side by side
+fn print_differences(local: Option<&DatabaseSummary>, backup: &DatabaseSummary, what: &str) {
+    let mut rows: BTreeMap<String, [(i64, i64); 2]> = BTreeMap::new();
+    for (i, summary) in [local, Some(backup)].iter().enumerate() {
+        for session_tag in summary.iter().flat_map(|summary| &summary.session_tags) {
+            let tag = session_tag.tag.clone().unwrap_or_else(|| "-".to_string());
+            rows.entry(tag).or_default()[i] = (session_tag.sessions, session_tag.total_seconds);
+        }
+    }
+
+    println!(
+        "{:<20} {:>20} {:>20} {:>12}",
+        "session tag",
+        "local sessions/time",
+        format!("{} sessions/time", what),
+        "difference"
+    );
+    let mut totals = [(0, 0); 2];
+    for (tag, [local, backup]) in &rows {
+        print_row(tag, *local, *backup);
+        totals[0] = (totals[0].0 + local.0, totals[0].1 + local.1);
+        totals[1] = (totals[1].0 + backup.0, totals[1].1 + backup.1);
+    }
+    print_row("total", totals[0], totals[1]);
+
+    if local.is_none() {
+        println!("(there's no local database yet)");
+    }
+}
+
+fn print_row(tag: &str, local: (i64, i64), backup: (i64, i64)) {
+    let hhmmss = |seconds| format_instant_to_hhmmss(Duration::seconds(seconds));
+    println!(
+        "{:<20} {:>20} {:>20} {:>12}",
+        tag,
+        format!("{} / {}", local.0, hhmmss(local.1)),
+        format!("{} / {}", backup.0, hhmmss(backup.1)),
+        hhmmss(backup.1 - local.1)
+    );
+}
+
+fn confirm(message: &str) -> Result<bool, Error> {
+    print!("{}", message);
+    stdout().flush()?;
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn starts_the_daemon_again_when_the_swap_fails() {
+        let calls = RefCell::new(Vec::new());
+        let result = with_daemon_stopped(
+            true,
+            || {
+                calls.borrow_mut().push("stop");
+                Ok(())
+            },
+            || {
+                calls.borrow_mut().push("start");
+                Ok(())
+            },
+            || {
+                calls.borrow_mut().push("swap");
+                Err(Error::other("No room for the snapshot"))
+            },
+        );
+
+        assert_eq!(result.unwrap_err().to_string(), "No room for the snapshot");
+        assert_eq!(*calls.borrow(), ["stop", "swap", "start"]);
+    }
+}
@@ -1,11 +1,13 @@
 use crate::analytics::Analytics;
 use crate::daemon::format_instant_to_hhmmss;
 use crate::database;
+use crate::git;
+use crate::issues;
 use crate::pt_duration::PTDuration;
-use core::time::Duration;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 use std::io::Error;
-use std::time::Instant;
 
 // TODO: only private data fields, add getters/setters
 #[derive(Debug)]
@@ -20,6 +22,56 @@ pub struct Session {
     pub tag: Option<String>,
 }
 
+// A snapshot of the running session. The daemon writes it out for the interface and the CLI when
+// it changes, rather than as time passes, so readers bring it up to date with `caught_up_to`. Times are
+// in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    // Unix timestamp of when the snapshot was taken
+    pub updated_at: i64,
+    pub active: bool,
+    // The tag of the running duration, or of the last one if paused
+    pub tag: Option<String>,
+    pub current_duration: i64,
+    pub time_gained: i64,
+    pub tags: Vec<(String, i64)>,
+    // Each duration so far, with where it was spent when the git context is on
+    #[serde(default)]
+    pub durations: Vec<DurationStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DurationStatus {
+    pub tag: Option<String>,
+    pub repo: Option<String>,
+    pub branch: Option<String>,
+    // Unix timestamp
+    pub begun_at: i64,
+    pub seconds: i64,
+}
+
+impl Status {
+    // The status at `now`, counting the time since the snapshot towards a running duration
+    pub fn caught_up_to(mut self, now: i64) -> Status {
+        let elapsed = now - self.updated_at;
+        self.updated_at = now;
+        if !self.active || elapsed <= 0 {
+            return self;
+        }
+
+        self.current_duration += elapsed;
+        self.time_gained += elapsed;
+        let tag = self.tag.clone().unwrap_or_default();
+        if let Some((_, seconds)) = self.tags.iter_mut().find(|(value, _)| value == &tag) {
+            *seconds += elapsed;
+        }
+        if let Some(duration) = self.durations.last_mut() {
+            duration.seconds += elapsed;
+        }
+        self
+    }
+}
+
 impl Session {
     pub fn new() -> Session {
         let id = database::new_session().unwrap();
@@ -37,36 +89,57 @@ impl Session {
     }
 
     // TODO: DRY up record_time and record_additions
-    pub fn record_time(&mut self, tag: Option<String>) {
-        self.durations.push(PTDuration::new(tag));
+    pub fn record_time(&mut self, tag: Option<String>, context: Option<git::Context>) {
+        let mut pt_duration = PTDuration::new(tag);
+        pt_duration.context = context;
+        self.durations.push(pt_duration);
         self.active = true;
     }
 
     pub fn record_addition(&mut self, minutes_to_add: u64) {
         // TODO: support tags
         let mut pt_duration = PTDuration::new(None);
-        let addition = Duration::new(minutes_to_add * 60, 0);
+        let addition = Duration::minutes(minutes_to_add as i64);
         pt_duration.update_time_gained(addition);
 
         self.additions.push(pt_duration);
     }
 
     pub fn record_subtraction(&mut self, minutes_to_subtract: u64) {
+        self.subtract(Duration::minutes(minutes_to_subtract as i64));
+    }
+
+    pub fn subtract(&mut self, time: Duration) {
         // TODO: support tags
         let mut pt_duration = PTDuration::new(None);
-        let subtraction = Duration::new(minutes_to_subtract * 60, 0);
-        pt_duration.update_time_gained(subtraction);
+        pt_duration.update_time_gained(-time);
 
         self.subtractions.push(pt_duration);
     }
 
+    // Takes seconds off each ended duration, in the order `spans` gives them, e.g. the time spent in
+    // flagged events, so the durations' tags lose it along with the session
+    pub fn deduct(&mut self, seconds: &[i64]) {
+        let ended = self
+            .durations
+            .iter_mut()
+            .filter(|duration| duration.ended_at.is_some());
+        for (duration, seconds) in ended.zip(seconds) {
+            duration.time_gained = Some(duration.elapsed() - Duration::seconds(*seconds));
+        }
+
+        self.update_time_gained();
+        self.analytics.update_duration_avg();
+    }
+
     pub fn pause(&mut self) {
         let active_duration = self.durations.last_mut().unwrap();
         active_duration.end();
         active_duration.time_gained = active_duration
             .end
             .unwrap()
-            .checked_duration_since(active_duration.begin);
+            .checked_duration_since(active_duration.begin)
+            .and_then(|time_gained| Duration::from_std(time_gained).ok());
 
         self.analytics.update_duration_count();
         self.analytics.update_duration_avg();
@@ -74,10 +147,8 @@ impl Session {
     }
 
     pub fn update_time_gained(&mut self) {
-        if self.durations.len() != 0 {
-            self.analytics
-                .update_time_gained(&self.durations, &self.additions, &self.subtractions);
-        }
+        self.analytics
+            .update_time_gained(&self.durations, &self.additions, &self.subtractions);
     }
 
     pub fn save_session(self) {
@@ -88,50 +159,236 @@ impl Session {
         };
 
         let duration_avg = match self.analytics.duration_avg {
-            Some(v) => v.to_string(),
+            Some(v) => v,
             None => 0.to_string(),
         };
 
         database::save_session(
             formatted_time_gained,
-            self.analytics.duration_count.unwrap().try_into().unwrap(),
+            // A session can be only additions/subtractions, which don't count as durations
+            self.analytics
+                .duration_count
+                .unwrap_or(0)
+                .try_into()
+                .unwrap(),
             duration_avg,
             self.id,
             self.tag.unwrap(),
         )
         .unwrap();
 
+        // Keys from notes are added when the session's noted
+        let patterns = issues::patterns().unwrap_or_else(|e| {
+            eprintln!("Error, {}", e);
+            Vec::new()
+        });
+
         // TODO: this only accounts for 'natural' durations, not additions or subtractions, but it
         // makes sense to take tags for, them, too. Expand this to cover them, which will require
         // supporting tags for adds/subs
         for duration in self.durations {
+            let issue = issues::extract(&patterns, duration.tag.as_deref(), None);
             database::save_tag(
                 self.id,
-                duration.tag.unwrap(),
+                duration.tag,
                 format_instant_to_hhmmss(duration.time_gained.unwrap()),
+                duration.begun_at,
+                duration.ended_at,
+                duration.context.as_ref(),
+                issue,
             )
             .expect("Error saving tag");
         }
     }
 
+    // The tag and seconds so far of the latest duration
+    pub fn last_duration(&self) -> Option<(Option<String>, i64)> {
+        self.durations
+            .last()
+            .map(|duration| (duration.tag.clone(), duration.elapsed().num_seconds()))
+    }
+
+    pub fn last_context(&self) -> Option<git::Context> {
+        self.durations.last()?.context.clone()
+    }
+
+    // When each duration that's ended began and ended
+    pub fn spans(&self) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        self.durations
+            .iter()
+            .filter_map(|duration| Some((duration.begun_at, duration.ended_at?)))
+            .collect()
+    }
+
+    pub fn durations_count(&self) -> usize {
+        self.durations.len()
+    }
+
+    pub fn status(&self) -> Status {
+        let current_duration = match self.durations.last() {
+            Some(duration) if self.active => duration.elapsed().num_seconds(),
+            _ => 0,
+        };
+
+        let mut tags: Vec<(String, i64)> = Vec::new();
+        for duration in &self.durations {
+            let tag = duration.tag.clone().unwrap_or_default();
+            let seconds = duration.elapsed().num_seconds();
+            match tags.iter_mut().find(|(value, _)| value == &tag) {
+                Some((_, total)) => *total += seconds,
+                None => tags.push((tag, seconds)),
+            }
+        }
+
+        Status {
+            updated_at: Utc::now().timestamp(),
+            active: self.active,
+            tag: self
+                .durations
+                .last()
+                .and_then(|duration| duration.tag.clone()),
+            current_duration,
+            time_gained: self
+                .analytics
+                .time_gained
+                .map(|time_gained| time_gained.num_seconds())
+                .unwrap_or(0),
+            tags,
+            durations: self
+                .durations
+                .iter()
+                .map(|duration| DurationStatus {
+                    tag: duration.tag.clone(),
+                    repo: duration
+                        .context
+                        .as_ref()
+                        .map(|context| context.repo.clone()),
+                    branch: duration
+                        .context
+                        .as_ref()
+                        .map(|context| context.branch.clone()),
+                    begun_at: duration.begun_at.timestamp(),
+                    seconds: duration.elapsed().num_seconds(),
+                })
+                .collect(),
+        }
+    }
+
     pub fn get_tag_time_gained(&self, tag: String) -> Result<String, Error> {
         let time_gained_for_tag: Duration = self
             .durations
             .iter()
-            .map(|duration| {
-                if duration.tag.as_ref().unwrap().to_string() == tag {
-                    match duration.time_gained {
-                        Some(time_gained) => time_gained,
-                        None => Instant::now()
-                            .checked_duration_since(duration.begin)
-                            .unwrap(),
-                    }
-                } else {
-                    Duration::new(0, 0)
-                }
-            })
-            .sum();
+            .filter(|duration| duration.tag.as_ref().unwrap() == &tag)
+            .map(PTDuration::elapsed)
+            .fold(Duration::zero(), |total, time_gained| total + time_gained);
 
         Ok(format_instant_to_hhmmss(time_gained_for_tag))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_running_duration_keeps_counting_after_the_snapshot() {
+        let duration = |tag: &str, begun_at, seconds| DurationStatus {
+            tag: Some(tag.to_string()),
+            repo: None,
+            branch: None,
+            begun_at,
+            seconds,
+        };
+        let status = Status {
+            updated_at: 1000,
+            active: true,
+            tag: Some("writing".to_string()),
+            current_duration: 60,
+            time_gained: 360,
+            tags: vec![("reading".to_string(), 300), ("writing".to_string(), 60)],
+            durations: vec![duration("reading", 600, 300), duration("writing", 940, 60)],
+        };
+
+        let later = status.clone().caught_up_to(1030);
+        assert_eq!(later.updated_at, 1030);
+        assert_eq!(later.current_duration, 90);
+        assert_eq!(later.time_gained, 390);
+        assert_eq!(
+            later.tags,
+            [("reading".to_string(), 300), ("writing".to_string(), 90)]
+        );
+        assert_eq!(later.durations[1].seconds, 90);
+
+        let paused = Status {
+            active: false,
+            current_duration: 0,
+            ..status
+        };
+        assert_eq!(
+            paused.clone().caught_up_to(1030).time_gained,
+            paused.time_gained
+        );
+    }
+
+    #[test]
+    fn subtracting_more_than_was_gained_goes_below_zero() {
+        let mut session = Session {
+            id: 0,
+            durations: Vec::new(),
+            additions: Vec::new(),
+            subtractions: Vec::new(),
+            active: false,
+            analytics: Analytics::new(),
+            tag: None,
+        };
+        session.record_addition(5);
+        session.record_subtraction(10);
+        session.update_time_gained();
+
+        assert_eq!(session.analytics.time_gained, Some(Duration::minutes(-5)));
+        assert_eq!(
+            format_instant_to_hhmmss(session.analytics.time_gained.unwrap()),
+            "-00:05:00"
+        );
+    }
+
+    #[test]
+    fn flagged_time_comes_off_the_durations_and_the_session() {
+        let ended = |tag: &str, minutes| {
+            let mut duration = PTDuration::new(Some(tag.to_string()));
+            duration.ended_at = Some(duration.begun_at);
+            duration.update_time_gained(Duration::minutes(minutes));
+            duration
+        };
+        let mut session = Session {
+            id: 0,
+            durations: vec![ended("writing", 60), ended("reading", 30)],
+            additions: Vec::new(),
+            subtractions: Vec::new(),
+            active: false,
+            analytics: Analytics::new(),
+            tag: None,
+        };
+        session.record_addition(5);
+
+        session.deduct(&[15 * 60, 0]);
+
+        // What's saved as the session's total, and as its durations' tags rows
+        assert_eq!(session.analytics.time_gained, Some(Duration::minutes(80)));
+        assert_eq!(
+            session
+                .durations
+                .iter()
+                .map(|duration| duration.time_gained)
+                .collect::<Vec<_>>(),
+            [Some(Duration::minutes(45)), Some(Duration::minutes(30))]
+        );
+        assert_eq!(
+            session.status().tags,
+            [
+                ("writing".to_string(), 45 * 60),
+                ("reading".to_string(), 30 * 60)
+            ]
+        );
+    }
+}
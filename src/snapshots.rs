@@ -0,0 +1,242 @@
+// Local copies of the database in `~/.productivity-timer/snapshots`, taken before anything that
+// replaces or rewrites it (restores, migrations, imports) and once a day by the daemon. Old ones
+// are pruned as new ones are taken, per the `[snapshots]` table in the config.
+use crate::config::{get_config, SnapshotsConfig};
+use crate::database::{copy_database, database_filename};
+use crate::restore::restore_from_file;
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime};
+use dirs::home_dir;
+use std::collections::HashSet;
+use std::fs::{copy, create_dir_all, read_dir, remove_file};
+use std::io::{Error, ErrorKind};
+
+const PREFIX: &str = "time_gained-";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d%H%M%S";
+const DAILY: &str = "daily";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    // Local time
+    pub taken_at: NaiveDateTime,
+    // daily, restore, migration, and so on
+    pub reason: String,
+    pub size: u64,
+}
+
+fn snapshots_directory() -> String {
+    home_dir().unwrap().as_path().display().to_string() + "/.productivity-timer/snapshots"
+}
+
+// Where a snapshot taken now should go
+pub fn new_filepath(reason: &str) -> Result<String, Error> {
+    create_dir_all(snapshots_directory())?;
+
+    Ok(format!(
+        "{}/{}{}-{}",
+        snapshots_directory(),
+        PREFIX,
+        Local::now().format(TIMESTAMP_FORMAT),
+        reason
+    ))
+}
+
+pub fn take(reason: &str) -> Result<String, Error> {
+    let filepath = new_filepath(reason)?;
+    copy_database(&filepath).map_err(Error::other)?;
+    prune()?;
+    Ok(filepath)
+}
+
+// For the daemon, which calls this whenever the day changes
+pub fn take_daily_if_due() -> Result<(), Error> {
+    let today = Local::now().date_naive();
+    let taken_today = get_snapshots()?
+        .iter()
+        .any(|snapshot| snapshot.reason == DAILY && snapshot.taken_at.date() == today);
+
+    if !taken_today {
+        take(DAILY)?;
+    }
+    Ok(())
+}
+
+pub fn get_snapshots() -> Result<Vec<Snapshot>, Error> {
+    let entries = match read_dir(snapshots_directory()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let (timestamp, reason) = match name
+            .strip_prefix(PREFIX)
+            .and_then(|rest| rest.split_once('-'))
+        {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let taken_at = match NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT) {
+            Ok(taken_at) => taken_at,
+            Err(_) => continue,
+        };
+
+        snapshots.push(Snapshot {
+            reason: reason.to_string(),
+            name,
+            taken_at,
+            size: entry.metadata()?.len(),
+        });
+    }
+
+    snapshots.sort_by_key(|snapshot| snapshot.taken_at);
+    Ok(snapshots)
+}
+
+pub fn prune() -> Result<(), Error> {
+    let config = get_config()?.snapshots;
+    let snapshots = get_snapshots()?;
+
+    for snapshot in to_prune(&snapshots, Local::now().date_naive(), &config) {
+        remove_file(snapshots_directory() + "/" + &snapshot.name)?;
+    }
+    Ok(())
+}
+
+// Of the daily snapshots, the newest of each of the last `daily` days is kept, then the newest of
+// each of the last `weekly` weeks. Of the rest, the newest `others` are kept.
+fn to_prune<'a>(
+    snapshots: &'a [Snapshot],
+    today: NaiveDate,
+    config: &SnapshotsConfig,
+) -> Vec<&'a Snapshot> {
+    let monday = |day: NaiveDate| day - Duration::days(day.weekday().num_days_from_monday() as i64);
+
+    let mut newest_first: Vec<&Snapshot> = snapshots.iter().collect();
+    newest_first.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.taken_at));
+
+    let mut kept_days = HashSet::new();
+    let mut kept_weeks = HashSet::new();
+    let mut others = 0;
+    let mut pruned = Vec::new();
+
+    for snapshot in newest_first {
+        let day = snapshot.taken_at.date();
+        let days_ago = (today - day).num_days();
+        let weeks_ago = (monday(today) - monday(day)).num_weeks();
+
+        let keep = if snapshot.reason == DAILY {
+            if days_ago < config.daily as i64 && !kept_days.contains(&day) {
+                // A week that already has a daily snapshot doesn't need a weekly one, too
+                kept_days.insert(day);
+                kept_weeks.insert(monday(day));
+                true
+            } else {
+                weeks_ago < config.weekly as i64 && kept_weeks.insert(monday(day))
+            }
+        } else {
+            others += 1;
+            others <= config.others
+        };
+
+        if !keep {
+            pruned.push(snapshot);
+        }
+    }
+
+    pruned
+}
+
+pub fn list() -> Result<(), Error> {
+    for snapshot in get_snapshots()?.iter().rev() {
+        println!(
+            "{}  {:<10} {:>10}  {}",
+            snapshot.taken_at.format("%Y-%m-%d %H:%M:%S"),
+            snapshot.reason,
+            snapshot.size,
+            snapshot.name
+        );
+    }
+    Ok(())
+}
+
+pub fn restore(name: &str, skip_confirmation: bool) -> Result<(), Error> {
+    if !get_snapshots()?
+        .iter()
+        .any(|snapshot| snapshot.name == name)
+    {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "There's no snapshot named {}; see `pt snapshots list`",
+                name
+            ),
+        ));
+    }
+
+    // Restoring swaps the file in by renaming it, and the snapshot should stay where it is
+    let filepath = database_filename() + ".restore";
+    copy(snapshots_directory() + "/" + name, &filepath)?;
+    restore_from_file(&filepath, "snapshot", skip_confirmation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(taken_at: &str, reason: &str) -> Snapshot {
+        let taken_at = NaiveDateTime::parse_from_str(taken_at, "%Y-%m-%d %H:%M").unwrap();
+        Snapshot {
+            name: format!("{}{}-{}", PREFIX, taken_at.format(TIMESTAMP_FORMAT), reason),
+            taken_at,
+            reason: reason.to_string(),
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn keeps_daily_then_weekly_then_newest_others() {
+        let config = SnapshotsConfig {
+            daily: 3,
+            weekly: 3,
+            others: 1,
+        };
+        // A Monday
+        let today = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let snapshots = vec![
+            // Three weeks back; out of the weekly window
+            snapshot("2026-09-28 09:00", DAILY),
+            // Two weeks back; the Sunday is the newest of its week
+            snapshot("2026-10-05 09:00", DAILY),
+            snapshot("2026-10-11 09:00", DAILY),
+            // Last week, where Saturday and Sunday are kept as dailies
+            snapshot("2026-10-12 09:00", DAILY),
+            snapshot("2026-10-17 09:00", DAILY),
+            snapshot("2026-10-18 09:00", DAILY),
+            // Today, twice
+            snapshot("2026-10-19 08:00", DAILY),
+            snapshot("2026-10-19 09:00", DAILY),
+            snapshot("2026-10-01 12:00", "restore"),
+            snapshot("2026-10-02 12:00", "migration"),
+        ];
+
+        let pruned: Vec<String> = to_prune(&snapshots, today, &config)
+            .iter()
+            .map(|snapshot| snapshot.taken_at.format("%Y-%m-%d %H:%M").to_string())
+            .collect();
+
+        assert_eq!(
+            pruned,
+            vec![
+                "2026-10-19 08:00",
+                "2026-10-12 09:00",
+                "2026-10-05 09:00",
+                "2026-10-01 12:00",
+                "2026-09-28 09:00",
+            ]
+        );
+    }
+}
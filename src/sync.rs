@@ -0,0 +1,80 @@
+// Keeps the database in step across machines that share a backup. Rather than one machine's upload
+// replacing the other's, the backup is downloaded, merged into the local database row by row, and
+// the result uploaded in its place.
+use crate::backup::{backend, open, record_status, upload_database};
+use crate::config::get_config;
+use crate::credentials::backup_passphrase;
+use crate::database::{
+    database_filename, delete_session as delete_session_rows, merge_into_local, migrate_database,
+    MergeSummary,
+};
+use crate::restore::check_database;
+use crate::snapshots;
+use std::fs::{remove_file, write};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+// `interactive` is false from the daemon, which can't log in to the backend
+pub fn sync(interactive: bool) -> Result<(), Error> {
+    record_status(merge_and_upload(interactive))
+}
+
+fn merge_and_upload(interactive: bool) -> Result<(), Error> {
+    let config = get_config()?.backup;
+
+    match backend(&config, interactive)?.download(&config.filename) {
+        Ok(backup) => {
+            let backup = open(backup, || backup_passphrase(false))?;
+            let summary = merge_backup(&backup)?;
+            print_summary(&summary);
+        }
+        // Nothing to merge with until some machine has uploaded
+        Err(e) if e.kind() == ErrorKind::NotFound => println!("There's no backup yet"),
+        Err(e) => return Err(e),
+    }
+
+    upload_database(interactive)
+}
+
+fn merge_backup(backup: &[u8]) -> Result<MergeSummary, Error> {
+    let filepath = database_filename() + ".sync";
+    write(&filepath, backup)?;
+
+    let result = check_database(&filepath, "backup").and_then(|_| {
+        migrate_database(&filepath).map_err(Error::other)?;
+        merge_into_local(&filepath).map_err(Error::other)
+    });
+
+    if Path::new(&filepath).exists() {
+        remove_file(&filepath)?;
+    }
+    result
+}
+
+fn print_summary(summary: &MergeSummary) {
+    println!(
+        "Merged the backup: {} sessions added, {} updated; {} durations added, {} updated; {} rows deleted",
+        summary.sessions_added,
+        summary.sessions_updated,
+        summary.durations_added,
+        summary.durations_updated,
+        summary.deleted
+    );
+}
+
+// Deletions are kept as tombstones, so they stick when the database is next merged
+pub fn delete_session(session_id: u64) -> Result<(), Error> {
+    let snapshot_filepath = snapshots::take("delete")?;
+
+    if delete_session_rows(session_id).map_err(Error::other)? == 0 {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("There's no session {}", session_id),
+        ));
+    }
+    println!(
+        "Deleted session {}; it's gone from other machines once they sync. The old database is at {}",
+        session_id, snapshot_filepath
+    );
+    Ok(())
+}
@@ -0,0 +1,360 @@
+// Drives a real daemon through its HTTP API, with HOME pointed at a directory of its own so the
+// daemon's files and database don't touch anyone's real ones
+use serde_json::{json, Value};
+use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+struct Daemon {
+    home: PathBuf,
+    port: u16,
+    token: String,
+}
+
+impl Daemon {
+    fn start(name: &str) -> Daemon {
+        Daemon::start_with(name, "")
+    }
+
+    // With more of the config after the API's table
+    fn start_with(name: &str, config: &str) -> Daemon {
+        let home = std::env::temp_dir().join(format!("pt-api-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&home);
+        let data_dir = home.join(".productivity-timer");
+        create_dir_all(&data_dir).unwrap();
+
+        // Whatever port the OS hands out is very likely still free a moment later
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        write(
+            data_dir.join("config.toml"),
+            format!("[api]\nenabled = true\nport = {}\n{}", port, config),
+        )
+        .unwrap();
+
+        let status = Command::new(env!("CARGO_BIN_EXE_pt"))
+            .arg("-d")
+            .env("HOME", &home)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let started = Instant::now();
+        loop {
+            if let Ok(token) = read_to_string(data_dir.join("api-token")) {
+                if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                    return Daemon {
+                        home,
+                        port,
+                        token: token.trim().to_string(),
+                    };
+                }
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "The API didn't come up"
+            );
+            sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+        self.request_with(method, path, Some(&self.token), body)
+    }
+
+    fn request_with(
+        &self,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (u16, Value) {
+        let (status, body) = self.raw_request(method, path, token, body);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    fn raw_request(
+        &self,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (u16, String) {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nhost: 127.0.0.1:{}\r\ncontent-length: {}\r\n",
+            method,
+            path,
+            self.port,
+            body.len()
+        );
+        if let Some(token) = token {
+            request += &format!("authorization: Bearer {}\r\n", token);
+        }
+        request += "\r\n";
+        request += &body;
+
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    // Reads past the response's head, leaving the events to come
+    fn subscribe(&self, path: &str) -> BufReader<TcpStream> {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nhost: localhost:{}\r\n\r\n",
+            path, self.port
+        )
+        .unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).unwrap();
+        assert!(status_line.starts_with("HTTP/1.1 200"), "{}", status_line);
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim_end().is_empty() {
+                return reader;
+            }
+        }
+    }
+}
+
+// The next event that isn't a tick
+fn next_event(events: &mut BufReader<TcpStream>) -> (String, Value) {
+    loop {
+        let (mut name, mut data) = (String::new(), String::new());
+        events.read_line(&mut name).unwrap();
+        events.read_line(&mut data).unwrap();
+        events.read_line(&mut String::new()).unwrap();
+
+        let name = name.trim_end().strip_prefix("event: ").unwrap().to_string();
+        let data = data.trim_end().strip_prefix("data: ").unwrap();
+        if name != "tick" {
+            return (name, serde_json::from_str(data).unwrap());
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let data_dir = self.home.join(".productivity-timer");
+        if let Ok(pid) = read_to_string(data_dir.join("timer.pid")) {
+            let _ = Command::new("kill").arg(pid.trim()).status();
+        }
+        let _ = remove_dir_all(&self.home);
+    }
+}
+
+#[test]
+fn requests_need_the_token() {
+    let daemon = Daemon::start("token");
+
+    let (status, _) = daemon.request_with("GET", "/status", None, None);
+    assert_eq!(status, 401);
+    let (status, _) = daemon.request_with("GET", "/status", Some("not-the-token"), None);
+    assert_eq!(status, 401);
+
+    // The description is for anyone
+    let (status, description) = daemon.raw_request("GET", "/openapi.yaml", None, None);
+    assert_eq!(status, 200);
+    assert!(description.starts_with("openapi: 3"));
+}
+
+#[test]
+fn drives_a_session_from_start_to_completion() {
+    let daemon = Daemon::start("session");
+
+    let (status, body) = daemon.request("POST", "/toggle", Some(json!({"tag": "writing"})));
+    assert_eq!(status, 200);
+    assert_eq!(body["active"], true);
+    assert_eq!(body["tag"], "writing");
+
+    let (status, body) = daemon.request("POST", "/add", Some(json!({"minutes": 5})));
+    assert_eq!(status, 200);
+    assert!(body["time_gained"].as_i64().unwrap() >= 5 * 60);
+
+    let (status, body) = daemon.request("POST", "/switch", Some(json!({"tag": "reading"})));
+    assert_eq!(status, 200);
+    assert_eq!(body["active"], true);
+    assert_eq!(body["tag"], "reading");
+
+    let (status, body) = daemon.request("POST", "/complete", Some(json!({"session_tag": "pt"})));
+    assert_eq!(status, 200);
+    assert_eq!(body["session_tag"], "pt");
+    assert_eq!(body["durations_count"], 2);
+    assert!(body["time_gained"].as_i64().unwrap() >= 5 * 60);
+
+    // Toggling after completing starts on a new session
+    let (_, body) = daemon.request("POST", "/toggle", None);
+    assert_eq!(body["active"], true);
+    assert_eq!(body["tags"], json!([["", 0]]));
+
+    let (status, body) = daemon.request("GET", "/report?by=tag", None);
+    assert_eq!(status, 200);
+    let keys: Vec<&str> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|total| total["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys, ["reading", "writing"]);
+
+    // Answered by the daemon's loop, so it's up to date straight away
+    let (status, body) = daemon.request("GET", "/status", None);
+    assert_eq!(status, 200);
+    assert_eq!(body["active"], true);
+
+    // The status file is only written when something changes, not as the duration runs
+    sleep(Duration::from_millis(1000));
+    let status_filepath = daemon.home.join(".productivity-timer/status");
+    let written = read_to_string(&status_filepath).unwrap();
+    assert!(written.contains("\"active\":true"));
+    sleep(Duration::from_millis(1500));
+    assert_eq!(read_to_string(&status_filepath).unwrap(), written);
+}
+
+// The tag's optional, so a session can have durations without one
+#[test]
+fn completes_a_session_with_an_untagged_duration() {
+    let daemon = Daemon::start("untagged");
+
+    daemon.request("POST", "/toggle", None);
+    daemon.request("POST", "/switch", Some(json!({"tag": "writing"})));
+    let (status, body) =
+        daemon.request("POST", "/complete", Some(json!({"session_tag": "errands"})));
+    assert_eq!(status, 200);
+    assert_eq!(body["session_tag"], "errands");
+
+    // The daemon's still there, and reports the duration as untagged
+    let (status, _) = daemon.request("GET", "/status", None);
+    assert_eq!(status, 200);
+    let (status, body) = daemon.request("GET", "/report?by=tag", None);
+    assert_eq!(status, 200);
+    let keys: Vec<&str> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|total| total["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys, ["untagged", "writing"]);
+}
+
+// Time in a flagged event comes off the durations it overlaps, as well as the session
+#[test]
+fn flagged_events_come_off_each_duration() {
+    let daemon = Daemon::start_with("flagged", "[calendar]\nfiles = [\"~/work.ics\"]\n");
+    let now = chrono::Utc::now();
+    let at = |hours| (now + chrono::Duration::hours(hours)).format("%Y%m%dT%H%M%SZ");
+    write(
+        daemon.home.join("work.ics"),
+        format!(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:review\r\nSUMMARY:Review\r\nDTSTART:{}\r\nDTEND:{}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+            at(-1),
+            at(1)
+        ),
+    )
+    .unwrap();
+
+    daemon.request("POST", "/toggle", Some(json!({"tag": "writing"})));
+    sleep(Duration::from_millis(2500));
+    let (status, body) = daemon.request("POST", "/complete", Some(json!({"session_tag": "pt"})));
+    assert_eq!(status, 200);
+    assert!(body["busy_seconds"].as_i64().unwrap() >= 2);
+    assert_eq!(body["busy_events"], json!(["Review"]));
+    assert_eq!(body["time_gained"], 0);
+
+    // The tags row lost the time too
+    let (status, body) = daemon.request("GET", "/report?by=tag", None);
+    assert_eq!(status, 200);
+    assert_eq!(body[0]["key"], "writing");
+    assert_eq!(body[0]["seconds"], 0);
+    let (_, body) = daemon.request("GET", "/report", None);
+    assert_eq!(body[0]["key"], "pt");
+    assert_eq!(body[0]["seconds"], 0);
+}
+
+#[test]
+fn bad_requests_are_turned_away() {
+    let daemon = Daemon::start("bad-requests");
+
+    let (status, body) = daemon.request("POST", "/complete", Some(json!({})));
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("session_tag"));
+
+    let (status, _) = daemon.request("POST", "/add", Some(json!({"minutes": -5})));
+    assert_eq!(status, 400);
+    let (status, _) = daemon.request("GET", "/report?since=yesterday", None);
+    assert_eq!(status, 400);
+    let (status, body) = daemon.request("GET", "/report?by=week", None);
+    assert_eq!(status, 400);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .ends_with("repo, branch, issue"));
+    let (status, _) = daemon.request("GET", "/toggle", None);
+    assert_eq!(status, 405);
+    let (status, _) = daemon.request("GET", "/nowhere", None);
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn streams_state_changes_to_subscribers() {
+    let daemon = Daemon::start("events");
+    let path = format!("/events?token={}", daemon.token);
+    let mut first = daemon.subscribe(&path);
+    let mut second = daemon.subscribe(&path);
+
+    daemon.request("POST", "/toggle", Some(json!({"tag": "writing"})));
+    daemon.request("POST", "/switch", Some(json!({"tag": "reading"})));
+    daemon.request("POST", "/toggle", None);
+    daemon.request("POST", "/complete", Some(json!({"session_tag": "pt"})));
+
+    for events in [&mut first, &mut second] {
+        let (name, data) = next_event(events);
+        assert_eq!(name, "started");
+        assert_eq!(data["tag"], "writing");
+
+        let (name, data) = next_event(events);
+        assert_eq!(name, "switched");
+        assert_eq!(data["previous_tag"], "writing");
+        assert_eq!(data["tag"], "reading");
+
+        let (name, data) = next_event(events);
+        assert_eq!(name, "paused");
+        assert_eq!(data["active"], false);
+        assert!(data["duration_seconds"].is_number());
+
+        let (name, data) = next_event(events);
+        assert_eq!(name, "completed");
+        assert_eq!(data["session_tag"], "pt");
+    }
+
+    // Ticks keep coming with nothing going on
+    let mut line = String::new();
+    first.read_line(&mut line).unwrap();
+    assert_eq!(line, "event: tick\n");
+
+    // Without the token, there's no stream
+    let (status, _) = daemon.request_with("GET", "/events", None, None);
+    assert_eq!(status, 401);
+}